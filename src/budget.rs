@@ -0,0 +1,141 @@
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use crate::error::runtime::{ErrorKind, EvalError};
+use crate::interrupt::Interrupt;
+
+// Reading the clock or the interrupt flag on every step would dominate small steps
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Resources a program may use in one call to `interpret`; `None` is unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// Statements and expressions evaluated, or instructions run on the VM.
+    pub max_steps: Option<u64>,
+    /// Wall-clock time.
+    pub timeout: Option<Duration>,
+    /// Bytes allocated for objects, list items and strings, including those
+    /// freed since.
+    pub max_heap_bytes: Option<usize>,
+}
+
+/// The limit a program was stopped by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Steps,
+    Time,
+    Heap,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps => write!(f, "Step limit exceeded"),
+            Limit::Time => write!(f, "Time limit exceeded"),
+            Limit::Heap => write!(f, "Heap limit exceeded"),
+        }
+    }
+}
+
+/// What a backend has used of its `Limits` since `interpret` was called, and
+/// whether it was interrupted.
+///
+/// Once a limit is exceeded or the program interrupted every following step
+/// fails too, so the program stops even when the error passes through a
+/// native (e.g. a generator resumed by `next`), and the backend can tell it
+/// apart from the errors that only end the current statement.
+pub struct Budget {
+    limits: Limits,
+    steps: u64,
+    // Steps and heap bytes up to which no limit needs checking
+    next_check: u64,
+    max_heap: usize,
+    deadline: Option<Instant>,
+    stopped: Option<ErrorKind>,
+    interrupt: Interrupt,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            limits: Limits::default(),
+            steps: 0,
+            next_check: u64::MAX,
+            max_heap: usize::MAX,
+            deadline: None,
+            stopped: None,
+            interrupt: Interrupt::default(),
+        }
+    }
+}
+
+impl Budget {
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Interrupts the evaluations checking this budget.
+    pub fn interrupt(&self) -> &Interrupt {
+        &self.interrupt
+    }
+
+    /// Starts counting afresh.
+    pub fn start(&mut self, heap_bytes: usize) {
+        self.steps = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.max_heap = self.limits.max_heap_bytes.map_or(usize::MAX, |max| heap_bytes.saturating_add(max));
+        self.stopped = None;
+        self.schedule();
+    }
+
+    #[inline]
+    pub fn step(&mut self, heap_bytes: usize) -> Result<(), EvalError> {
+        self.steps += 1;
+        if self.steps < self.next_check && heap_bytes <= self.max_heap {
+            return Ok(());
+        }
+        self.check(heap_bytes)
+    }
+
+    fn check(&mut self, heap_bytes: usize) -> Result<(), EvalError> {
+        if self.stopped.is_none() {
+            if self.interrupt.take() {
+                self.stopped = Some(ErrorKind::Interrupted);
+            } else if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+                self.stopped = Some(ErrorKind::Limit(Limit::Steps));
+            } else if heap_bytes > self.max_heap {
+                self.stopped = Some(ErrorKind::Limit(Limit::Heap));
+            } else if self.deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                self.stopped = Some(ErrorKind::Limit(Limit::Time));
+            }
+        }
+        match self.stopped() {
+            Some(e) => {
+                // Fail every following step too
+                self.next_check = 0;
+                Err(e)
+            },
+            None => {
+                self.schedule();
+                Ok(())
+            },
+        }
+    }
+
+    fn schedule(&mut self) {
+        let steps = self.limits.max_steps.map_or(u64::MAX, |max| max.saturating_add(1));
+        self.next_check = steps.min(self.steps + CHECK_INTERVAL);
+    }
+
+    /// Stops the program as if it exceeded a limit or was interrupted.
+    pub fn stop(&mut self, kind: ErrorKind) -> EvalError {
+        self.stopped = Some(kind);
+        self.next_check = 0;
+        EvalError::stopped(kind)
+    }
+
+    /// The error the program was stopped with, if any.
+    pub fn stopped(&self) -> Option<EvalError> {
+        self.stopped.map(EvalError::stopped)
+    }
+}
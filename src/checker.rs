@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::compiletime::TypeError;
+use crate::expr::{Expr, FunctionDecl, Program, Stmt};
+use crate::token::{Token, TokenKind};
+use crate::types::Type;
+use crate::value::{NUMBER_OPERAND, NUMBER_OPERANDS, SUM_OPERANDS};
+
+/// Gradual type checker run over the tree before interpretation.
+/// Only annotated bindings are checked; anything unannotated is `any`.
+pub struct Checker {
+    scopes: Vec<HashMap<String, Binding>>,
+    // Declared return type of each function being checked, innermost last
+    returns: Vec<Type>,
+    errors: Vec<TypeError>,
+}
+
+enum Binding {
+    Var(Type),
+    // Function declarations keep their signature so calls can be checked
+    Fun(Rc<FunctionDecl>),
+}
+
+impl Checker {
+    pub fn new() -> Checker {
+        Checker {
+            scopes: vec![HashMap::new()],
+            returns: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Returns whether the program is well typed; see `errors` otherwise.
+    pub fn check(&mut self, program: &Program) -> bool {
+        for stmt in &program.stmts {
+            self.check_stmt(stmt);
+        }
+        self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[TypeError] {
+        &self.errors
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) | Stmt::Print { value: e, .. } => {
+                let _ = self.check_expr(e);
+            },
+            Stmt::Var { name, ty, initializer, .. } => {
+                let found = match initializer {
+                    Some(expr) => self.check_expr(expr),
+                    None => Type::Nil,
+                };
+                let declared = match ty {
+                    Some(ty) => {
+                        if !ty.accepts(found) {
+                            self.error(&format!("Cannot initialize variable of type {ty} with a value of type {found}"), name);
+                        }
+                        *ty
+                    },
+                    None => Type::Any,
+                };
+                self.declare(name, Binding::Var(declared));
+            },
+            Stmt::Block { stmts, .. } => {
+                self.scopes.push(HashMap::new());
+                for stmt in stmts {
+                    self.check_stmt(stmt);
+                }
+                self.scopes.pop();
+            },
+            Stmt::If { condition, then_br, else_br } => {
+                let _ = self.check_expr(condition);
+                self.check_stmt(then_br);
+                if let Some(else_br) = else_br {
+                    self.check_stmt(else_br);
+                }
+            },
+            Stmt::While { condition, body } => {
+                let _ = self.check_expr(condition);
+                self.check_stmt(body);
+            },
+            Stmt::Function(decl) => {
+                self.declare(&decl.name, Binding::Fun(decl.clone()));
+                self.scopes.push(HashMap::new());
+                for param in &decl.params {
+                    let declared = if param.variadic { Type::List } else { param.ty.unwrap_or(Type::Any) };
+                    if let Some(default) = &param.default {
+                        let found = self.check_expr(default);
+                        if !declared.accepts(found) {
+                            self.error(&format!("Default value of type {found} does not match parameter of type {declared}"), &param.name);
+                        }
+                    }
+                    self.declare(&param.name, Binding::Var(declared));
+                }
+                // Values returned by a generator are dropped, not handed to the caller
+                let returns = if decl.generator { Type::Any } else { decl.return_ty.unwrap_or(Type::Any) };
+                self.returns.push(returns);
+                for stmt in &decl.body {
+                    self.check_stmt(stmt);
+                }
+                self.returns.pop();
+                self.scopes.pop();
+            },
+            Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    let _ = self.check_expr(value);
+                }
+            },
+            Stmt::ForIn { name, iterable, body } => {
+                let found = self.check_expr(iterable);
+                if !Type::List.accepts(found) && !Type::Generator.accepts(found) {
+                    self.error("Can only iterate over lists and generators", name);
+                }
+                self.scopes.push(HashMap::new());
+                self.declare(name, Binding::Var(Type::Any));
+                self.check_stmt(body);
+                self.scopes.pop();
+            },
+            Stmt::Return { keyword, value } => {
+                let found = match value {
+                    Some(expr) => self.check_expr(expr),
+                    None => Type::Nil,
+                };
+                let declared = self.returns.last().copied().unwrap_or(Type::Any);
+                if !declared.accepts(found) {
+                    self.error(&format!("Cannot return a value of type {found} from a function returning {declared}"), keyword);
+                }
+            },
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal(v) => Type::of(v),
+            Expr::Variable { name, .. } => self.lookup(name),
+            Expr::Grouping(e) => self.check_expr(e),
+            Expr::Assign { name, value, .. } => {
+                let found = self.check_expr(value);
+                let declared = self.lookup(name);
+                if !declared.accepts(found) {
+                    self.error(&format!("Cannot assign a value of type {found} to variable of type {declared}"), name);
+                }
+                found
+            },
+            Expr::Unary { op, right } => {
+                let right = self.check_expr(right);
+                match op.kind() {
+                    TokenKind::Minus => {
+                        if !Type::Number.accepts(right) {
+                            self.error(NUMBER_OPERAND, op);
+                        }
+                        Type::Number
+                    },
+                    TokenKind::Bang => Type::Bool,
+                    _ => unreachable!(),
+                }
+            },
+            Expr::Binary { left, op, right } => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                match op.kind() {
+                    TokenKind::Minus | TokenKind::Slash | TokenKind::Star => {
+                        if !Type::Number.accepts(left) || !Type::Number.accepts(right) {
+                            self.error(NUMBER_OPERANDS, op);
+                        }
+                        Type::Number
+                    },
+                    TokenKind::Plus => {
+                        match (left, right) {
+                            (Type::Number, Type::Number) => Type::Number,
+                            (Type::String, Type::String) => Type::String,
+                            (Type::Any, Type::Number | Type::String | Type::Any)
+                            | (Type::Number | Type::String, Type::Any) => Type::Any,
+                            _ => {
+                                self.error(SUM_OPERANDS, op);
+                                Type::Any
+                            }
+                        }
+                    },
+                    TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual => {
+                        if !Type::Number.accepts(left) || !Type::Number.accepts(right) {
+                            self.error(NUMBER_OPERANDS, op);
+                        }
+                        Type::Bool
+                    },
+                    TokenKind::EqualEqual | TokenKind::BangEqual => Type::Bool,
+                    _ => unreachable!(),
+                }
+            },
+            Expr::Logical { left, right, .. } => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                if left == right { left } else { Type::Any }
+            },
+            Expr::Call { callee, paren, args } => {
+                let decl = match callee.as_ref() {
+                    Expr::Variable { name, .. } => self.lookup_fun(name),
+                    _ => None,
+                };
+                let found = self.check_expr(callee);
+                if !Type::Function.accepts(found) {
+                    self.error("Can only call functions", paren);
+                }
+                let mut position = 0;
+                for arg in args {
+                    let found = self.check_expr(&arg.value);
+                    let Some(decl) = &decl else { continue };
+                    let param = match &arg.name {
+                        Some(name) => decl.params.iter().find(|p| !p.variadic && p.name.lexeme() == name.lexeme()),
+                        None => {
+                            position += 1;
+                            decl.params.get(position - 1).filter(|p| !p.variadic)
+                        },
+                    };
+                    if let Some(param) = param {
+                        let declared = param.ty.unwrap_or(Type::Any);
+                        if !declared.accepts(found) {
+                            let token = arg.name.as_ref().unwrap_or(paren);
+                            self.error(&format!("Argument of type {found} does not match parameter '{}' of type {declared}", param.name.lexeme()), token);
+                        }
+                    }
+                }
+                match decl {
+                    Some(decl) if decl.generator => Type::Generator,
+                    Some(decl) => decl.return_ty.unwrap_or(Type::Any),
+                    None => Type::Any,
+                }
+            },
+            Expr::Index { object, bracket, index } => {
+                let object = self.check_expr(object);
+                let index = self.check_expr(index);
+                if !Type::List.accepts(object) {
+                    self.error("Only lists can be indexed", bracket);
+                } else if !Type::Number.accepts(index) {
+                    self.error("List index must be a number", bracket);
+                }
+                Type::Any
+            },
+        }
+    }
+
+    fn declare(&mut self, name: &Token, binding: Binding) {
+        self.scopes.last_mut().unwrap().insert(name.lexeme().to_string(), binding);
+    }
+
+    fn lookup(&self, name: &Token) -> Type {
+        for scope in self.scopes.iter().rev() {
+            match scope.get(name.lexeme()) {
+                Some(Binding::Var(ty)) => return *ty,
+                Some(Binding::Fun(_)) => return Type::Function,
+                None => {},
+            }
+        }
+        // Undefined variables are reported by the interpreter
+        Type::Any
+    }
+
+    fn lookup_fun(&self, name: &Token) -> Option<Rc<FunctionDecl>> {
+        for scope in self.scopes.iter().rev() {
+            match scope.get(name.lexeme()) {
+                Some(Binding::Fun(decl)) => return Some(decl.clone()),
+                Some(Binding::Var(_)) => return None,
+                None => {},
+            }
+        }
+        None
+    }
+
+    fn error(&mut self, messg: &str, token: &Token) {
+        self.errors.push(TypeError::new(messg, token));
+    }
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
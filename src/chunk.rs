@@ -0,0 +1,143 @@
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::value::Value;
+
+/// One VM instruction. Operands index the constant pool, the stack frame
+/// or the code of the chunk that contains the instruction.
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    Constant(u32),
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal(u16),
+    SetLocal(u16),
+    GetGlobal(Symbol),
+    SetGlobal(Symbol),
+    DefineGlobal { name: Symbol, constant: bool },
+    GetUpvalue(u16),
+    SetUpvalue(u16),
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    // Jump targets are absolute offsets into the chunk
+    Jump(u32),
+    JumpIfFalse(u32),
+    Loop(u32),
+    // The last `names.len()` arguments are named, see `Chunk::arg_names`
+    Call { argc: u8, names: u16 },
+    // Like `Call`, but the callee's frame replaces the current one; always
+    // followed by a `Return` for callees that are not closures
+    TailCall { argc: u8, names: u16 },
+    Index,
+    Closure(u32),
+    CloseUpvalue,
+    Return,
+    // Skips the default of parameter `param` when the caller passed it
+    DefaultArg { param: u16, skip: u32 },
+    // Suspends a freshly called generator function once its defaults are bound
+    GeneratorStart,
+    Yield,
+    // Checks that the top of the stack can be iterated by `ForNext`
+    IterInit,
+    // Pushes the next item of the iterable at `slot` (the index lives at
+    // `slot + 1`) or jumps to `exit` when there are no more
+    ForNext { slot: u16, exit: u32 },
+}
+
+/// Signature and code of a compiled function (or of a whole script).
+pub struct Proto {
+    // Name of the function, `script` for top-level code
+    pub token: Token,
+    pub params: Vec<ParamInfo>,
+    pub upvalues: Vec<UpvalueDesc>,
+    pub chunk: Chunk,
+}
+
+pub struct ParamInfo {
+    pub name: String,
+    pub has_default: bool,
+    pub variadic: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct UpvalueDesc {
+    // Captures a local of the enclosing function, or one of its upvalues
+    pub is_local: bool,
+    pub index: u16,
+}
+
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<Value>,
+    pub protos: Vec<Rc<Proto>>,
+    pub arg_names: Vec<Vec<Token>>,
+    // Line table: for every instruction, the index in `tokens` of the
+    // token it was compiled from, used for error messages
+    pub spans: Vec<u32>,
+    pub tokens: Vec<Token>,
+    // Start of every top-level statement; after a runtime error the
+    // script resumes at the next one, like the tree-walking interpreter
+    pub stmt_starts: Vec<usize>,
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        Chunk {
+            code: Vec::new(),
+            constants: Vec::new(),
+            protos: Vec::new(),
+            arg_names: Vec::new(),
+            spans: Vec::new(),
+            tokens: Vec::new(),
+            stmt_starts: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, op: Op, token: &Token) -> usize {
+        let same = self.tokens.last().is_some_and(|last| {
+            last.line() == token.line() && last.lexeme() == token.lexeme()
+        });
+        if !same {
+            self.tokens.push(token.clone());
+        }
+        self.code.push(op);
+        self.spans.push(self.tokens.len() as u32 - 1);
+        self.code.len() - 1
+    }
+
+    pub fn token(&self, offset: usize) -> &Token {
+        &self.tokens[self.spans[offset] as usize]
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> u32 {
+        if let Some(i) = self.constants.iter().position(|c| match (c, &value) {
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }) {
+            return i as u32;
+        }
+        self.constants.push(value);
+        self.constants.len() as u32 - 1
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,493 @@
+use std::rc::Rc;
+
+use crate::chunk::{Chunk, Op, ParamInfo, Proto, UpvalueDesc};
+use crate::error::compiletime::ParseError;
+use crate::expr::{Arg, Expr, FunctionDecl, Stmt};
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+use crate::value::Value;
+
+/// `Op::Call` operand for calls without named arguments.
+pub const NO_NAMES: u16 = u16::MAX;
+
+/// Compiles a tree into bytecode for the `Vm`. Locals live in stack slots
+/// and captured ones are reached through upvalues; anything not resolved
+/// lexically is a global, indexed by its symbol at runtime.
+pub struct Compiler {
+    // Function being compiled, innermost last; the first one is the script
+    states: Vec<FnState>,
+    // Token attached to instructions that have no token of their own
+    token: Token,
+    errors: Vec<ParseError>,
+}
+
+struct FnState {
+    token: Token,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueDesc>,
+    scope_depth: usize,
+    // Generators and the script have frames that cannot be replaced by a tail call
+    tail_calls: bool,
+}
+
+struct Local {
+    name: Symbol,
+    depth: usize,
+    captured: bool,
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        let token = Token::new(TokenKind::Identifier(Symbol::intern("script")), None, "script".to_string(), 1, 0);
+        Compiler {
+            states: vec![FnState::new(token.clone(), 0)],
+            token,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Compiles a whole script, or returns every error found.
+    pub fn compile(mut self, stmts: &[Stmt]) -> Result<Rc<Proto>, Vec<ParseError>> {
+        for stmt in stmts {
+            let start = self.chunk().code.len();
+            self.chunk().stmt_starts.push(start);
+            self.stmt(stmt);
+        }
+        self.emit(Op::Nil);
+        self.emit(Op::Return);
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        let state = self.states.pop().unwrap();
+        Ok(Rc::new(state.into_proto(Vec::new())))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) => {
+                self.expr(e);
+                self.emit(Op::Pop);
+            },
+            Stmt::Print { value, .. } => {
+                self.expr(value);
+                self.emit(Op::Print);
+            },
+            Stmt::Var { name, initializer, constant, .. } => {
+                match initializer {
+                    Some(e) => self.expr(e),
+                    None => {
+                        self.token = name.clone();
+                        self.emit(Op::Nil);
+                    },
+                }
+                self.define(name, *constant);
+            },
+            Stmt::Block { stmts, .. } => {
+                self.begin_scope();
+                for stmt in stmts {
+                    self.stmt(stmt);
+                }
+                self.end_scope();
+            },
+            Stmt::If { condition, then_br, else_br } => {
+                self.expr(condition);
+                let then_jump = self.emit(Op::JumpIfFalse(0));
+                self.emit(Op::Pop);
+                self.stmt(then_br);
+                let else_jump = self.emit(Op::Jump(0));
+                self.patch(then_jump);
+                self.emit(Op::Pop);
+                if let Some(else_br) = else_br {
+                    self.stmt(else_br);
+                }
+                self.patch(else_jump);
+            },
+            Stmt::While { condition, body } => {
+                let start = self.chunk().code.len();
+                self.expr(condition);
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.emit(Op::Pop);
+                self.stmt(body);
+                self.emit(Op::Loop(start as u32));
+                self.patch(exit);
+                self.emit(Op::Pop);
+            },
+            Stmt::Function(decl) => {
+                self.token = decl.name.clone();
+                if self.is_global() {
+                    self.function(decl);
+                    self.define(&decl.name, false);
+                } else if let Some(slot) = self.local_in_scope(decl.name.symbol()) {
+                    self.function(decl);
+                    self.emit(Op::SetLocal(slot));
+                    self.emit(Op::Pop);
+                } else {
+                    // Declared before compiling the body so it can call itself
+                    self.add_local(&decl.name);
+                    self.function(decl);
+                }
+            },
+            Stmt::Return { keyword, value } => {
+                self.token = keyword.clone();
+                match value {
+                    Some(Expr::Call { callee, paren, args }) if self.state().tail_calls => {
+                        self.call(callee, paren, args, true);
+                    },
+                    Some(e) => self.expr(e),
+                    None => {
+                        self.emit(Op::Nil);
+                    },
+                }
+                self.token = keyword.clone();
+                self.emit(Op::Return);
+            },
+            Stmt::Yield { keyword, value } => {
+                self.token = keyword.clone();
+                match value {
+                    Some(e) => self.expr(e),
+                    None => {
+                        self.emit(Op::Nil);
+                    },
+                }
+                self.token = keyword.clone();
+                self.emit(Op::Yield);
+            },
+            Stmt::ForIn { name, iterable, body } => {
+                self.begin_scope();
+                self.expr(iterable);
+                self.token = name.clone();
+                self.emit(Op::IterInit);
+                let slot = self.add_hidden_local("(iter)", name.line());
+                let zero = self.chunk().add_constant(Value::Number(0.0));
+                self.emit(Op::Constant(zero));
+                self.add_hidden_local("(index)", name.line());
+
+                let start = self.chunk().code.len();
+                self.token = name.clone();
+                let exit = self.emit(Op::ForNext { slot, exit: 0 });
+                self.begin_scope();
+                self.add_local(name);
+                self.stmt(body);
+                self.end_scope();
+                self.emit(Op::Loop(start as u32));
+                self.patch(exit);
+                self.end_scope();
+            },
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(v) => {
+                match v {
+                    Value::Nil => self.emit(Op::Nil),
+                    Value::Bool(true) => self.emit(Op::True),
+                    Value::Bool(false) => self.emit(Op::False),
+                    v => {
+                        let i = self.chunk().add_constant(v.clone());
+                        self.emit(Op::Constant(i))
+                    },
+                };
+            },
+            Expr::Grouping(e) => self.expr(e),
+            Expr::Variable { name, .. } => {
+                self.token = name.clone();
+                let op = match self.resolve(name) {
+                    Resolved::Local(slot) => Op::GetLocal(slot),
+                    Resolved::Upvalue(i) => Op::GetUpvalue(i),
+                    Resolved::Global(name) => Op::GetGlobal(name),
+                };
+                self.emit(op);
+            },
+            Expr::Assign { name, value, .. } => {
+                self.expr(value);
+                self.token = name.clone();
+                let op = match self.resolve(name) {
+                    Resolved::Local(slot) => Op::SetLocal(slot),
+                    Resolved::Upvalue(i) => Op::SetUpvalue(i),
+                    Resolved::Global(name) => Op::SetGlobal(name),
+                };
+                self.emit(op);
+            },
+            Expr::Unary { op, right } => {
+                self.expr(right);
+                self.token = op.clone();
+                match op.kind() {
+                    TokenKind::Minus => self.emit(Op::Negate),
+                    TokenKind::Bang => self.emit(Op::Not),
+                    _ => unreachable!(),
+                };
+            },
+            Expr::Binary { left, op, right } => {
+                self.expr(left);
+                self.expr(right);
+                self.token = op.clone();
+                let op = match op.kind() {
+                    TokenKind::Minus => Op::Subtract,
+                    TokenKind::Plus => Op::Add,
+                    TokenKind::Slash => Op::Divide,
+                    TokenKind::Star => Op::Multiply,
+                    TokenKind::Greater => Op::Greater,
+                    TokenKind::GreaterEqual => Op::GreaterEqual,
+                    TokenKind::Less => Op::Less,
+                    TokenKind::LessEqual => Op::LessEqual,
+                    TokenKind::EqualEqual => Op::Equal,
+                    TokenKind::BangEqual => Op::NotEqual,
+                    _ => unreachable!(),
+                };
+                self.emit(op);
+            },
+            Expr::Logical { left, op, right } => {
+                self.expr(left);
+                self.token = op.clone();
+                match op.kind() {
+                    TokenKind::And => {
+                        let end = self.emit(Op::JumpIfFalse(0));
+                        self.emit(Op::Pop);
+                        self.expr(right);
+                        self.patch(end);
+                    },
+                    TokenKind::Or => {
+                        let else_jump = self.emit(Op::JumpIfFalse(0));
+                        let end = self.emit(Op::Jump(0));
+                        self.patch(else_jump);
+                        self.emit(Op::Pop);
+                        self.expr(right);
+                        self.patch(end);
+                    },
+                    _ => unreachable!(),
+                }
+            },
+            Expr::Call { callee, paren, args } => self.call(callee, paren, args, false),
+            Expr::Index { object, bracket, index } => {
+                self.expr(object);
+                self.expr(index);
+                self.token = bracket.clone();
+                self.emit(Op::Index);
+            },
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, paren: &Token, args: &[Arg], tail: bool) {
+        self.expr(callee);
+        for arg in args {
+            self.expr(&arg.value);
+        }
+        self.token = paren.clone();
+        if args.len() > u8::MAX as usize {
+            self.error("Can't have more than 255 arguments", paren);
+        }
+        let named: Vec<Token> = args.iter().filter_map(|a| a.name.clone()).collect();
+        let names = if named.is_empty() {
+            NO_NAMES
+        } else {
+            self.chunk().arg_names.push(named);
+            self.chunk().arg_names.len() as u16 - 1
+        };
+        let argc = args.len() as u8;
+        self.emit(if tail { Op::TailCall { argc, names } } else { Op::Call { argc, names } });
+    }
+
+    fn function(&mut self, decl: &FunctionDecl) {
+        let mut state = FnState::new(decl.name.clone(), 1);
+        state.tail_calls = !decl.generator;
+        self.states.push(state);
+        // Defaults are evaluated at call time, and only see the parameters
+        // before them
+        for (i, param) in decl.params.iter().enumerate() {
+            if let Some(default) = &param.default {
+                self.token = param.name.clone();
+                let skip = self.emit(Op::DefaultArg { param: i as u16, skip: 0 });
+                self.expr(default);
+                self.token = param.name.clone();
+                self.emit(Op::SetLocal(i as u16));
+                self.emit(Op::Pop);
+                self.patch(skip);
+            }
+            self.add_local(&param.name);
+        }
+        if decl.generator {
+            self.token = decl.name.clone();
+            self.emit(Op::GeneratorStart);
+        }
+        for stmt in &decl.body {
+            self.stmt(stmt);
+        }
+        self.emit(Op::Nil);
+        self.emit(Op::Return);
+
+        let state = self.states.pop().unwrap();
+        let params = decl.params.iter().map(|p| ParamInfo {
+            name: p.name.lexeme().to_string(),
+            has_default: p.default.is_some(),
+            variadic: p.variadic,
+        }).collect();
+        let proto = state.into_proto(params);
+        self.chunk().protos.push(Rc::new(proto));
+        let i = self.chunk().protos.len() as u32 - 1;
+        self.token = decl.name.clone();
+        self.emit(Op::Closure(i));
+    }
+
+    // Binds the value on top of the stack to `name` in the current scope
+    fn define(&mut self, name: &Token, constant: bool) {
+        self.token = name.clone();
+        if self.is_global() {
+            self.emit(Op::DefineGlobal { name: name.symbol(), constant });
+        } else if let Some(slot) = self.local_in_scope(name.symbol()) {
+            // Redeclaring in the same scope overwrites the variable, like
+            // `Environment::define` does
+            self.emit(Op::SetLocal(slot));
+            self.emit(Op::Pop);
+        } else {
+            self.add_local(name);
+        }
+    }
+
+    fn is_global(&self) -> bool {
+        self.states.len() == 1 && self.state().scope_depth == 0
+    }
+
+    fn local_in_scope(&self, name: Symbol) -> Option<u16> {
+        let state = self.state();
+        state.locals.iter().enumerate().rev()
+            .take_while(|(_, l)| l.depth == state.scope_depth)
+            .find(|(_, l)| l.name == name)
+            .map(|(i, _)| i as u16)
+    }
+
+    fn add_local(&mut self, name: &Token) -> u16 {
+        if self.state().locals.len() >= u16::MAX as usize {
+            self.error("Too many local variables in function", name);
+        }
+        let depth = self.state().scope_depth;
+        self.state_mut().locals.push(Local { name: name.symbol(), depth, captured: false });
+        self.state().locals.len() as u16 - 1
+    }
+
+    // Local used by the compiler itself, named so that no identifier can refer to it
+    fn add_hidden_local(&mut self, name: &str, line: usize) -> u16 {
+        let token = Token::new(TokenKind::Identifier(Symbol::intern(name)), None, name.to_string(), line, 0);
+        self.add_local(&token)
+    }
+
+    fn resolve(&mut self, name: &Token) -> Resolved {
+        let last = self.states.len() - 1;
+        if let Some(slot) = self.resolve_local(last, name.symbol()) {
+            return Resolved::Local(slot);
+        }
+        if let Some(i) = self.resolve_upvalue(last, name.symbol()) {
+            return Resolved::Upvalue(i);
+        }
+        Resolved::Global(name.symbol())
+    }
+
+    fn resolve_local(&self, state: usize, name: Symbol) -> Option<u16> {
+        self.states[state].locals.iter().rposition(|l| l.name == name).map(|i| i as u16)
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: Symbol) -> Option<u16> {
+        if state == 0 {
+            return None;
+        }
+        if let Some(slot) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[slot as usize].captured = true;
+            return Some(self.add_upvalue(state, UpvalueDesc { is_local: true, index: slot }));
+        }
+        let index = self.resolve_upvalue(state - 1, name)?;
+        Some(self.add_upvalue(state, UpvalueDesc { is_local: false, index }))
+    }
+
+    fn add_upvalue(&mut self, state: usize, desc: UpvalueDesc) -> u16 {
+        let upvalues = &mut self.states[state].upvalues;
+        if let Some(i) = upvalues.iter().position(|u| u.is_local == desc.is_local && u.index == desc.index) {
+            return i as u16;
+        }
+        upvalues.push(desc);
+        upvalues.len() as u16 - 1
+    }
+
+    fn begin_scope(&mut self) {
+        self.state_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.state_mut().scope_depth -= 1;
+        let depth = self.state().scope_depth;
+        while let Some(local) = self.state().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            let op = if local.captured { Op::CloseUpvalue } else { Op::Pop };
+            self.emit(op);
+            self.state_mut().locals.pop();
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let token = self.token.clone();
+        self.chunk().write(op, &token)
+    }
+
+    // Points the jump at `offset` to the next instruction
+    fn patch(&mut self, offset: usize) {
+        let target = self.chunk().code.len() as u32;
+        match &mut self.chunk().code[offset] {
+            Op::Jump(to) | Op::JumpIfFalse(to) => *to = target,
+            Op::DefaultArg { skip, .. } => *skip = target,
+            Op::ForNext { exit, .. } => *exit = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn state(&self) -> &FnState {
+        self.states.last().unwrap()
+    }
+
+    fn state_mut(&mut self) -> &mut FnState {
+        self.states.last_mut().unwrap()
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state_mut().chunk
+    }
+
+    fn error(&mut self, messg: &str, token: &Token) {
+        self.errors.push(ParseError::new(messg, token));
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Resolved {
+    Local(u16),
+    Upvalue(u16),
+    Global(Symbol),
+}
+
+impl FnState {
+    fn new(token: Token, scope_depth: usize) -> FnState {
+        FnState {
+            token,
+            chunk: Chunk::new(),
+            locals: Vec::new(),
+            upvalues: Vec::new(),
+            scope_depth,
+            tail_calls: false,
+        }
+    }
+
+    fn into_proto(self, params: Vec<ParamInfo>) -> Proto {
+        Proto {
+            token: self.token,
+            params,
+            upvalues: self.upvalues,
+            chunk: self.chunk,
+        }
+    }
+}
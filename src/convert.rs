@@ -0,0 +1,273 @@
+//! Conversions between Rust types and Lux values, for host functions.
+//!
+//! Lux has no dictionaries, so a `HashMap<String, T>` becomes a list of
+//! `[key, value]` pairs sorted by key, and is read back from one. Lists made
+//! here are not tracked by the collector: they are freed like any `Rc`, and
+//! only leak if a program puts them in a cycle.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::string::Str;
+use crate::types::Type;
+use crate::value::Value;
+
+/// A Rust value that can be passed to Lux.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// A Rust value that can be taken from Lux. The error says what was expected
+/// instead (`expected number but got string`).
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, String>;
+}
+
+fn mismatch(expected: &str, value: &Value) -> String {
+    format!("expected {expected} but got {}", Type::of(value))
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            _ => Err(mismatch("number", value)),
+        }
+    }
+}
+
+macro_rules! integer {
+    ($($int:ty),*) => {$(
+        impl IntoValue for $int {
+            fn into_value(self) -> Value {
+                Value::Number(self as f64)
+            }
+        }
+
+        impl FromValue for $int {
+            fn from_value(value: &Value) -> Result<Self, String> {
+                // `MAX as f64` rounds up to 2^63 or 2^64 for the 64-bit types,
+                // so the bound is the exclusive 2^bits
+                let bits = <$int>::BITS - (<$int>::MIN != 0) as u32;
+                match value {
+                    Value::Number(n) if n.fract() == 0.0 && *n >= <$int>::MIN as f64 && *n < 2f64.powi(bits as i32) => {
+                        Ok(*n as $int)
+                    },
+                    Value::Number(n) => {
+                        Err(format!("expected integer from {} to {} but got {n}", <$int>::MIN, <$int>::MAX))
+                    },
+                    _ => Err(mismatch("integer", value)),
+                }
+            }
+        }
+    )*};
+}
+
+integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(mismatch("bool", value)),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(Str::from(self))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(Str::from(self))
+    }
+}
+
+// No `FromValue` for `&str`: strings live in shared buffers that may grow,
+// so a borrow can't outlive the `Ref` of `Str::as_str`. Take a `Str` to read
+// an argument without copying it.
+impl FromValue for Str {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            _ => Err(mismatch("string", value)),
+        }
+    }
+}
+
+impl IntoValue for Str {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::String(s) => Ok(s.as_str().to_string()),
+            _ => Err(mismatch("string", value)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Nil,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Nil => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        let items = self.into_iter().map(IntoValue::into_value).collect();
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let Value::List(items) = value else {
+            return Err(mismatch("list", value));
+        };
+        items.borrow().iter().enumerate()
+            .map(|(i, item)| T::from_value(item).map_err(|e| format!("item {i}: {e}")))
+            .collect()
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        let mut entries: Vec<_> = self.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let pairs = entries.into_iter()
+            .map(|(key, value)| vec![key.into_value(), value.into_value()].into_value())
+            .collect::<Vec<_>>();
+        pairs.into_value()
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let Value::List(pairs) = value else {
+            return Err(mismatch("list of [key, value] pairs", value));
+        };
+        pairs.borrow().iter().enumerate().map(|(i, pair)| {
+            match pair {
+                Value::List(pair) if pair.borrow().len() == 2 => {
+                    let pair = pair.borrow();
+                    let key = String::from_value(&pair[0]).map_err(|e| format!("item {i}: key {e}"))?;
+                    let value = T::from_value(&pair[1]).map_err(|e| format!("item {i}: value {e}"))?;
+                    Ok((key, value))
+                },
+                _ => Err(format!("item {i}: {}", mismatch("[key, value] pair", pair))),
+            }
+        }).collect()
+    }
+}
+
+/// A Rust function callable from Lux, with arguments and result converted
+/// through `FromValue` and `IntoValue`; see `Lux::register_fn`.
+///
+/// `Ret` is the function's return type, or `Fallible<T>` for functions
+/// returning `Result<T, String>`, whose errors become runtime errors.
+pub trait HostFn<Args, Ret> {
+    fn arity(&self) -> usize;
+
+    fn call(&self, args: &[Value]) -> Result<Value, String>;
+}
+
+/// Marks host functions returning `Result<T, String>`, see `HostFn`.
+pub struct Fallible<T>(T);
+
+fn arg<T: FromValue>(args: &[Value], i: usize) -> Result<T, String> {
+    T::from_value(&args[i]).map_err(|e| format!("Argument {}: {e}", i + 1))
+}
+
+macro_rules! host_fn {
+    ($arity:expr $(, $arg:ident $i:expr)*) => {
+        impl<F, R, $($arg),*> HostFn<($($arg,)*), R> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: IntoValue,
+            $($arg: FromValue,)*
+        {
+            fn arity(&self) -> usize {
+                $arity
+            }
+
+            #[allow(unused_variables)]
+            fn call(&self, args: &[Value]) -> Result<Value, String> {
+                Ok(self($(arg::<$arg>(args, $i)?),*).into_value())
+            }
+        }
+
+        impl<F, R, $($arg),*> HostFn<($($arg,)*), Fallible<R>> for F
+        where
+            F: Fn($($arg),*) -> Result<R, String>,
+            R: IntoValue,
+            $($arg: FromValue,)*
+        {
+            fn arity(&self) -> usize {
+                $arity
+            }
+
+            #[allow(unused_variables)]
+            fn call(&self, args: &[Value]) -> Result<Value, String> {
+                Ok(self($(arg::<$arg>(args, $i)?),*)?.into_value())
+            }
+        }
+    };
+}
+
+host_fn!(0);
+host_fn!(1, A 0);
+host_fn!(2, A 0, B 1);
+host_fn!(3, A 0, B 1, C 2);
+host_fn!(4, A 0, B 1, C 2, D 3);
+host_fn!(5, A 0, B 1, C 2, D 3, E 4);
+host_fn!(6, A 0, B 1, C 2, D 3, E 4, G 5);
@@ -0,0 +1,176 @@
+//! Line and branch coverage of programs on the tree-walking interpreter, as
+//! lcov tracefiles. The lines are those of statements, and the branches those
+//! of `if`, `while`, `and` and `or`: the first one leads into the `then`
+//! branch, the loop body or the right operand, the second one past it.
+//!
+//! Branches are told apart by the address of their node, which stays put
+//! while the program runs. Every statement and branch of a program counts
+//! once it is interpreted, so those never reached show with 0.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::expr::{Arg, Expr, Stmt};
+
+/// How often each way of a branch was taken.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Branch {
+    pub line: usize,
+    /// Which of the branches of its line it is, from 0.
+    pub block: usize,
+    pub taken: [u64; 2],
+}
+
+#[derive(Default)]
+pub struct Coverage {
+    lines: BTreeMap<usize, u64>,
+    branches: Vec<Branch>,
+    // An `Expr` can share its address with the `Stmt` holding it, so they
+    // are looked up apart
+    stmts: HashMap<*const Stmt, usize>,
+    exprs: HashMap<*const Expr, usize>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Lines of statements, with how often statements of theirs ran.
+    pub fn lines(&self) -> Vec<(usize, u64)> {
+        self.lines.iter().map(|(&line, &count)| (line, count)).collect()
+    }
+
+    /// Branches by line, then in the order of the source.
+    pub fn branches(&self) -> Vec<Branch> {
+        let mut branches = self.branches.clone();
+        branches.sort_by_key(|branch| (branch.line, branch.block));
+        branches
+    }
+
+    /// An lcov record of `path`, the source file the program was read from.
+    pub fn lcov(&self, path: &str) -> String {
+        let branches = self.branches();
+        let mut lcov = format!("TN:\nSF:{path}\n");
+        for branch in &branches {
+            let reached = branch.taken[0] + branch.taken[1] > 0;
+            for (i, taken) in branch.taken.iter().enumerate() {
+                let taken = if reached { taken.to_string() } else { "-".to_string() };
+                let _ = writeln!(lcov, "BRDA:{},{},{i},{taken}", branch.line, branch.block);
+            }
+        }
+        let hit = branches.iter().flat_map(|branch| branch.taken).filter(|&taken| taken > 0).count();
+        let _ = writeln!(lcov, "BRF:{}\nBRH:{hit}", branches.len() * 2);
+        for (line, count) in &self.lines {
+            let _ = writeln!(lcov, "DA:{line},{count}");
+        }
+        let hit = self.lines.values().filter(|&&count| count > 0).count();
+        let _ = writeln!(lcov, "LF:{}\nLH:{hit}\nend_of_record", self.lines.len());
+        lcov
+    }
+
+    /// Adds the lines and branches of a program about to run.
+    pub(crate) fn add(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.add_stmt(stmt);
+        }
+    }
+
+    fn add_stmt(&mut self, stmt: &Stmt) {
+        let line = stmt.line();
+        if let (false, Some(line)) = (matches!(stmt, Stmt::Block { .. }), line) {
+            self.lines.entry(line).or_insert(0);
+        }
+        match stmt {
+            Stmt::Print { value, .. } | Stmt::Expr(value) => self.add_expr(value),
+            Stmt::Var { initializer, .. } => self.add_exprs(initializer),
+            Stmt::Return { value, .. } | Stmt::Yield { value, .. } => self.add_exprs(value),
+            Stmt::If { condition, then_br, else_br } => {
+                if let Some(line) = line {
+                    let id = self.add_branch(line);
+                    self.stmts.insert(stmt, id);
+                }
+                self.add_expr(condition);
+                self.add_stmt(then_br);
+                if let Some(else_br) = else_br {
+                    self.add_stmt(else_br);
+                }
+            },
+            Stmt::While { condition, body } => {
+                if let Some(line) = line {
+                    let id = self.add_branch(line);
+                    self.stmts.insert(stmt, id);
+                }
+                self.add_expr(condition);
+                self.add_stmt(body);
+            },
+            Stmt::ForIn { iterable, body, .. } => {
+                self.add_expr(iterable);
+                self.add_stmt(body);
+            },
+            Stmt::Block { stmts, .. } => self.add(stmts),
+            Stmt::Function(decl) => {
+                for param in &decl.params {
+                    self.add_exprs(&param.default);
+                }
+                self.add(&decl.body);
+            },
+        }
+    }
+
+    fn add_exprs(&mut self, expr: &Option<Expr>) {
+        if let Some(expr) = expr {
+            self.add_expr(expr);
+        }
+    }
+
+    fn add_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Logical { left, op, right } => {
+                let id = self.add_branch(op.line());
+                self.exprs.insert(expr, id);
+                self.add_expr(left);
+                self.add_expr(right);
+            },
+            Expr::Binary { left, right, .. } | Expr::Index { object: left, index: right, .. } => {
+                self.add_expr(left);
+                self.add_expr(right);
+            },
+            Expr::Call { callee, args, .. } => {
+                self.add_expr(callee);
+                for Arg { value, .. } in args {
+                    self.add_expr(value);
+                }
+            },
+            Expr::Assign { value, .. } | Expr::Unary { right: value, .. } | Expr::Grouping(value) => self.add_expr(value),
+            Expr::Literal(_) | Expr::Variable { .. } => {},
+        }
+    }
+
+    fn add_branch(&mut self, line: usize) -> usize {
+        let block = self.branches.iter().filter(|branch| branch.line == line).count();
+        self.branches.push(Branch { line, block, taken: [0, 0] });
+        self.branches.len() - 1
+    }
+
+    /// Counts a run of `stmt` on its line; blocks only run statements.
+    pub(crate) fn run(&mut self, stmt: &Stmt) {
+        if let (false, Some(line)) = (matches!(stmt, Stmt::Block { .. }), stmt.line()) {
+            *self.lines.entry(line).or_insert(0) += 1;
+        }
+    }
+
+    /// Counts which way the `if` or `while` of `stmt` went.
+    pub(crate) fn stmt_branch(&mut self, stmt: &Stmt, into: bool) {
+        if let Some(&id) = self.stmts.get(&(stmt as *const Stmt)) {
+            self.branches[id].taken[usize::from(!into)] += 1;
+        }
+    }
+
+    /// Counts whether the `and` or `or` of `expr` went on to its right operand.
+    pub(crate) fn expr_branch(&mut self, expr: &Expr, into: bool) {
+        if let Some(&id) = self.exprs.get(&(expr as *const Expr)) {
+            self.branches[id].taken[usize::from(!into)] += 1;
+        }
+    }
+}
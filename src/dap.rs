@@ -0,0 +1,347 @@
+//! Debug adapter for editors, speaking the Debug Adapter Protocol over any
+//! reader and writer (stdin and stdout for `rlux dap`).
+//!
+//! The program runs on the tree-walking interpreter under a `Debugger` that
+//! reads requests from the client while it is paused. There is one thread,
+//! and one stack frame standing for wherever the program is; its scopes are
+//! the `Environment` chain, innermost first, then the globals. Lines count
+//! from 1, the protocol's default. As nothing is read while the program
+//! runs, `pause` is unsupported.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use crate::debugger::{Breakpoint, Debugger, Paused, Reason, Resume};
+use crate::error::runtime::ErrorKind;
+use crate::expr::Program;
+use crate::interpreter::Interpreter;
+use crate::json::{read_message, write_message, Json};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::value::Value;
+
+const THREAD_ID: usize = 1;
+
+/// Answers the requests read from `input` on `output`, running the program
+/// `launch`ed once the client is done configuring it, until the client
+/// disconnects or closes `input`.
+pub fn serve<R: BufRead + 'static, W: Write + 'static>(input: R, output: W) -> io::Result<()> {
+    let adapter = Rc::new(RefCell::new(Adapter {
+        input,
+        output,
+        seq: 0,
+        program: None,
+        breakpoints: Vec::new(),
+        stop_on_entry: false,
+    }));
+    loop {
+        let Some(request) = adapter.borrow_mut().request()? else {
+            return Ok(());
+        };
+        let command = request.get("command").and_then(Json::as_str).unwrap_or_default();
+        let args = request.get("arguments").unwrap_or(&Json::Null);
+        let mut state = adapter.borrow_mut();
+        let result = match command {
+            "initialize" => Ok(Json::object([
+                ("supportsConfigurationDoneRequest", Json::Bool(true)),
+                ("supportsConditionalBreakpoints", Json::Bool(true)),
+                ("supportsEvaluateForHovers", Json::Bool(true)),
+                ("supportsTerminateRequest", Json::Bool(true)),
+            ])),
+            "launch" => state.launch(args).map(|()| Json::Null),
+            "setBreakpoints" => Ok(state.set_breakpoints(args)),
+            "threads" => Ok(threads()),
+            "setExceptionBreakpoints" | "configurationDone" | "disconnect" | "terminate" => Ok(Json::Null),
+            command => Err(format!("Unsupported request '{command}'")),
+        };
+        state.respond(&request, result)?;
+        match command {
+            "initialize" => state.event("initialized", Json::object([]))?,
+            "configurationDone" => {
+                let program = state.program.take();
+                drop(state);
+                if let Some(program) = program {
+                    run(&adapter, program)?;
+                }
+            },
+            "disconnect" | "terminate" => return Ok(()),
+            _ => {},
+        }
+    }
+}
+
+// The program launched, with its path
+type Launched = (String, Program);
+
+struct Adapter<R, W> {
+    input: R,
+    output: W,
+    // Of the last message sent
+    seq: usize,
+    program: Option<Launched>,
+    breakpoints: Vec<Breakpoint>,
+    stop_on_entry: bool,
+}
+
+impl<R: BufRead, W: Write> Adapter<R, W> {
+    // The next request, skipping what doesn't parse
+    fn request(&mut self) -> io::Result<Option<Json>> {
+        while let Some(body) = read_message(&mut self.input)? {
+            if let Ok(request) = Json::parse(&body) {
+                return Ok(Some(request));
+            }
+        }
+        Ok(None)
+    }
+
+    fn send<const N: usize>(&mut self, fields: [(&str, Json); N]) -> io::Result<()> {
+        self.seq += 1;
+        let mut message = vec![("seq".to_string(), self.seq.into())];
+        message.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value)));
+        write_message(&mut self.output, &Json::Object(message))
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) -> io::Result<()> {
+        let seq = request.get("seq").cloned().unwrap_or(Json::Null);
+        let command = request.get("command").cloned().unwrap_or(Json::Null);
+        let (success, body) = match result {
+            Ok(body) => (true, ("body", body)),
+            Err(messg) => (false, ("message", messg.as_str().into())),
+        };
+        self.send([
+            ("type", "response".into()),
+            ("request_seq", seq),
+            ("success", Json::Bool(success)),
+            ("command", command),
+            body,
+        ])
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send([("type", "event".into()), ("event", event.into()), ("body", body)])
+    }
+
+    fn output(&mut self, category: &str, text: &str) -> io::Result<()> {
+        self.event("output", Json::object([("category", category.into()), ("output", text.into())]))
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<(), String> {
+        let path = args.get("program").and_then(Json::as_str).ok_or("Expected the path of a 'program'")?;
+        let source = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.tokens().to_vec());
+        let program = parser.program();
+        let mut errors = scanner.errors().to_vec();
+        errors.extend_from_slice(parser.errors());
+        let mut resolver = Resolver::new();
+        if errors.is_empty() && !resolver.resolve(&program) {
+            errors.extend_from_slice(resolver.errors());
+        }
+        if !errors.is_empty() {
+            let errors: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(errors.join("\n"));
+        }
+        self.stop_on_entry = args.get("stopOnEntry") == Some(&Json::Bool(true));
+        self.program = Some((path.to_string(), program));
+        Ok(())
+    }
+
+    // Replaces every breakpoint, the program having a single source
+    fn set_breakpoints(&mut self, args: &Json) -> Json {
+        let requested = args.get("breakpoints").and_then(Json::as_array).unwrap_or_default();
+        self.breakpoints = requested.iter().filter_map(|breakpoint| {
+            let line = breakpoint.get("line")?.as_f64()? as usize;
+            let condition = breakpoint.get("condition").and_then(Json::as_str).filter(|c| !c.trim().is_empty());
+            Some(Breakpoint { line, condition: condition.map(str::to_string) })
+        }).collect();
+        let verified = self.breakpoints.iter()
+            .map(|breakpoint| Json::object([("verified", Json::Bool(true)), ("line", breakpoint.line.into())]));
+        Json::object([("breakpoints", Json::Array(verified.collect()))])
+    }
+}
+
+fn threads() -> Json {
+    let thread = Json::object([("id", THREAD_ID.into()), ("name", "main".into())]);
+    Json::object([("threads", Json::Array(vec![thread]))])
+}
+
+fn run<R: BufRead + 'static, W: Write + 'static>(adapter: &Rc<RefCell<Adapter<R, W>>>, program: Launched) -> io::Result<()> {
+    let (path, program) = program;
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(Output { adapter: adapter.clone(), category: "stdout", line: Vec::new() }));
+    interp.set_diagnostics(Box::new(Output { adapter: adapter.clone(), category: "stderr", line: Vec::new() }));
+    interp.set_debugger(Box::new(Client { adapter: adapter.clone(), path, scopes: Vec::new() }));
+    let exit_code = match interp.interpret(program) {
+        Ok(()) => 0,
+        Err(e) => {
+            adapter.borrow_mut().output("stderr", &format!("{e}\n"))?;
+            if e.kind() == ErrorKind::Interrupted { 130 } else { 70 }
+        },
+    };
+    let mut adapter = adapter.borrow_mut();
+    adapter.event("exited", Json::object([("exitCode", Json::Number(exit_code as f64))]))?;
+    adapter.event("terminated", Json::object([]))
+}
+
+/// Sends what the program writes to the client as `output` events, a line
+/// at a time.
+struct Output<R, W> {
+    adapter: Rc<RefCell<Adapter<R, W>>>,
+    category: &'static str,
+    line: Vec<u8>,
+}
+
+impl<R: BufRead, W: Write> Write for Output<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.adapter.borrow_mut().output(self.category, &String::from_utf8_lossy(&line))?;
+        }
+        Ok(())
+    }
+}
+
+/// Answers the client's requests while the program is paused.
+struct Client<R, W> {
+    adapter: Rc<RefCell<Adapter<R, W>>>,
+    path: String,
+    // Variables of the current pause, by `variablesReference` less one
+    scopes: Vec<Vec<(String, Value)>>,
+}
+
+impl<R: BufRead, W: Write> Client<R, W> {
+    fn pause(&mut self, paused: &mut Paused<'_>) -> io::Result<Resume> {
+        let reason = match paused.reason() {
+            Reason::Entry => {
+                *paused.breakpoints() = self.adapter.borrow().breakpoints.clone();
+                let stop_on_entry = self.adapter.borrow().stop_on_entry;
+                match stop_on_entry || self.breakpoint_hit(paused) {
+                    true if stop_on_entry => "entry",
+                    true => "breakpoint",
+                    false => return Ok(Resume::Continue),
+                }
+            },
+            Reason::Breakpoint => "breakpoint",
+            Reason::Step => "step",
+        };
+        self.scopes = paused.scopes();
+        self.adapter.borrow_mut().event("stopped", Json::object([
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", Json::Bool(true)),
+        ]))?;
+
+        loop {
+            let Some(request) = self.adapter.borrow_mut().request()? else {
+                return Ok(Resume::Quit);
+            };
+            let command = request.get("command").and_then(Json::as_str).unwrap_or_default();
+            let args = request.get("arguments").unwrap_or(&Json::Null);
+            let (result, resume) = match command {
+                "continue" => (Ok(Json::object([("allThreadsContinued", Json::Bool(true))])), Some(Resume::Continue)),
+                "next" => (Ok(Json::Null), Some(Resume::StepOver)),
+                "stepIn" => (Ok(Json::Null), Some(Resume::StepInto)),
+                "stepOut" => (Ok(Json::Null), Some(Resume::StepOut)),
+                "disconnect" | "terminate" => (Ok(Json::Null), Some(Resume::Quit)),
+                "threads" => (Ok(threads()), None),
+                "stackTrace" => (Ok(self.stack_trace(paused)), None),
+                "scopes" => (Ok(self.scope_list()), None),
+                "variables" => (self.variables(args), None),
+                // Evaluated without the adapter borrowed, as calls may print
+                "evaluate" => (self.evaluate(paused, args), None),
+                "setBreakpoints" => {
+                    let breakpoints = self.adapter.borrow_mut().set_breakpoints(args);
+                    *paused.breakpoints() = self.adapter.borrow().breakpoints.clone();
+                    (Ok(breakpoints), None)
+                },
+                "setExceptionBreakpoints" => (Ok(Json::Null), None),
+                command => (Err(format!("Unsupported request '{command}'")), None),
+            };
+            self.adapter.borrow_mut().respond(&request, result)?;
+            if let Some(resume) = resume {
+                return Ok(resume);
+            }
+        }
+    }
+
+    // Whether a breakpoint of the first line stops the program there
+    fn breakpoint_hit(&self, paused: &mut Paused<'_>) -> bool {
+        let line = paused.line();
+        let breakpoints: Vec<_> = paused.breakpoints().iter().filter(|b| b.line == line).cloned().collect();
+        breakpoints.into_iter().any(|breakpoint| match breakpoint.condition {
+            None => true,
+            Some(condition) => paused.eval(&condition).map_or(true, |value| value.bool()),
+        })
+    }
+
+    fn stack_trace(&self, paused: &Paused<'_>) -> Json {
+        let name = match paused.depth() {
+            0 => "<script>".to_string(),
+            depth => format!("<call depth {depth}>"),
+        };
+        let file = self.path.rsplit(['/', '\\']).next().unwrap_or(&self.path);
+        let frame = Json::object([
+            ("id", Json::Number(0.0)),
+            ("name", name.as_str().into()),
+            ("line", paused.line().into()),
+            ("column", Json::Number(1.0)),
+            ("source", Json::object([("name", file.into()), ("path", self.path.as_str().into())])),
+        ]);
+        Json::object([("stackFrames", Json::Array(vec![frame])), ("totalFrames", Json::Number(1.0))])
+    }
+
+    fn scope_list(&self) -> Json {
+        let scopes = self.scopes.iter().enumerate().map(|(i, vars)| {
+            let name = match i {
+                i if i + 1 == self.scopes.len() => "Globals".to_string(),
+                0 => "Locals".to_string(),
+                i => format!("Enclosing {i}"),
+            };
+            Json::object([
+                ("name", name.as_str().into()),
+                ("variablesReference", (i + 1).into()),
+                ("namedVariables", vars.len().into()),
+                ("expensive", Json::Bool(false)),
+            ])
+        });
+        Json::object([("scopes", Json::Array(scopes.collect()))])
+    }
+
+    fn variables(&self, args: &Json) -> Result<Json, String> {
+        let reference = args.get("variablesReference").and_then(Json::as_f64).unwrap_or_default() as usize;
+        let vars = reference.checked_sub(1).and_then(|i| self.scopes.get(i)).ok_or("Unknown variables reference")?;
+        let vars = vars.iter().map(|(name, value)| {
+            Json::object([
+                ("name", name.as_str().into()),
+                ("value", value.to_string().as_str().into()),
+                ("variablesReference", Json::Number(0.0)),
+            ])
+        });
+        Ok(Json::object([("variables", Json::Array(vars.collect()))]))
+    }
+
+    fn evaluate(&mut self, paused: &mut Paused<'_>, args: &Json) -> Result<Json, String> {
+        let expr = args.get("expression").and_then(Json::as_str).ok_or("Expected an 'expression'")?;
+        let value = paused.eval(expr).map_err(|e| e.to_string())?;
+        // It may have assigned variables
+        self.scopes = paused.scopes();
+        Ok(Json::object([("result", value.to_string().as_str().into()), ("variablesReference", Json::Number(0.0))]))
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for Client<R, W> {
+    fn paused(&mut self, paused: &mut Paused<'_>) -> Resume {
+        self.pause(paused).unwrap_or(Resume::Quit)
+    }
+}
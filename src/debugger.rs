@@ -0,0 +1,308 @@
+//! Stepping through programs on the tree-walking interpreter. Before each line
+//! it arrives at, the interpreter checks the breakpoints and the step in
+//! progress, and hands over to its `Debugger` where it stops. Without a
+//! debugger nothing is checked.
+
+use std::fmt::{self, Display};
+use std::io::{self, BufRead, Write};
+
+use crate::expr::Stmt;
+use crate::interpreter::Interpreter;
+use crate::lux::Error;
+use crate::value::Value;
+
+/// How the program goes on after a pause.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resume {
+    /// Runs until the next breakpoint.
+    Continue,
+    /// Stops on the next line, in whatever function it is.
+    StepInto,
+    /// Stops on the next line of the current function or of its callers.
+    StepOver,
+    /// Stops once the current function returned.
+    StepOut,
+    /// Stops the program with an `Interrupted` error.
+    Quit,
+}
+
+/// Why the program paused.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reason {
+    /// Before its first line.
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// A line breakpoint, which only stops the program when its `condition`, if
+/// any, evaluates to a truthy value there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub line: usize,
+    pub condition: Option<String>,
+}
+
+/// Front end of a debugging session, such as `Console`.
+pub trait Debugger {
+    /// Called whenever the program pauses, to inspect it and change the
+    /// breakpoints. Returns how the program goes on.
+    fn paused(&mut self, paused: &mut Paused<'_>) -> Resume;
+}
+
+/// The program paused before a line, as its debugger sees it.
+pub struct Paused<'a> {
+    interp: &'a mut Interpreter,
+    line: usize,
+    reason: Reason,
+    breakpoints: &'a mut Vec<Breakpoint>,
+}
+
+impl Paused<'_> {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn reason(&self) -> Reason {
+        self.reason
+    }
+
+    /// Function calls in progress, 0 at the top level.
+    pub fn depth(&self) -> usize {
+        self.interp.depth()
+    }
+
+    /// Breakpoints of the session, kept from one pause to the next.
+    pub fn breakpoints(&mut self) -> &mut Vec<Breakpoint> {
+        self.breakpoints
+    }
+
+    /// Evaluates an expression where the program paused, e.g. `x + 1`.
+    /// Assignments and calls take effect.
+    pub fn eval(&mut self, expr: &str) -> Result<Value, Error> {
+        self.interp.eval_here(expr)
+    }
+
+    /// Variables of each `Environment` of the current chain, innermost
+    /// first, then the globals but for natives.
+    pub fn scopes(&self) -> Vec<Vec<(String, Value)>> {
+        let scopes = self.interp.scopes().into_iter();
+        scopes.map(|vars| vars.into_iter().map(|(name, value)| (name.to_string(), value)).collect()).collect()
+    }
+}
+
+/// What the interpreter keeps of a debugging session between pauses.
+pub(crate) struct Session {
+    debugger: Box<dyn Debugger>,
+    breakpoints: Vec<Breakpoint>,
+    resume: Resume,
+    entry: bool,
+    // Line and call depth of the last pause, where steps count from
+    paused_at: (usize, usize),
+    // Those of the last statement checked: a program only stops when it
+    // arrives at a line, not at every statement of it
+    last: Option<(usize, usize)>,
+}
+
+impl Session {
+    pub(crate) fn new(debugger: Box<dyn Debugger>) -> Session {
+        Session {
+            debugger,
+            breakpoints: Vec::new(),
+            resume: Resume::StepInto,
+            entry: true,
+            paused_at: (0, 0),
+            last: None,
+        }
+    }
+
+    /// A call arrives at its lines anew, even those of the caller's line.
+    pub(crate) fn enter_call(&mut self) {
+        self.last = None;
+    }
+
+    /// Pauses for the debugger if the program stops before `stmt`. Returns
+    /// whether the debugger quit.
+    pub(crate) fn before(&mut self, stmt: &Stmt, interp: &mut Interpreter) -> bool {
+        // So does each run of a block
+        if let Stmt::Block { .. } = stmt {
+            self.last = None;
+            return false;
+        }
+        let Some(line) = stmt.line() else {
+            return false;
+        };
+        let here = (line, interp.depth());
+        if self.last.replace(here) == Some(here) {
+            return false;
+        }
+        let depth = self.paused_at.1;
+        let step = match self.resume {
+            Resume::Continue | Resume::Quit => false,
+            Resume::StepInto => true,
+            Resume::StepOver => here.1 <= depth,
+            Resume::StepOut => here.1 < depth,
+        };
+        let reason = if self.entry {
+            Reason::Entry
+        } else if step {
+            Reason::Step
+        } else if self.hits(line, interp) {
+            Reason::Breakpoint
+        } else {
+            return false;
+        };
+        self.entry = false;
+        let mut paused = Paused { interp, line, reason, breakpoints: &mut self.breakpoints };
+        self.resume = self.debugger.paused(&mut paused);
+        self.paused_at = here;
+        self.resume == Resume::Quit
+    }
+
+    fn hits(&self, line: usize, interp: &mut Interpreter) -> bool {
+        self.breakpoints.iter().filter(|breakpoint| breakpoint.line == line).any(|breakpoint| {
+            match &breakpoint.condition {
+                None => true,
+                // Stops on conditions that fail, to show why
+                Some(condition) => interp.eval_here(condition).map_or(true, |value| value.bool()),
+            }
+        })
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Breakpoint at line {}", self.line)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {condition}")?;
+        }
+        Ok(())
+    }
+}
+
+const HELP: &str = "\
+break LINE [if EXPR]  stop at LINE, only when EXPR is true if given (b)
+break                 list the breakpoints
+delete [LINE]         remove the breakpoints of LINE, or all of them (d)
+continue              run until the next breakpoint (c)
+step                  go to the next line, into calls (s)
+next                  go to the next line, over calls (n)
+finish                run until the current function returns (f)
+print EXPR            evaluate EXPR where the program is (p)
+vars                  list the variables of each scope, innermost first (v)
+quit                  stop the program (q)";
+
+/// Debugger reading commands from `input` and answering on `output`, for
+/// `rlux debug`. Shows the lines of `source` it stops at. The end of `input`
+/// quits.
+pub struct Console<R, W> {
+    lines: Vec<String>,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    pub fn new(source: &str, input: R, output: W) -> Console<R, W> {
+        Console { lines: source.lines().map(str::to_string).collect(), input, output }
+    }
+
+    // Runs a command, returning how the program goes on if it resumes it
+    fn command(&mut self, paused: &mut Paused<'_>, command: &str) -> io::Result<Option<Resume>> {
+        let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+        let arg = arg.trim();
+        let resume = match command {
+            "c" | "continue" => Resume::Continue,
+            "s" | "step" => Resume::StepInto,
+            "n" | "next" => Resume::StepOver,
+            "f" | "finish" => Resume::StepOut,
+            "q" | "quit" => Resume::Quit,
+            "b" | "break" if arg.is_empty() => {
+                for breakpoint in paused.breakpoints().iter() {
+                    writeln!(self.output, "{breakpoint}")?;
+                }
+                return Ok(None);
+            },
+            "b" | "break" => {
+                let (line, condition) = match arg.split_once(" if ") {
+                    Some((line, condition)) => (line, Some(condition.trim().to_string())),
+                    None => (arg, None),
+                };
+                match line.trim().parse() {
+                    Ok(line) => {
+                        let breakpoint = Breakpoint { line, condition };
+                        writeln!(self.output, "{breakpoint}")?;
+                        paused.breakpoints().push(breakpoint);
+                    },
+                    Err(_) => writeln!(self.output, "Expected a line number, got '{line}'")?,
+                }
+                return Ok(None);
+            },
+            "d" | "delete" => {
+                match arg.parse::<usize>() {
+                    Ok(line) => paused.breakpoints().retain(|breakpoint| breakpoint.line != line),
+                    Err(_) if arg.is_empty() => paused.breakpoints().clear(),
+                    Err(_) => writeln!(self.output, "Expected a line number, got '{arg}'")?,
+                }
+                return Ok(None);
+            },
+            "p" | "print" => {
+                match paused.eval(arg) {
+                    Ok(value) => writeln!(self.output, "{value}")?,
+                    Err(e) => writeln!(self.output, "{e}")?,
+                }
+                return Ok(None);
+            },
+            "v" | "vars" => {
+                let scopes = paused.scopes();
+                for (i, vars) in scopes.iter().enumerate() {
+                    match i + 1 == scopes.len() {
+                        true => writeln!(self.output, "globals:")?,
+                        false => writeln!(self.output, "scope {i}:")?,
+                    }
+                    for (name, value) in vars {
+                        writeln!(self.output, "  {name} = {value}")?;
+                    }
+                }
+                return Ok(None);
+            },
+            "h" | "help" => {
+                writeln!(self.output, "{HELP}")?;
+                return Ok(None);
+            },
+            "" => return Ok(None),
+            _ => {
+                writeln!(self.output, "Unknown command '{command}', try 'help'")?;
+                return Ok(None);
+            },
+        };
+        Ok(Some(resume))
+    }
+
+    fn prompt(&mut self, paused: &mut Paused<'_>) -> io::Result<Resume> {
+        let line = paused.line();
+        match paused.reason() {
+            Reason::Breakpoint => writeln!(self.output, "Breakpoint at line {line}")?,
+            Reason::Entry | Reason::Step => writeln!(self.output, "Stopped at line {line}")?,
+        }
+        if let Some(source) = self.lines.get(line - 1) {
+            writeln!(self.output, "{line:>4} | {source}")?;
+        }
+        loop {
+            write!(self.output, "(rlux) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                return Ok(Resume::Quit);
+            }
+            if let Some(resume) = self.command(paused, command.trim())? {
+                return Ok(resume);
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for Console<R, W> {
+    fn paused(&mut self, paused: &mut Paused<'_>) -> Resume {
+        self.prompt(paused).unwrap_or(Resume::Quit)
+    }
+}
@@ -0,0 +1,135 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::value::Value;
+use crate::gc::{self, ObjectId};
+use crate::symbol::Symbol;
+
+/// Local variables of one scope, addressed by the slots the `Resolver`
+/// assigned to them.
+pub struct Environment {
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    slots: Vec<Value>,
+    // Names of the slots, only recorded while debugging
+    names: Vec<Option<Symbol>>,
+}
+
+impl Environment {
+    /// `None` encloses a top-level scope, whose variables are `Globals`.
+    pub fn with_enclosing(enclosing: Option<Rc<RefCell<Environment>>>) -> Environment {
+        Environment {
+            enclosing,
+            slots: Vec::new(),
+            names: Vec::new(),
+        }
+    }
+
+    pub fn define(&mut self, index: usize, value: Value) {
+        if index >= self.slots.len() {
+            self.slots.resize(index + 1, Value::Nil);
+        }
+        self.slots[index] = value;
+    }
+
+    pub fn assign(&mut self, depth: usize, index: usize, value: Value) {
+        match depth {
+            0 => self.slots[index] = value,
+            _ => self.enclosing().borrow_mut().assign(depth - 1, index, value),
+        }
+    }
+
+    pub fn get(&self, depth: usize, index: usize) -> Value {
+        match depth {
+            0 => self.slots[index].clone(),
+            _ => self.enclosing().borrow().get(depth - 1, index),
+        }
+    }
+
+    /// Records the name of the variable in `index`, for debuggers to list
+    /// and evaluate it by.
+    pub fn name(&mut self, index: usize, name: Symbol) {
+        if index >= self.names.len() {
+            self.names.resize(index + 1, None);
+        }
+        self.names[index] = Some(name);
+    }
+
+    /// The variables given a `name`, with their slot.
+    pub fn named(&self) -> Vec<(usize, Symbol, Value)> {
+        let named = self.names.iter().enumerate().zip(&self.slots);
+        named.filter_map(|((i, name), value)| Some((i, (*name)?, value.clone()))).collect()
+    }
+
+    /// The scope this one is nested in, `None` for a top-level scope.
+    pub fn parent(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
+
+    fn enclosing(&self) -> &Rc<RefCell<Environment>> {
+        self.enclosing.as_ref().expect("variable resolved past the outermost scope")
+    }
+
+    pub fn trace(&self, visit: &mut dyn FnMut(ObjectId)) {
+        if let Some(enclosing) = &self.enclosing {
+            visit(gc::id(enclosing));
+        }
+        for value in &self.slots {
+            gc::trace_value(value, visit);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.enclosing = None;
+        self.slots.clear();
+        self.names.clear();
+    }
+}
+
+struct Global {
+    value: Value,
+    constant: bool,
+}
+
+/// Global variables of either backend, indexed by their symbol.
+#[derive(Default)]
+pub struct Globals {
+    values: Vec<Option<Global>>,
+}
+
+impl Globals {
+    pub fn define(&mut self, name: Symbol, value: Value, constant: bool) {
+        let i = name.index();
+        if i >= self.values.len() {
+            self.values.resize_with(i + 1, || None);
+        }
+        self.values[i] = Some(Global { value, constant });
+    }
+
+    pub fn is_constant(&self, name: Symbol) -> bool {
+        matches!(self.values.get(name.index()), Some(Some(Global { constant: true, .. })))
+    }
+
+    pub fn assign(&mut self, name: Symbol, value: Value) -> Result<(), &'static str> {
+        match self.values.get_mut(name.index()) {
+            Some(Some(Global { constant: true, .. })) => Err("Cannot assign to a constant"),
+            Some(Some(global)) => {
+                global.value = value;
+                Ok(())
+            },
+            _ => Err("Variable is not defined"),
+        }
+    }
+
+    pub fn get(&self, name: Symbol) -> Result<&Value, &'static str> {
+        match self.values.get(name.index()) {
+            Some(Some(global)) => Ok(&global.value),
+            _ => Err("Variable is not defined"),
+        }
+    }
+
+    /// Every global defined, in the order their names were interned.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &Value)> {
+        let defined = self.values.iter().enumerate();
+        defined.filter_map(|(i, global)| global.as_ref().map(|global| (Symbol::from_index(i), &global.value)))
+    }
+}
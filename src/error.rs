@@ -1,51 +1,184 @@
 pub mod compiletime {
-    use std::fmt::Display;
+    use std::fmt::{Debug, Display};
 
+    use crate::linter::Rule;
     use crate::token::{Token, TokenKind};
 
+    #[derive(Clone)]
     pub struct ParseError {
-        token: Token,
+        // `None` for errors of the scanner, which has no token yet. Boxed to
+        // keep `Result`s of the parser small
+        token: Option<Box<Token>>,
+        line: usize,
+        column: usize,
         messg: String,
     }
     
     impl ParseError {
         pub fn new(messg: &str, token: &Token) -> ParseError {
-            ParseError { token: token.clone(), messg: messg.to_string() }
+            ParseError { token: Some(Box::new(token.clone())), line: token.line(), column: token.column(), messg: messg.to_string() }
+        }
+
+        pub fn at(messg: &str, line: usize, column: usize) -> ParseError {
+            ParseError { token: None, line, column, messg: messg.to_string() }
+        }
+
+        pub fn message(&self) -> &str {
+            &self.messg
+        }
+
+        /// Where the parser found the error; `None` for the scanner's errors.
+        pub fn token(&self) -> Option<&Token> {
+            self.token.as_deref()
+        }
+
+        pub fn line(&self) -> usize {
+            self.line
+        }
+
+        pub fn column(&self) -> usize {
+            self.column
         }
     }
     impl Display for ParseError {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            match self.token.kind() {
-                TokenKind::Eof => {write!(f, "[line {}] at end: {}", self.token.line(), self.messg)},
-                _ => {write!(f, "[line {}] at '{}': {}", self.token.line(), self.token.lexeme(), self.messg)}
+            match &self.token {
+                Some(token) => fmt_at(f, token, &self.messg),
+                None => write!(f, "[line {}] Error: {}", self.line, self.messg),
             }
-            
+        }
+    }
+
+    impl Debug for ParseError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            Display::fmt(self, f)
+        }
+    }
+
+    impl std::error::Error for ParseError {}
+
+    pub struct TypeError {
+        token: Token,
+        messg: String,
+    }
+
+    impl TypeError {
+        pub fn new(messg: &str, token: &Token) -> TypeError {
+            TypeError { token: token.clone(), messg: messg.to_string() }
+        }
+    }
+
+    impl Display for TypeError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            fmt_at(f, &self.token, &self.messg)
+        }
+    }
+
+    /// A problem found by the `Linter`, followed by the rule that found it.
+    pub struct Lint {
+        token: Token,
+        rule: Rule,
+        messg: String,
+    }
+
+    impl Lint {
+        pub fn new(rule: Rule, messg: &str, token: &Token) -> Lint {
+            Lint { token: token.clone(), rule, messg: messg.to_string() }
+        }
+
+        pub fn rule(&self) -> Rule {
+            self.rule
+        }
+
+        pub fn token(&self) -> &Token {
+            &self.token
+        }
+    }
+
+    impl Display for Lint {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            fmt_at(f, &self.token, &self.messg)?;
+            write!(f, " ({})", self.rule)
+        }
+    }
+
+    fn fmt_at(f: &mut std::fmt::Formatter<'_>, token: &Token, messg: &str) -> std::fmt::Result {
+        match token.kind() {
+            TokenKind::Eof => {write!(f, "[line {}] at end: {}", token.line(), messg)},
+            _ => {write!(f, "[line {}] at '{}': {}", token.line(), token.lexeme(), messg)}
         }
     }
 
 }
 
 pub mod runtime {
-    use std::fmt::Display;
+    use std::fmt::{Debug, Display};
 
+    use crate::budget::Limit;
     use crate::token::Token;
+
+    /// What stopped the evaluation.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum ErrorKind {
+        /// An error in the program; the statements after it still run.
+        Runtime,
+        /// The program used up one of its `Limits` and was stopped.
+        Limit(Limit),
+        /// The program was stopped by `interrupt` (Ctrl-C on the command line).
+        Interrupted,
+    }
+
     pub struct EvalError {
         messg: String,
-        token: Token,
+        // `None` for errors no one token of the program caused. Boxed to keep
+        // `Result`s of the evaluation small
+        token: Option<Box<Token>>,
+        kind: ErrorKind,
     }
     
     impl EvalError {
         pub fn new(messg: &str, token: &Token)->EvalError {
             EvalError {
                 messg: messg.to_string(),
-                token: token.clone(),
+                token: Some(Box::new(token.clone())),
+                kind: ErrorKind::Runtime,
             }
         }
+
+        /// An error raised by the host application rather than by the program.
+        pub fn host(messg: &str) -> EvalError {
+            EvalError { messg: messg.to_string(), token: None, kind: ErrorKind::Runtime }
+        }
+
+        /// An error stopping the whole program, of kind `Limit` or `Interrupted`.
+        pub fn stopped(kind: ErrorKind) -> EvalError {
+            let messg = match kind {
+                ErrorKind::Limit(limit) => limit.to_string(),
+                ErrorKind::Interrupted => "Interrupted".to_string(),
+                ErrorKind::Runtime => unreachable!("runtime errors don't stop the program"),
+            };
+            EvalError { messg, token: None, kind }
+        }
+
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
     }
     
     impl Display for EvalError {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f,"[line {}] at '{}': {}",self.token.line(),self.token.lexeme(), self.messg)
+            match &self.token {
+                Some(token) => write!(f,"[line {}] at '{}': {}",token.line(),token.lexeme(), self.messg),
+                None => write!(f, "{}", self.messg),
+            }
         }
     }
-}
\ No newline at end of file
+
+    impl Debug for EvalError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            Display::fmt(self, f)
+        }
+    }
+
+    impl std::error::Error for EvalError {}
+}
@@ -1,12 +1,17 @@
 //TODO: Change name of the module to ast
 
 pub enum Stmt {
-    Print(Expr),
+    Print {
+        keyword: Token,
+        value: Expr,
+    },
     Expr(Expr),
     Var {
-        // name: Token,
-        name: String,
+        name: Token,
+        ty: Option<Type>,
         initializer: Option<Expr>,
+        constant: bool,
+        slot: Cell<Slot>,
     },
     If {
         condition: Expr,
@@ -17,16 +22,72 @@ pub enum Stmt {
         condition: Expr,
         body: Box<Stmt>,
     },
-    Block(Vec<Stmt>),
+    Block {
+        // `None` for blocks the parser makes up, e.g. around a `for` loop
+        brace: Option<Token>,
+        stmts: Vec<Stmt>,
+    },
+    Function(Rc<FunctionDecl>),
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    Yield {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    ForIn {
+        name: Token,
+        iterable: Expr,
+        body: Box<Stmt>,
+    },
+}
+
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Param>,
+    pub return_ty: Option<Type>,
+    pub body: Vec<Stmt>,
+    // Set when the body contains a `yield`; calling it returns a generator
+    pub generator: bool,
+    pub slot: Cell<Slot>,
+}
+
+pub struct Param {
+    pub name: Token,
+    pub ty: Option<Type>,
+    pub default: Option<Expr>,
+    // `...name` collects the remaining positional arguments into a list
+    pub variadic: bool,
 }
 
+pub struct Arg {
+    // Set for named arguments (`greet(name: "bob")`)
+    pub name: Option<Token>,
+    pub value: Expr,
+}
+
+use std::cell::Cell;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
 use crate::token::Token;
+use crate::types::Type;
 use crate::value::Value;
 
+/// A parsed program. Its syntax tree is private to the crate: the resolver,
+/// the tools and the backends take the program whole.
+pub struct Program {
+    pub(crate) stmts: Vec<Stmt>,
+    // Whether `Resolver::resolve` has given its variables their slots
+    pub(crate) resolved: Cell<bool>,
+}
+
 pub enum Expr {
     Assign {
         name: Token,
         value: Box<Expr>,
+        slot: Cell<Slot>,
     },
     Binary {
         left: Box<Expr>,
@@ -38,32 +99,199 @@ pub enum Expr {
         op: Token,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        args: Vec<Arg>,
+    },
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
     Grouping (Box<Expr>),
     Literal (Value),
-    Variable (Token),
+    Variable {
+        name: Token,
+        slot: Cell<Slot>,
+    },
     Unary {
         op: Token,
         right: Box<Expr>,
     }
 }
 
-// impl Display for Expr {
-//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//         match self {
-//             Expr::Binary { left, op, right } => write!(f,"( {op} {left} {right} )"),
-//             Expr::Grouping(e) => write!(f, "( Grouping {e} )"),
-//             Expr::Literal(l) => write!(f, "{l}"),
-//             Expr::Unary { op, right } => write!(f, "( {op} {right} )"),
-//             Expr::Variable(a) => write!(f,"( VAR {})", a.lexeme()),
-//         }
-//     }
-// }
+/// Where a variable lives, filled in by the `Resolver`. A local is `depth`
+/// environments up from the current one, at `index` among its slots.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub enum Slot {
+    #[default]
+    Global,
+    Local { depth: usize, index: usize },
+}
+
+impl Stmt {
+    /// The first token the statement holds: `None` for those without any,
+    /// like `1;`.
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Stmt::Print { keyword, .. } | Stmt::Return { keyword, .. } | Stmt::Yield { keyword, .. } => {
+                Some(keyword)
+            },
+            Stmt::Expr(e) => e.token(),
+            Stmt::Var { name, .. } | Stmt::ForIn { name, .. } => Some(name),
+            Stmt::Function(decl) => Some(&decl.name),
+            Stmt::If { condition, then_br, .. } => condition.token().or_else(|| then_br.token()),
+            Stmt::While { condition, body } => condition.token().or_else(|| body.token()),
+            Stmt::Block { brace, stmts } => brace.as_ref().or_else(|| stmts.iter().find_map(Stmt::token)),
+        }
+    }
+
+    /// Line the statement starts on, as far as its tokens tell.
+    pub fn line(&self) -> Option<usize> {
+        self.token().map(Token::line)
+    }
+}
+
+impl Expr {
+    /// The first token of the expression; `None` for literals.
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Expr::Literal(_) => None,
+            Expr::Assign { name, .. } | Expr::Variable { name, .. } => Some(name),
+            Expr::Unary { op, .. } => Some(op),
+            Expr::Grouping(e) => e.token(),
+            Expr::Binary { left, op, .. } | Expr::Logical { left, op, .. } => left.token().or(Some(op)),
+            Expr::Call { callee, paren, .. } => callee.token().or(Some(paren)),
+            Expr::Index { object, bracket, .. } => object.token().or(Some(bracket)),
+        }
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        self.token().map(Token::line)
+    }
+}
+
+/// Prints the tree as an S-expression, like the book's `AstPrinter`:
+/// `(print (+ 1 (group 2)))`.
+impl Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stmt::Print { value, .. } => write!(f, "(print {value})"),
+            Stmt::Expr(e) => write!(f, "(expr {e})"),
+            Stmt::Var { name, ty, initializer, constant, .. } => {
+                write!(f, "({} {}", if *constant { "const" } else { "var" }, name.lexeme())?;
+                if let Some(ty) = ty {
+                    write!(f, ": {ty}")?;
+                }
+                if let Some(init) = initializer {
+                    write!(f, " {init}")?;
+                }
+                write!(f, ")")
+            },
+            Stmt::If { condition, then_br, else_br } => {
+                write!(f, "(if {condition} {then_br}")?;
+                if let Some(else_br) = else_br {
+                    write!(f, " {else_br}")?;
+                }
+                write!(f, ")")
+            },
+            Stmt::While { condition, body } => write!(f, "(while {condition} {body})"),
+            Stmt::Block { stmts, .. } => {
+                write!(f, "(block")?;
+                for stmt in stmts {
+                    write!(f, " {stmt}")?;
+                }
+                write!(f, ")")
+            },
+            Stmt::Function(decl) => write!(f, "{decl}"),
+            Stmt::Return { value, .. } => match value {
+                Some(value) => write!(f, "(return {value})"),
+                None => write!(f, "(return)"),
+            },
+            Stmt::Yield { value, .. } => match value {
+                Some(value) => write!(f, "(yield {value})"),
+                None => write!(f, "(yield)"),
+            },
+            Stmt::ForIn { name, iterable, body } => write!(f, "(for {} {iterable} {body})", name.lexeme()),
+        }
+    }
+}
+
+/// One statement per line.
+impl Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for stmt in &self.stmts {
+            writeln!(f, "{stmt}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for FunctionDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(fun {} (", self.name.lexeme())?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{param}")?;
+        }
+        write!(f, ")")?;
+        if let Some(ty) = self.return_ty {
+            write!(f, ": {ty}")?;
+        }
+        for stmt in &self.body {
+            write!(f, " {stmt}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.variadic {
+            write!(f, "...")?;
+        }
+        write!(f, "{}", self.name.lexeme())?;
+        if let Some(ty) = self.ty {
+            write!(f, ": {ty}")?;
+        }
+        match &self.default {
+            Some(default) => write!(f, " = {default}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Assign { name, value, .. } => write!(f, "(= {} {value})", name.lexeme()),
+            Expr::Binary { left, op, right } | Expr::Logical { left, op, right } => {
+                write!(f, "({} {left} {right})", op.lexeme())
+            },
+            Expr::Call { callee, args, .. } => {
+                write!(f, "(call {callee}")?;
+                for arg in args {
+                    match &arg.name {
+                        Some(name) => write!(f, " {}: {}", name.lexeme(), arg.value)?,
+                        None => write!(f, " {}", arg.value)?,
+                    }
+                }
+                write!(f, ")")
+            },
+            Expr::Index { object, index, .. } => write!(f, "(index {object} {index})"),
+            Expr::Grouping(e) => write!(f, "(group {e})"),
+            Expr::Literal(value) => write!(f, "{value}"),
+            Expr::Variable { name, .. } => write!(f, "{}", name.lexeme()),
+            Expr::Unary { op, right } => write!(f, "({} {right})", op.lexeme()),
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::token::{Token, TokenKind};
-    use super::*;
-
     // fn gen_expr() -> Expr {
     //     TokenKind::Number(45.67);
     //     Expr::Binary {
@@ -0,0 +1,154 @@
+//! Opinionated formatter, reprinting a program token by token with canonical
+//! indentation, spacing and brace style. Comments come along as the trivia
+//! of the tokens they precede, so nothing but whitespace changes.
+
+use crate::error::compiletime::ParseError;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::token::{Token, TokenKind};
+
+const INDENT: &str = "  ";
+
+/// Formats `source`, or returns its syntax errors: only programs that parse
+/// are formatted.
+pub fn format(source: &str) -> Result<String, Vec<ParseError>> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.tokens().to_vec();
+    if !scanner.errors().is_empty() {
+        return Err(scanner.errors().to_vec());
+    }
+    let mut parser = Parser::new(tokens.clone());
+    let _ = parser.stmts();
+    if !parser.errors().is_empty() {
+        return Err(parser.errors().to_vec());
+    }
+    let mut printer = Printer::default();
+    printer.print(&tokens);
+    Ok(printer.out)
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize,
+    // Open `(` and `[`: the semicolons of a `for` don't end lines
+    parens: usize,
+    // Line in the source of the last token or comment printed
+    last_line: usize,
+    // Whatever comes next starts a new line
+    newline: bool,
+    // The last token printed was a prefix `-` or `!`
+    unary: bool,
+    // Inside `{}`, which stays on one line
+    empty_block: bool,
+    // Nothing was printed yet on its own line in the block just opened
+    opened: bool,
+}
+
+impl Printer {
+    fn print(&mut self, tokens: &[Token]) {
+        let mut prev: Option<&Token> = None;
+        for (i, token) in tokens.iter().enumerate() {
+            for comment in token.comments() {
+                if !self.out.is_empty() && comment.line == self.last_line {
+                    self.out.push(' ');
+                } else {
+                    self.start_line(comment.line, true);
+                }
+                self.out.push_str(&comment.text);
+                self.newline = true;
+                self.last_line = comment.line;
+            }
+            let kind = token.kind();
+            if *kind == TokenKind::Eof {
+                break;
+            }
+            if *kind == TokenKind::RightBrace && !self.empty_block {
+                self.depth -= 1;
+                self.newline = true;
+            }
+            if self.newline {
+                self.start_line(token.line(), *kind != TokenKind::RightBrace);
+                self.newline = false;
+            } else if prev.is_some_and(|prev| self.space_between(prev, token)) {
+                self.out.push(' ');
+            }
+            match kind {
+                TokenKind::String => {
+                    self.out.push('"');
+                    self.out.push_str(token.lexeme());
+                    self.out.push('"');
+                },
+                _ => self.out.push_str(token.lexeme()),
+            }
+
+            let next = tokens.get(i + 1);
+            self.unary = matches!(kind, TokenKind::Minus | TokenKind::Bang) && !prev.is_some_and(ends_operand);
+            match kind {
+                TokenKind::LeftParen | TokenKind::LeftBracket => self.parens += 1,
+                TokenKind::RightParent | TokenKind::RightBracket => self.parens = self.parens.saturating_sub(1),
+                TokenKind::Semicolon => self.newline = self.parens == 0,
+                TokenKind::LeftBrace => {
+                    match next {
+                        Some(next) if *next.kind() == TokenKind::RightBrace && next.comments().is_empty() => {
+                            self.empty_block = true;
+                        },
+                        _ => {
+                            self.depth += 1;
+                            self.newline = true;
+                            self.opened = true;
+                        },
+                    }
+                },
+                TokenKind::RightBrace => {
+                    self.empty_block = false;
+                    // `} else {`
+                    self.newline = !next.is_some_and(|next| *next.kind() == TokenKind::Else);
+                },
+                _ => {},
+            }
+            self.last_line = token.line();
+            prev = Some(token);
+        }
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    // Keeps one blank line where the source had any, except at the start of
+    // a block and, with `blank` false, at its end
+    fn start_line(&mut self, line: usize, blank: bool) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+            if blank && line > self.last_line + 1 && !self.opened {
+                self.out.push('\n');
+            }
+        }
+        self.opened = false;
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn space_between(&self, prev: &Token, token: &Token) -> bool {
+        use TokenKind::*;
+        match (prev.kind(), token.kind()) {
+            (_, RightParent | RightBracket | Comma | Semicolon | Colon | Dot) => false,
+            (LeftBrace, RightBrace) => false,
+            (LeftParen | LeftBracket | Dot | Ellipsis, _) => false,
+            (Minus | Bang, _) if self.unary => false,
+            // Calls and indexing
+            (_, LeftParen | LeftBracket) => !ends_operand(prev),
+            _ => true,
+        }
+    }
+}
+
+// Whether a `-` or `!` after this token is binary, a `(` a call
+fn ends_operand(token: &Token) -> bool {
+    use TokenKind::*;
+    matches!(
+        token.kind(),
+        Identifier(_) | String | Number | True | False | Nil | This | Super | RightParent | RightBracket
+    )
+}
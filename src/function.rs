@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::environment::Environment;
+use crate::error::runtime::EvalError;
+use crate::expr::FunctionDecl;
+use crate::generator::{Generator, GeneratorState};
+use crate::value::Value;
+
+/// A function declared in Lux, which hosts can only call (see `Lux::call`)
+/// or name.
+pub struct Function {
+    pub(crate) decl: Rc<FunctionDecl>,
+    // `None` for functions declared at the top level
+    pub(crate) closure: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Function {
+    pub(crate) fn new(decl: Rc<FunctionDecl>, closure: Option<Rc<RefCell<Environment>>>) -> Function {
+        Function { decl, closure }
+    }
+
+    pub fn name(&self) -> &str {
+        self.decl.name.lexeme()
+    }
+
+    /// Number of parameters that have neither a default nor are variadic.
+    pub fn min_arity(&self) -> usize {
+        self.decl.params.iter().filter(|p| p.default.is_none() && !p.variadic).count()
+    }
+
+    /// `None` when a variadic parameter accepts any number of extra arguments.
+    pub fn max_arity(&self) -> Option<usize> {
+        if self.decl.params.iter().any(|p| p.variadic) {
+            None
+        } else {
+            Some(self.decl.params.len())
+        }
+    }
+
+    pub fn arity_messg(&self, got: usize) -> String {
+        arity_messg(self.min_arity(), self.max_arity(), got)
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+/// What a backend offers to the natives it runs.
+pub trait Runtime {
+    /// Runs a generator until its next `yield` (`Some`) or until it finishes (`None`).
+    fn resume(&mut self, gen: &Rc<RefCell<Generator>>) -> Result<Option<Value>, EvalError>;
+
+    /// Runs the garbage collector, returning how many objects it freed.
+    fn collect(&mut self) -> usize;
+}
+
+/// Why a native function failed.
+pub enum NativeError {
+    /// A message reported at the call.
+    Message(String),
+    /// An error of the Lux code the native ran, reported as it is.
+    Eval(EvalError),
+}
+
+impl From<String> for NativeError {
+    fn from(messg: String) -> NativeError {
+        NativeError::Message(messg)
+    }
+}
+
+impl From<EvalError> for NativeError {
+    fn from(e: EvalError) -> NativeError {
+        NativeError::Eval(e)
+    }
+}
+
+type NativeFn = dyn Fn(&mut dyn Runtime, &[Value]) -> Result<Value, NativeError>;
+
+/// A function implemented in Rust and exposed to Lux as a global.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    fun: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, arity: usize, fun: F) -> NativeFunction
+    where
+        F: Fn(&mut dyn Runtime, &[Value]) -> Result<Value, NativeError> + 'static,
+    {
+        NativeFunction { name: name.to_string(), arity, fun: Box::new(fun) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn call(&self, runtime: &mut dyn Runtime, args: &[Value]) -> Result<Value, NativeError> {
+        (self.fun)(runtime, args)
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+/// Nesting of calls allowed before a "Stack overflow" error, unless the
+/// backend is configured otherwise. The tree-walking interpreter recurses
+/// on the native stack, and this fits in 8MB even in debug builds; raise
+/// it only on a thread with a larger stack.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 200;
+
+pub fn arity_messg(min: usize, max: Option<usize>, got: usize) -> String {
+    match max {
+        Some(max) if max == min => format!("Expected {min} arguments but got {got}"),
+        Some(max) => format!("Expected {min} to {max} arguments but got {got}"),
+        None => format!("Expected at least {min} arguments but got {got}"),
+    }
+}
+
+/// Globals every backend starts with.
+pub fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("clock", 0, |_, _| {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?;
+            Ok(Value::Number(now.as_secs_f64()))
+        }),
+        NativeFunction::new("len", 1, |_, args| {
+            match &args[0] {
+                Value::List(items) => Ok(Value::Number(items.borrow().len() as f64)),
+                Value::String(s) => Ok(Value::Number(s.as_str().chars().count() as f64)),
+                _ => Err("Argument must be a list or a string".to_string().into()),
+            }
+        }),
+        // Resumes a generator, returning nil once it is exhausted
+        NativeFunction::new("next", 1, |runtime, args| {
+            match &args[0] {
+                Value::Generator(gen) => Ok(runtime.resume(gen)?.unwrap_or(Value::Nil)),
+                _ => Err("Argument must be a generator".to_string().into()),
+            }
+        }),
+        // Whether a generator has finished, telling a yielded nil from the
+        // nil `next` returns once it is exhausted
+        NativeFunction::new("done", 1, |_, args| {
+            match &args[0] {
+                Value::Generator(gen) => Ok(Value::Bool(matches!(gen.borrow().state, GeneratorState::Done))),
+                _ => Err("Argument must be a generator".to_string().into()),
+            }
+        }),
+        // Lux code can't change a list, nor any other value, so every value
+        // is already as read-only as freezing would make it
+        NativeFunction::new("freeze", 1, |_, args| {
+            Ok(args[0].clone())
+        }),
+        NativeFunction::new("gc", 0, |runtime, _| {
+            Ok(Value::Number(runtime.collect() as f64))
+        }),
+    ]
+}
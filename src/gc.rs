@@ -0,0 +1,204 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::environment::Environment;
+use crate::function::Function;
+use crate::generator::{Frame, Generator, GeneratorBody, GeneratorState, Iter};
+use crate::value::Value;
+
+// Collections run once this many objects were allocated since the last one,
+// or twice the number of objects that survived it, whichever is larger
+const INITIAL_THRESHOLD: usize = 1024;
+
+/// Identity of a heap object: the address of its `Rc` allocation.
+pub type ObjectId = *const ();
+
+/// An object that can hold references to other heap objects.
+pub trait Trace {
+    /// Calls `visit` once for every reference to a heap object held by this
+    /// one. Returns false when the object is borrowed and cannot be traced.
+    fn trace(&self, visit: &mut dyn FnMut(ObjectId)) -> bool;
+
+    /// Drops every reference held by this object, breaking the cycles it is part of.
+    fn clear(&self);
+}
+
+/// Mark-and-sweep collector for the reference cycles `Rc` cannot free.
+///
+/// Every object that can hold references (environments, functions, lists,
+/// generators, closures and upvalues) is allocated through the heap, which
+/// keeps a weak reference to it. Collection finds the roots without asking
+/// the backends for them: an object whose strong count is higher than the
+/// number of references coming from other heap objects is held by the
+/// interpreter itself (its environments, call stack or temporaries). Objects
+/// not reachable from a root are garbage and get cleared, so `Rc` can free them.
+pub struct Heap {
+    objects: Vec<Weak<dyn Trace>>,
+    allocated: usize,
+    threshold: usize,
+    // Bytes allocated so far, freed or not, see `Limits::max_heap_bytes`
+    bytes: usize,
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap { objects: Vec::new(), allocated: 0, threshold: INITIAL_THRESHOLD, bytes: 0 }
+    }
+
+    pub fn alloc<T: Trace + 'static>(&mut self, object: T) -> Rc<T> {
+        if self.allocated >= self.threshold {
+            self.collect();
+        }
+        self.allocated += 1;
+        self.bytes += std::mem::size_of::<T>();
+        let object = Rc::new(object);
+        let weak: Weak<dyn Trace> = Rc::downgrade(&object) as Weak<dyn Trace>;
+        self.objects.push(weak);
+        object
+    }
+
+    /// Counts memory allocated outside the heap's objects, like list items and strings.
+    pub fn account(&mut self, bytes: usize) {
+        self.bytes += bytes;
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Frees every unreachable object, returning how many there were.
+    pub fn collect(&mut self) -> usize {
+        self.objects.retain(|object| object.strong_count() > 0);
+        let index: HashMap<ObjectId, usize> = self.objects.iter()
+            .enumerate()
+            .map(|(i, object)| (object.as_ptr() as ObjectId, i))
+            .collect();
+
+        // References between heap objects
+        let mut edges = vec![Vec::new(); self.objects.len()];
+        let mut internal = vec![0; self.objects.len()];
+        let mut traced = vec![false; self.objects.len()];
+        for (i, object) in self.objects.iter().enumerate() {
+            let Some(object) = object.upgrade() else { continue };
+            traced[i] = object.trace(&mut |id| {
+                if let Some(&child) = index.get(&id) {
+                    edges[i].push(child);
+                    internal[child] += 1;
+                }
+            });
+        }
+
+        let mut marked = vec![false; self.objects.len()];
+        let mut pending: Vec<usize> = (0..self.objects.len())
+            .filter(|&i| !traced[i] || self.objects[i].strong_count() > internal[i])
+            .collect();
+        while let Some(i) = pending.pop() {
+            if !marked[i] {
+                marked[i] = true;
+                pending.extend(&edges[i]);
+            }
+        }
+
+        // Keep the garbage alive until every object in it has been cleared
+        let garbage: Vec<Rc<dyn Trace>> = self.objects.iter()
+            .zip(&marked)
+            .filter(|(_, &marked)| !marked)
+            .filter_map(|(object, _)| object.upgrade())
+            .collect();
+        for object in &garbage {
+            object.clear();
+        }
+        let freed = garbage.len();
+        drop(garbage);
+
+        self.objects.retain(|object| object.strong_count() > 0);
+        self.allocated = 0;
+        self.threshold = INITIAL_THRESHOLD.max(self.objects.len() * 2);
+        freed
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn id<T: ?Sized>(object: &Rc<T>) -> ObjectId {
+    Rc::as_ptr(object) as ObjectId
+}
+
+pub fn trace_value(value: &Value, visit: &mut dyn FnMut(ObjectId)) {
+    match value {
+        Value::Function(fun) => visit(id(fun)),
+        Value::Closure(closure) => visit(id(closure)),
+        Value::List(items) => visit(id(items)),
+        Value::Generator(gen) => visit(id(gen)),
+        Value::Number(_) | Value::String(_) | Value::Bool(_) | Value::Nil | Value::Native(_) => {},
+    }
+}
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, visit: &mut dyn FnMut(ObjectId)) -> bool {
+        let Ok(env) = self.try_borrow() else { return false };
+        env.trace(visit);
+        true
+    }
+
+    fn clear(&self) {
+        self.borrow_mut().clear();
+    }
+}
+
+impl Trace for Function {
+    fn trace(&self, visit: &mut dyn FnMut(ObjectId)) -> bool {
+        if let Some(closure) = &self.closure {
+            visit(id(closure));
+        }
+        true
+    }
+
+    // Immutable: freed once the environment it closes over is cleared
+    fn clear(&self) {}
+}
+
+impl Trace for RefCell<Vec<Value>> {
+    fn trace(&self, visit: &mut dyn FnMut(ObjectId)) -> bool {
+        let Ok(items) = self.try_borrow() else { return false };
+        for item in items.iter() {
+            trace_value(item, visit);
+        }
+        true
+    }
+
+    fn clear(&self) {
+        self.borrow_mut().clear();
+    }
+}
+
+impl Trace for RefCell<Generator> {
+    fn trace(&self, visit: &mut dyn FnMut(ObjectId)) -> bool {
+        let Ok(gen) = self.try_borrow() else { return false };
+        match &gen.state {
+            GeneratorState::Suspended(GeneratorBody::Tree { env, frames, .. }) => {
+                visit(id(env));
+                for frame in frames {
+                    match frame {
+                        Frame::Restore { previous: Some(previous) } => visit(id(previous)),
+                        Frame::ForIn { iter: Iter::List { items, .. }, .. } => visit(id(items)),
+                        Frame::ForIn { iter: Iter::Generator(gen), .. } => visit(id(gen)),
+                        Frame::Restore { previous: None } | Frame::List { .. } | Frame::Stmt { .. } | Frame::While { .. } => {},
+                    }
+                }
+            },
+            GeneratorState::Suspended(GeneratorBody::Vm(suspended)) => suspended.trace(visit),
+            GeneratorState::Running | GeneratorState::Done => {},
+        }
+        true
+    }
+
+    fn clear(&self) {
+        self.borrow_mut().state = GeneratorState::Done;
+    }
+}
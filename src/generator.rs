@@ -0,0 +1,104 @@
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::expr::{FunctionDecl, Stmt};
+use crate::token::Token;
+use crate::value::Value;
+use crate::vm::Suspended;
+
+/// A call to a function containing `yield`.
+pub struct Generator {
+    // Name of the function, also used to report errors about the generator
+    pub(crate) token: Token,
+    pub(crate) state: GeneratorState,
+}
+
+pub enum GeneratorState {
+    Suspended(GeneratorBody),
+    Running,
+    Done,
+}
+
+/// Where each backend keeps the state of a suspended generator.
+///
+/// The tree-walking evaluator keeps its state on the Rust stack, so a
+/// generator body is instead driven by an explicit stack of frames over
+/// its statements. Statements are addressed by their path from the body
+/// (child indices: block items, 0/1 for then/else, 0 for loop bodies), and
+/// expressions are still evaluated in one go, which is why `yield` is a
+/// statement.
+pub enum GeneratorBody {
+    Tree {
+        decl: Rc<FunctionDecl>,
+        env: Rc<RefCell<Environment>>,
+        frames: Vec<Frame>,
+    },
+    Vm(Suspended),
+}
+
+pub enum Frame {
+    // Executes the statements of the body (empty path) or of a block one by one
+    List { path: Vec<usize>, next: usize },
+    // A statement about to be executed
+    Stmt { path: Vec<usize> },
+    // Re-evaluates the condition of the `while` at `path` on every visit
+    While { path: Vec<usize> },
+    ForIn { path: Vec<usize>, iter: Iter },
+    // Leaves a scope opened by a block or a loop iteration
+    Restore { previous: Option<Rc<RefCell<Environment>>> },
+}
+
+/// Source of the values of a `for-in` loop.
+pub enum Iter {
+    List { items: Rc<RefCell<Vec<Value>>>, next: usize },
+    Generator(Rc<RefCell<Generator>>),
+}
+
+impl Generator {
+    pub(crate) fn new(decl: Rc<FunctionDecl>, env: Rc<RefCell<Environment>>) -> Generator {
+        Generator {
+            token: decl.name.clone(),
+            state: GeneratorState::Suspended(GeneratorBody::Tree {
+                decl,
+                env,
+                frames: vec![Frame::List { path: Vec::new(), next: 0 }],
+            }),
+        }
+    }
+}
+
+impl PartialEq for Generator {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<generator {}>", self.token.lexeme())
+    }
+}
+
+/// Statement at `path` inside `body`. Paths are only built from existing
+/// children, so a missing one is a bug in the generator driver.
+pub fn stmt_at<'a>(body: &'a [Stmt], path: &[usize]) -> &'a Stmt {
+    let mut stmt = &body[path[0]];
+    for &i in &path[1..] {
+        stmt = match (stmt, i) {
+            (Stmt::Block { stmts, .. }, i) => &stmts[i],
+            (Stmt::If { then_br, .. }, 0) => then_br,
+            (Stmt::If { else_br: Some(else_br), .. }, 1) => else_br,
+            (Stmt::While { body, .. }, 0) | (Stmt::ForIn { body, .. }, 0) => body,
+            _ => unreachable!("invalid generator path"),
+        };
+    }
+    stmt
+}
+
+pub fn child(path: &[usize], i: usize) -> Vec<usize> {
+    let mut path = path.to_vec();
+    path.push(i);
+    path
+}
@@ -1,133 +1,368 @@
-use std::collections::HashMap;
 use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
 
-use crate::expr::Expr;
+use crate::environment::{Environment, Globals};
+use crate::budget::{Budget, Limits};
+use crate::debugger::{Debugger, Session};
+use crate::profiler::Profiler;
+use crate::coverage::Coverage;
+use crate::interrupt::Interrupt;
+use crate::gc::Heap;
+use crate::function::{arity_messg, natives, Function, NativeError, Runtime, DEFAULT_MAX_CALL_DEPTH};
+use crate::generator::{child, stmt_at, Frame, Generator, GeneratorBody, GeneratorState, Iter};
+use crate::expr::{Arg, Expr, Slot};
+use crate::symbol::Symbol;
 use crate::token::{TokenKind, Token};
-use crate::error::runtime::EvalError;
+use crate::error::compiletime::ParseError;
+use crate::error::runtime::{ErrorKind, EvalError};
+use crate::lux::Error;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
 use crate::value::Value;
-use crate::expr::Stmt;
+use crate::expr::{Program, Stmt};
 
-struct Environment {
-    enclosing: Option<Box<Environment>>,
-    values: HashMap<String, Value>,
+/// Why the evaluation of a statement stopped early.
+enum Flow {
+    Return(Value),
+    // `return f(x);` leaves the call to the caller's `call_function`, so
+    // tail calls run in constant stack space
+    TailCall(Box<TailCall>),
+    Error(EvalError),
 }
 
-impl Environment {
-    pub fn new() -> Environment {
-        Environment {
-            enclosing:None,
-            values: HashMap::new(),
-        }
+struct TailCall {
+    fun: Rc<Function>,
+    positional: Vec<Value>,
+    named: Vec<(Token, Value)>,
+    paren: Token,
+}
+
+impl From<EvalError> for Flow {
+    fn from(e: EvalError) -> Self {
+        Flow::Error(e)
     }
+}
+
+pub struct Interpreter {
+    // `None` at the top level
+    environment: Option<Rc<RefCell<Environment>>>,
+    globals: Globals,
+    heap: Heap,
+    // Function calls and generator resumptions in progress
+    depth: usize,
+    max_depth: usize,
+    budget: Budget,
+    out: Box<dyn Write>,
+    diagnostics: Box<dyn Write>,
+    // Checked before each statement only when set
+    debugger: Option<Box<Session>>,
+    // Times statements and calls only when set
+    profiler: Option<Box<Profiler>>,
+    // Counts lines and branches only when set
+    coverage: Option<Box<Coverage>>,
+}
+
+// Gives the variables of a program no resolver has seen their slots
+fn resolve(program: &Program) -> Result<(), Vec<ParseError>> {
+    let mut resolver = Resolver::new();
+    match program.resolved.get() || resolver.resolve(program) {
+        true => Ok(()),
+        false => Err(resolver.errors().to_vec()),
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    pub fn push(self) -> Environment {
-        Environment { 
-            enclosing: Some(Box::new(self)), 
-            values: HashMap::new(), 
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut globals = Globals::default();
+        for native in natives() {
+            let name = Symbol::intern(native.name());
+            globals.define(name, Value::Native(Rc::new(native)), false);
+        }
+        Interpreter {
+            environment: None,
+            globals,
+            heap: Heap::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_CALL_DEPTH,
+            budget: Budget::default(),
+            out: Box::new(io::stdout()),
+            diagnostics: Box::new(io::stderr()),
+            debugger: None,
+            profiler: None,
+            coverage: None,
         }
     }
 
-    pub fn pop(self) -> Environment {
-        *self.enclosing.unwrap()
+    /// Calls nested deeper than this raise a "Stack overflow" error.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
     }
-    pub fn define(&mut self, name: String, value:Value) {
-        self.values.insert(name, value);
+
+    /// Limits every following call to `interpret`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget.set_limits(limits);
     }
 
-    pub fn assign(&mut self, name_tk: &Token, value:Value) -> Result<(), EvalError> {
-        let name = name_tk.lexeme();
-        if self.values.contains_key(name) {
-            self.values.insert(name.to_string(), value);
-            Ok(())
-        } else {
-            match &mut self.enclosing {
-                Some(enclosing) => {
-                    enclosing.assign(name_tk, value)?;
-                    Ok(())
-                },
-                None => {
-                    Err(EvalError::new("Variable is not defined", name_tk))
+    /// A handle stopping the running `interpret` from any thread.
+    pub fn interrupt_handle(&self) -> Interrupt {
+        self.budget.interrupt().clone()
+    }
+
+    /// Where `print` writes, standard output by default.
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        self.out = out;
+    }
+
+    /// Where reported errors go, standard error by default.
+    pub fn set_diagnostics(&mut self, diagnostics: Box<dyn Write>) {
+        self.diagnostics = diagnostics;
+    }
+
+    /// Pauses the program for `debugger` before its first line, then at its
+    /// breakpoints and wherever it steps to.
+    pub fn set_debugger(&mut self, debugger: Box<dyn Debugger>) {
+        self.debugger = Some(Box::new(Session::new(debugger)));
+    }
+
+    /// Times every following statement and call with `profiler`.
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(Box::new(profiler));
+    }
+
+    /// What the profiler recorded so far, if one is set.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+
+    /// Records which lines and branches of every following program run.
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(Box::new(coverage));
+    }
+
+    /// What the coverage recorded so far, if it is set.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_deref()
+    }
+
+    /// Reports runtime errors and carries on with the next statement, unless
+    /// the program exceeded its `Limits` or was interrupted: then it stops
+    /// with that error. A program `Resolver::resolve` hasn't been through is
+    /// resolved first, and not run if that fails, its errors being reported
+    /// like the VM's compilation errors.
+    pub fn interpret(&mut self, program: Program) -> Result<(), EvalError> {
+        if let Err(errors) = resolve(&program) {
+            for e in errors {
+                let _ = writeln!(self.diagnostics, "{e}");
+            }
+            return Ok(());
+        }
+        let mut stmts = program.stmts;
+        self.budget.start(self.heap.bytes());
+        if let Some(coverage) = &mut self.coverage {
+            coverage.add(&stmts);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_call(Symbol::intern("<script>"), None);
+        }
+        let mut result = Ok(());
+        for stmt in stmts.iter_mut() {
+            if let Err(Flow::Error(e)) = self.eval_stmt(stmt) {
+                if let Some(e) = self.budget.stopped() {
+                    result = Err(e);
+                    break;
                 }
+                let _ = writeln!(self.diagnostics, "{e}");
+                //set runtimeErr = true
             }
         }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_call();
+        }
+        result
     }
 
-    pub fn get(&self, name_tk: &Token) -> Result<Value, EvalError> {
-        match self.values.get(name_tk.lexeme()) {
-            Some(v) => Ok(v.clone()),
-            None => {
-                match &self.enclosing {
-                    Some(enclosing) => {
-                        Ok(enclosing.get(name_tk)?)
-                    },
-                    None => Err(EvalError::new("Variable is not defined", name_tk))
-                }
+    fn eval_block(&mut self, stmts: &[Stmt], env: Rc<RefCell<Environment>>) -> Result<(), Flow> {
+        let previous = self.environment.replace(env);
+        let mut result = Ok(());
+        for stmt in stmts {
+            result = self.eval_stmt(stmt);
+            if result.is_err() {
+                break;
             }
         }
+        self.environment = previous;
+        result
     }
-}
-pub struct Interpreter {
-    environment: Option<Environment>,
-}
 
-impl Interpreter {
-    pub fn new() -> Interpreter {
-        Interpreter {
-            environment: Some(Environment::new()),
+    /// Runs `stmts` until the first error, which it returns instead of
+    /// reporting. Evaluates to the value of the last statement when it is an
+    /// expression statement, to nil otherwise. Resolves the program like
+    /// `interpret`, returning the first error of that instead.
+    pub fn eval(&mut self, program: Program) -> Result<Value, EvalError> {
+        if let Err(errors) = resolve(&program) {
+            let e = &errors[0];
+            return Err(e.token().map_or_else(|| EvalError::host(e.message()), |token| EvalError::new(e.message(), token)));
+        }
+        let stmts = program.stmts;
+        self.budget.start(self.heap.bytes());
+        if let Some(coverage) = &mut self.coverage {
+            coverage.add(&stmts);
+        }
+        let mut value = Value::Nil;
+        for stmt in &stmts {
+            let result = match stmt {
+                Stmt::Expr(e) => self.eval_expr(e).map_err(Flow::Error),
+                stmt => self.eval_stmt(stmt).map(|_| Value::Nil),
+            };
+            value = match result {
+                Ok(value) => value,
+                Err(Flow::Error(e)) => return Err(self.budget.stopped().unwrap_or(e)),
+                Err(Flow::Return(_) | Flow::TailCall(_)) => unreachable!("return outside a function"),
+            };
         }
+        Ok(value)
     }
 
-    pub fn interpret(&mut self, mut stmts:Vec<Stmt>) {
-        for stmt in stmts.iter_mut() {
-            if let Err(e) = self.eval_stmt(stmt) {
-                eprintln!("{e}");
-                //set runtimeErr = true
-            }
+    /// Calls a function with positional arguments on behalf of the host,
+    /// under the same limits as `eval`. Errors of the call itself point at the
+    /// name of a Lux function.
+    pub fn call_value(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, EvalError> {
+        self.budget.start(self.heap.bytes());
+        let result = match callee {
+            Value::Function(ref fun) => {
+                let name = fun.decl.name.clone();
+                self.call(callee, args, Vec::new(), &name)
+            },
+            Value::Native(fun) if args.len() != fun.arity() => {
+                Err(EvalError::host(&arity_messg(fun.arity(), Some(fun.arity()), args.len())))
+            },
+            Value::Native(fun) => fun.call(self, &args).map_err(|e| match e {
+                NativeError::Message(messg) => EvalError::host(&messg),
+                NativeError::Eval(e) => e,
+            }),
+            _ => Err(EvalError::host("Can only call functions")),
+        };
+        result.map_err(|e| self.budget.stopped().unwrap_or(e))
+    }
+
+    /// The value of a global variable, if it is defined.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.get(Symbol::intern(name)).ok().cloned()
+    }
+
+    /// Defines or redefines a global variable, unless it is a constant.
+    pub fn define_global(&mut self, name: &str, value: Value) -> Result<(), EvalError> {
+        let name = Symbol::intern(name);
+        if self.globals.is_constant(name) {
+            return Err(EvalError::host("Cannot assign to a constant"));
         }
+        self.globals.define(name, value, false);
+        Ok(())
     }
 
-    fn eval_stmt(&mut self, stmt: &Stmt) -> Result<(), EvalError>{
+    fn eval_stmt(&mut self, stmt: &Stmt) -> Result<(), Flow>{
+        self.budget.step(self.heap.bytes())?;
+        if self.debugger.is_some() {
+            self.debug(stmt)?;
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.run(stmt);
+        }
+        if self.profiler.is_some() {
+            return self.profile(stmt);
+        }
+        self.run_stmt(stmt)
+    }
+
+    // Times `stmt` under its line; blocks only run the statements timed
+    fn profile(&mut self, stmt: &Stmt) -> Result<(), Flow> {
+        let line = match stmt {
+            Stmt::Block { .. } => None,
+            stmt => stmt.line(),
+        };
+        let timed = line.is_some_and(|line| self.profiler.as_mut().unwrap().enter_line(line));
+        let result = self.run_stmt(stmt);
+        if timed {
+            self.profiler.as_mut().unwrap().exit_line();
+        }
+        result
+    }
+
+    fn run_stmt(&mut self, stmt: &Stmt) -> Result<(), Flow> {
         match stmt {
             Stmt::Expr(e) => {
                 let _ = self.eval_expr(e)?;
                 Ok(())
             },
-            Stmt::Print(e) => {
-                let val = self.eval_expr(e)?;
-                println!("{val}");
+            Stmt::Print { value, .. } => {
+                let val = self.eval_expr(value)?;
+                let _ = writeln!(self.out, "{val}");
                 Ok(())
             }
-            Stmt::Var { name, initializer } => {
+            Stmt::Var { name, initializer, constant, slot, .. } => {
                 let val = match initializer {
                     Some(expr) => {
                         self.eval_expr(expr)?
                     },
                     None => Value::Nil
                 };
-                self.environment.as_mut().unwrap().define(name.to_string(), val);
+                self.define(name, slot.get(), val, *constant);
                 Ok(())
             },
-            Stmt::Block(stmts) => {
-                if let Some(e) = self.environment.take() {
-                    self.environment = Some(e.push());
-                }
-                for stmt in stmts {
-                    if let Err(e) = self.eval_stmt(stmt) {
-                        if let Some(e) = self.environment.take() {
-                            self.environment = Some(e.pop());
-                        }
-                        return Err(e);
-                    }
+            Stmt::Block { stmts, .. } => {
+                let env = Environment::with_enclosing(self.environment.clone());
+                let env = self.heap.alloc(RefCell::new(env));
+                self.eval_block(stmts, env)
+            },
+            Stmt::Function(decl) => {
+                let fun = self.heap.alloc(Function::new(decl.clone(), self.environment.clone()));
+                self.define(&decl.name, decl.slot.get(), Value::Function(fun), false);
+                Ok(())
+            },
+            Stmt::Return { value: Some(Expr::Call { callee, paren, args }), .. } => {
+                let (callee, positional, named) = self.eval_args(callee, args)?;
+                match callee {
+                    Value::Function(fun) => {
+                        Err(Flow::TailCall(Box::new(TailCall { fun, positional, named, paren: paren.clone() })))
+                    },
+                    callee => Err(Flow::Return(self.call(callee, positional, named, paren)?)),
                 }
-                
-                if let Some(e) = self.environment.take() {
-                    self.environment = Some(e.pop());
+            },
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(expr) => self.eval_expr(expr)?,
+                    None => Value::Nil,
+                };
+                Err(Flow::Return(value))
+            },
+            Stmt::Yield { keyword, .. } => {
+                // Generator bodies are driven by `run_frames`
+                Err(EvalError::new("Cannot yield here", keyword).into())
+            },
+            Stmt::ForIn { name, iterable, body } => {
+                let value = self.eval_expr(iterable)?;
+                let mut iter = self.iter(value, name)?;
+                while let Some(item) = self.next_item(&mut iter)? {
+                    let mut env = Environment::with_enclosing(self.environment.clone());
+                    env.define(0, item);
+                    let env = self.heap.alloc(RefCell::new(env));
+                    self.name_local(&env, 0, name);
+                    self.eval_block(std::slice::from_ref(body), env)?;
                 }
-
                 Ok(())
             },
             Stmt::If { condition, then_br, else_br } => {
-                if self.eval_expr(condition)?.bool() {
+                let taken = self.eval_expr(condition)?.bool();
+                self.cover(stmt, taken);
+                if taken {
                     self.eval_stmt(then_br)?;
                 } else {
                     if let Some(else_br) = else_br {
@@ -137,7 +372,12 @@ impl Interpreter {
                 Ok(())
             }
             Stmt::While { condition, body } => {
-                while self.eval_expr(condition)?.bool() {
+                loop {
+                    let taken = self.eval_expr(condition)?.bool();
+                    self.cover(stmt, taken);
+                    if !taken {
+                        break;
+                    }
                     self.eval_stmt(body)?;
                 }
                 Ok(())
@@ -146,11 +386,10 @@ impl Interpreter {
     }
 
     fn eval_expr(&mut self, expr: &Expr) -> Result<Value, EvalError> {
+        self.budget.step(self.heap.bytes())?;
         match expr {
             Expr::Literal(v) => Ok(v.clone()),
-            Expr::Variable(var_name) => {
-                Ok(self.environment.as_ref().unwrap().get(var_name)?)
-            }
+            Expr::Variable { name, slot } => self.lookup(name, slot.get()),
             Expr::Binary { left, op, right } => {
                 let mut left = self.eval_expr(left)?;
                 let right = self.eval_expr(right)?;
@@ -162,6 +401,7 @@ impl Interpreter {
                         }
                     },
                     TokenKind::Plus => {
+                        self.heap.account(left.sum_size(&right));
                         match left.try_sum(&right) {
                             Ok(_) => Ok(left),
                             Err(msg) => Err(EvalError::new(msg, op))
@@ -231,21 +471,24 @@ impl Interpreter {
                     _ => unreachable!(),
                 }
             },
-            Expr::Assign { name, value } => {
+            Expr::Assign { name, value, slot } => {
                 let value = self.eval_expr(value)?;
-                let _ = self.environment.as_mut().unwrap().assign(name, value.clone())?;
+                self.assign(name, slot.get(), value.clone())?;
                 Ok(value)
             },
             Expr::Logical { left, op, right } => {
                 let left = self.eval_expr(left)?;
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.expr_branch(expr, left.bool() == matches!(op.kind(), TokenKind::And));
+                }
                 match op.kind() {
                     TokenKind::And => {
-                        if left.bool() == false {
+                        if !left.bool() {
                             return Ok(left)
                         }
                     },
                     TokenKind::Or => {
-                        if left.bool() == true {
+                        if left.bool() {
                             return Ok(left)
                         }
                     },
@@ -253,7 +496,454 @@ impl Interpreter {
                 }
                 Ok(self.eval_expr(right)?)
             },
+            Expr::Call { callee, paren, args } => {
+                let (callee, positional, named) = self.eval_args(callee, args)?;
+                self.call(callee, positional, named, paren)
+            },
+            Expr::Index { object, bracket, index } => {
+                let object = self.eval_expr(object)?;
+                let index = self.eval_expr(index)?;
+                match (object, index) {
+                    (Value::List(items), Value::Number(n)) => {
+                        let items = items.borrow();
+                        if n.fract() != 0.0 || n < 0.0 || n as usize >= items.len() {
+                            return Err(EvalError::new("List index out of range", bracket));
+                        }
+                        Ok(items[n as usize].clone())
+                    },
+                    (Value::List(_), _) => Err(EvalError::new("List index must be a number", bracket)),
+                    _ => Err(EvalError::new("Only lists can be indexed", bracket)),
+                }
+            },
+        }
+    }
+
+    // Evaluates the callee of a call, then its positional and named arguments
+    #[allow(clippy::type_complexity)]
+    fn eval_args(&mut self, callee: &Expr, args: &[Arg]) -> Result<(Value, Vec<Value>, Vec<(Token, Value)>), EvalError> {
+        let callee = self.eval_expr(callee)?;
+        let mut positional = Vec::new();
+        let mut named = Vec::new();
+        for arg in args {
+            let value = self.eval_expr(&arg.value)?;
+            match &arg.name {
+                Some(name) => named.push((name.clone(), value)),
+                None => positional.push(value),
+            }
+        }
+        Ok((callee, positional, named))
+    }
+
+    fn call(&mut self, callee: Value, positional: Vec<Value>, named: Vec<(Token, Value)>, paren: &Token) -> Result<Value, EvalError> {
+        match callee {
+            Value::Function(fun) => self.call_function(fun, positional, named, paren),
+            Value::Native(fun) => {
+                if let Some((name, _)) = named.first() {
+                    return Err(EvalError::new("Native functions only take positional arguments", name));
+                }
+                if positional.len() != fun.arity() {
+                    return Err(EvalError::new(&arity_messg(fun.arity(), Some(fun.arity()), positional.len()), paren));
+                }
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.enter_call(Symbol::intern(fun.name()), None);
+                }
+                let result = fun.call(self, &positional).map_err(|e| match e {
+                    NativeError::Message(messg) => EvalError::new(&messg, paren),
+                    NativeError::Eval(e) => e,
+                });
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.exit_call();
+                }
+                result
+            },
+            _ => Err(EvalError::new("Can only call functions", paren)),
+        }
+    }
+
+    fn call_function(&mut self, fun: Rc<Function>, positional: Vec<Value>, named: Vec<(Token, Value)>, paren: &Token) -> Result<Value, EvalError> {
+        if self.depth >= self.max_depth {
+            return Err(EvalError::new("Stack overflow", paren));
+        }
+        self.depth += 1;
+        let result = self.run_calls(fun, positional, named, paren);
+        self.depth -= 1;
+        result
+    }
+
+    // Runs a call, then the calls it makes in tail position, in this same Rust frame
+    fn run_calls(&mut self, mut fun: Rc<Function>, mut positional: Vec<Value>, mut named: Vec<(Token, Value)>, paren: &Token) -> Result<Value, EvalError> {
+        let mut tail_paren = None;
+        loop {
+            let paren = tail_paren.as_ref().unwrap_or(paren);
+            let env = self.bind_args(&fun, positional, named, paren)?;
+            if fun.decl.generator {
+                let gen = Generator::new(fun.decl.clone(), env);
+                return Ok(Value::Generator(self.heap.alloc(RefCell::new(gen))));
+            }
+            if let Some(debugger) = &mut self.debugger {
+                debugger.enter_call();
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.enter_call(fun.decl.name.symbol(), Some(fun.decl.name.line()));
+            }
+            let result = self.eval_block(&fun.decl.body, env);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.exit_call();
+            }
+            match result {
+                Ok(()) => return Ok(Value::Nil),
+                Err(Flow::Return(value)) => return Ok(value),
+                Err(Flow::Error(e)) => return Err(e),
+                Err(Flow::TailCall(call)) => {
+                    fun = call.fun;
+                    positional = call.positional;
+                    named = call.named;
+                    tail_paren = Some(call.paren);
+                },
+            }
+        }
+    }
+
+    // Environment of a call, with every parameter bound
+    fn bind_args(&mut self, fun: &Function, positional: Vec<Value>, named: Vec<(Token, Value)>, paren: &Token) -> Result<Rc<RefCell<Environment>>, EvalError> {
+        let params = &fun.decl.params;
+        let fixed = params.iter().filter(|p| !p.variadic).count();
+        let mut slots: Vec<Option<Value>> = vec![None; fixed];
+        let mut rest = Vec::new();
+        let got = positional.len() + named.len();
+        for (i, value) in positional.into_iter().enumerate() {
+            if i < fixed {
+                slots[i] = Some(value);
+            } else if fun.max_arity().is_none() {
+                rest.push(value);
+            } else {
+                return Err(EvalError::new(&fun.arity_messg(got), paren));
+            }
+        }
+        for (name, value) in named {
+            match params.iter().position(|p| !p.variadic && p.name.lexeme() == name.lexeme()) {
+                Some(i) if slots[i].is_some() => {
+                    return Err(EvalError::new("Argument given more than once", &name));
+                },
+                Some(i) => slots[i] = Some(value),
+                None => return Err(EvalError::new("Unknown parameter name", &name)),
+            }
+        }
+
+        // Defaults are evaluated at call time, in an environment where
+        // the previous parameters are already bound.
+        let env = self.heap.alloc(RefCell::new(Environment::with_enclosing(fun.closure.clone())));
+        let previous = self.environment.replace(env.clone());
+        let bound = self.bind_params(fun, slots, rest, got, paren);
+        self.environment = previous;
+        bound?;
+        Ok(env)
+    }
+
+    fn bind_params(&mut self, fun: &Function, slots: Vec<Option<Value>>, rest: Vec<Value>, got: usize, paren: &Token) -> Result<(), EvalError> {
+        let mut slots = slots.into_iter();
+        let mut rest = Some(rest);
+        for (i, param) in fun.decl.params.iter().enumerate() {
+            let value = if param.variadic {
+                let items = rest.take().unwrap_or_default();
+                self.heap.account(items.len() * std::mem::size_of::<Value>());
+                Value::List(self.heap.alloc(RefCell::new(items)))
+            } else {
+                match (slots.next().flatten(), &param.default) {
+                    (Some(value), _) => value,
+                    (None, Some(default)) => self.eval_expr(default)?,
+                    (None, None) => return Err(EvalError::new(&fun.arity_messg(got), paren)),
+                }
+            };
+            let env = self.environment.as_ref().unwrap();
+            env.borrow_mut().define(i, value);
+            self.name_local(env, i, &param.name);
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &Token, slot: Slot, value: Value, constant: bool) {
+        match (slot, &self.environment) {
+            (Slot::Local { index, .. }, Some(env)) => {
+                env.borrow_mut().define(index, value);
+                self.name_local(env, index, name);
+            },
+            _ => self.globals.define(name.symbol(), value, constant),
+        }
+    }
+
+    fn lookup(&self, name: &Token, slot: Slot) -> Result<Value, EvalError> {
+        match (slot, &self.environment) {
+            (Slot::Local { depth, index }, Some(env)) => Ok(env.borrow().get(depth, index)),
+            _ => self.globals.get(name.symbol()).cloned().map_err(|messg| EvalError::new(messg, name)),
+        }
+    }
+
+    fn assign(&mut self, name: &Token, slot: Slot, value: Value) -> Result<(), EvalError> {
+        match (slot, &self.environment) {
+            (Slot::Local { depth, index }, Some(env)) => {
+                env.borrow_mut().assign(depth, index, value);
+                Ok(())
+            },
+            _ => self.globals.assign(name.symbol(), value).map_err(|messg| EvalError::new(messg, name)),
+        }
+    }
+
+    // Gives the program to the debugger if it stops before `stmt`; the
+    // debugger is taken out meanwhile, so what it evaluates doesn't stop
+    fn debug(&mut self, stmt: &Stmt) -> Result<(), EvalError> {
+        let mut debugger = self.debugger.take().unwrap();
+        let quit = debugger.before(stmt, self);
+        self.debugger = Some(debugger);
+        match quit {
+            true => Err(self.budget.stop(ErrorKind::Interrupted)),
+            false => Ok(()),
+        }
+    }
+
+    // Counts which way the `if` or `while` of `stmt` went, for coverage
+    fn cover(&mut self, stmt: &Stmt, taken: bool) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.stmt_branch(stmt, taken);
+        }
+    }
+
+    // Locals only need names for the debugger to list them
+    fn name_local(&self, env: &Rc<RefCell<Environment>>, index: usize, name: &Token) {
+        if self.debugger.is_some() {
+            env.borrow_mut().name(index, name.symbol());
         }
     }
+
+    /// Function calls and generator resumptions in progress.
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Variables the running code can see, innermost scope first; the last
+    /// holds the globals but for natives.
+    pub(crate) fn scopes(&self) -> Vec<Vec<(Symbol, Value)>> {
+        let mut scopes = Vec::new();
+        let mut env = self.environment.clone();
+        while let Some(current) = env {
+            scopes.push(current.borrow().named().into_iter().map(|(_, name, value)| (name, value)).collect());
+            env = current.borrow().parent();
+        }
+        let globals = self.globals.iter().filter(|(_, value)| !matches!(value, Value::Native(_)));
+        scopes.push(globals.map(|(name, value)| (name, value.clone())).collect());
+        scopes
+    }
+
+    /// Evaluates the expression in `source` where the program is, reading
+    /// and assigning its local variables.
+    pub(crate) fn eval_here(&mut self, source: &str) -> Result<Value, Error> {
+        let source = source.trim().trim_end_matches(';');
+        let mut scanner = Scanner::new(format!("{source};"));
+        let mut parser = Parser::new(scanner.tokens().to_vec());
+        let mut stmts = parser.stmts();
+        let mut errors = scanner.errors().to_vec();
+        errors.extend_from_slice(parser.errors());
+        let expr = match stmts.pop() {
+            Some(Stmt::Expr(expr)) if errors.is_empty() && stmts.is_empty() => expr,
+            _ if !errors.is_empty() => return Err(Error::Syntax(errors)),
+            _ => return Err(Error::Syntax(vec![ParseError::at("Expected an expression", 1, 1)])),
+        };
+
+        let mut resolver = Resolver::new();
+        let mut chain = Vec::new();
+        let mut env = self.environment.clone();
+        while let Some(current) = env {
+            chain.push(current.borrow().named().into_iter().map(|(index, name, _)| (index, name)).collect::<Vec<_>>());
+            env = current.borrow().parent();
+        }
+        for names in chain.iter().rev() {
+            resolver.enter(names);
+        }
+        let stmt = Stmt::Expr(expr);
+        if !resolver.resolve_stmts(std::slice::from_ref(&stmt)) {
+            return Err(Error::Syntax(resolver.errors().to_vec()));
+        }
+        let Stmt::Expr(expr) = stmt else { unreachable!() };
+        Ok(self.eval_expr(&expr)?)
+    }
+
+    fn iter(&self, value: Value, token: &Token) -> Result<Iter, EvalError> {
+        match value {
+            Value::List(items) => Ok(Iter::List { items, next: 0 }),
+            Value::Generator(gen) => Ok(Iter::Generator(gen)),
+            _ => Err(EvalError::new("Can only iterate over lists and generators", token)),
+        }
+    }
+
+    fn next_item(&mut self, iter: &mut Iter) -> Result<Option<Value>, EvalError> {
+        match iter {
+            Iter::List { items, next } => {
+                let item = items.borrow().get(*next).cloned();
+                *next += 1;
+                Ok(item)
+            },
+            Iter::Generator(gen) => self.resume_generator(gen),
+        }
+    }
+
+    fn resume_generator(&mut self, gen: &Rc<RefCell<Generator>>) -> Result<Option<Value>, EvalError> {
+        if self.depth >= self.max_depth {
+            return Err(EvalError::new("Stack overflow", &gen.borrow().token));
+        }
+        let (decl, env, mut frames) = {
+            let mut gen = gen.borrow_mut();
+            match std::mem::replace(&mut gen.state, GeneratorState::Running) {
+                GeneratorState::Suspended(GeneratorBody::Tree { decl, env, frames }) => (decl, env, frames),
+                GeneratorState::Suspended(GeneratorBody::Vm(_)) => unreachable!("generator created by another backend"),
+                GeneratorState::Done => {
+                    gen.state = GeneratorState::Done;
+                    return Ok(None);
+                },
+                GeneratorState::Running => {
+                    return Err(EvalError::new("Generator is already running", &gen.token));
+                },
+            }
+        };
+        let previous = self.environment.replace(env);
+        self.depth += 1;
+        if let Some(debugger) = &mut self.debugger {
+            debugger.enter_call();
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_call(decl.name.symbol(), Some(decl.name.line()));
+        }
+        let result = self.run_frames(&decl.body, &mut frames);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_call();
+        }
+        self.depth -= 1;
+        let env = std::mem::replace(&mut self.environment, previous).unwrap();
+
+        gen.borrow_mut().state = match result {
+            Ok(Some(_)) => GeneratorState::Suspended(GeneratorBody::Tree { decl, env, frames }),
+            _ => GeneratorState::Done,
+        };
+        result
+    }
+
+    fn run_frames(&mut self, body: &[Stmt], frames: &mut Vec<Frame>) -> Result<Option<Value>, EvalError> {
+        while let Some(frame) = frames.pop() {
+            self.budget.step(self.heap.bytes())?;
+            match frame {
+                Frame::List { path, next } => {
+                    let stmts = match path.is_empty() {
+                        true => body,
+                        false => match stmt_at(body, &path) {
+                            Stmt::Block { stmts, .. } => stmts,
+                            _ => unreachable!(),
+                        },
+                    };
+                    if next < stmts.len() {
+                        let child_path = child(&path, next);
+                        frames.push(Frame::List { path, next: next + 1 });
+                        frames.push(Frame::Stmt { path: child_path });
+                    }
+                },
+                Frame::Restore { previous } => {
+                    self.environment = previous;
+                },
+                Frame::While { path } => {
+                    let stmt = stmt_at(body, &path);
+                    let Stmt::While { condition, .. } = stmt else { unreachable!() };
+                    let taken = self.eval_expr(condition)?.bool();
+                    self.cover(stmt, taken);
+                    if taken {
+                        let child_path = child(&path, 0);
+                        frames.push(Frame::While { path });
+                        frames.push(Frame::Stmt { path: child_path });
+                    }
+                },
+                Frame::ForIn { path, mut iter } => {
+                    if let Some(item) = self.next_item(&mut iter)? {
+                        let mut env = Environment::with_enclosing(self.environment.clone());
+                        env.define(0, item);
+                        let env = self.heap.alloc(RefCell::new(env));
+                        if let Stmt::ForIn { name, .. } = stmt_at(body, &path) {
+                            self.name_local(&env, 0, name);
+                        }
+                        let previous = self.environment.replace(env);
+                        let child_path = child(&path, 0);
+                        frames.push(Frame::ForIn { path, iter });
+                        frames.push(Frame::Restore { previous });
+                        frames.push(Frame::Stmt { path: child_path });
+                    }
+                },
+                Frame::Stmt { path } => {
+                    let stmt = stmt_at(body, &path);
+                    // Those not run by `eval_stmt`, which checks the rest
+                    let own = matches!(
+                        stmt,
+                        Stmt::Block { .. } | Stmt::If { .. } | Stmt::While { .. } | Stmt::ForIn { .. }
+                            | Stmt::Yield { .. } | Stmt::Return { .. }
+                    );
+                    if own && self.debugger.is_some() {
+                        self.debug(stmt)?;
+                    }
+                    if let (true, Some(coverage)) = (own, &mut self.coverage) {
+                        coverage.run(stmt);
+                    }
+                    match stmt {
+                        Stmt::Block { .. } => {
+                            let env = Environment::with_enclosing(self.environment.clone());
+                            let env = self.heap.alloc(RefCell::new(env));
+                            let previous = self.environment.replace(env);
+                            frames.push(Frame::Restore { previous });
+                            frames.push(Frame::List { path, next: 0 });
+                        },
+                        Stmt::If { condition, else_br, .. } => {
+                            let taken = self.eval_expr(condition)?.bool();
+                            self.cover(stmt, taken);
+                            if taken {
+                                frames.push(Frame::Stmt { path: child(&path, 0) });
+                            } else if else_br.is_some() {
+                                frames.push(Frame::Stmt { path: child(&path, 1) });
+                            }
+                        },
+                        Stmt::While { .. } => {
+                            frames.push(Frame::While { path });
+                        },
+                        Stmt::ForIn { name, iterable, .. } => {
+                            let value = self.eval_expr(iterable)?;
+                            let iter = self.iter(value, name)?;
+                            frames.push(Frame::ForIn { path, iter });
+                        },
+                        Stmt::Yield { value, .. } => {
+                            let value = match value {
+                                Some(expr) => self.eval_expr(expr)?,
+                                None => Value::Nil,
+                            };
+                            return Ok(Some(value));
+                        },
+                        Stmt::Return { value, .. } => {
+                            if let Some(expr) = value {
+                                self.eval_expr(expr)?;
+                            }
+                            return Ok(None);
+                        },
+                        stmt => match self.eval_stmt(stmt) {
+                            Ok(()) => {},
+                            Err(Flow::Error(e)) => return Err(e),
+                            Err(Flow::Return(_) | Flow::TailCall(_)) => unreachable!(),
+                        },
+                    }
+                },
+            }
+        }
+        Ok(None)
+    }
 }
 
+impl Runtime for Interpreter {
+    fn resume(&mut self, gen: &Rc<RefCell<Generator>>) -> Result<Option<Value>, EvalError> {
+        self.resume_generator(gen)
+    }
+
+    fn collect(&mut self) -> usize {
+        self.heap.collect()
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Stops the evaluations of one backend from any thread. Clones share the
+/// same flag.
+#[derive(Clone, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    /// Makes the running evaluation stop with an "Interrupted" error, as
+    /// soon as its backend checks (at least every few thousand steps). Safe
+    /// to call from a signal handler.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Forgets an interrupt no evaluation has noticed yet.
+    pub fn clear(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub(crate) fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+// Interrupted by the signal handler
+static ON_SIGINT: OnceLock<Interrupt> = OnceLock::new();
+
+/// Interrupts evaluations through `interrupt` on Ctrl-C instead of killing
+/// the process. Only the first call installs a handler, and it does nothing
+/// on platforms other than Unix.
+pub fn install_handler(interrupt: Interrupt) {
+    #[cfg(unix)]
+    {
+        use std::os::raw::c_int;
+
+        const SIGINT: c_int = 2;
+        extern "C" {
+            fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        }
+        extern "C" fn on_sigint(_: c_int) {
+            if let Some(interrupt) = ON_SIGINT.get() {
+                interrupt.interrupt();
+            }
+        }
+        if ON_SIGINT.set(interrupt).is_err() {
+            return;
+        }
+        // SAFETY: the handler only loads the initialized cell and stores to
+        // an atomic, which are async-signal-safe
+        unsafe {
+            signal(SIGINT, on_sigint);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = interrupt;
+}
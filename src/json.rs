@@ -0,0 +1,499 @@
+//! Minimal JSON values, written for tools reading what rlux dumps and read
+//! from the editors talking to `rlux lsp` and `rlux dap`.
+
+use std::fmt::{self, Display};
+use std::io::{self, BufRead, Write};
+
+use crate::error::compiletime::ParseError;
+use crate::expr::{Arg, Expr, FunctionDecl, Param, Program, Stmt};
+use crate::token::Token;
+use crate::types::Type;
+use crate::value::Value;
+
+/// A JSON value. Object keys keep their insertion order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut reader = Reader { chars: text.chars().collect(), pos: 0 };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        match reader.peek() {
+            None => Ok(value),
+            Some(c) => Err(format!("Unexpected '{c}' after the value")),
+        }
+    }
+
+    /// The field `key` of an object; `None` for anything else.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        // `{:#}` puts every item on its own line
+        let pretty = f.alternate();
+        let newline = |f: &mut fmt::Formatter<'_>, indent: usize| {
+            if pretty {
+                write!(f, "\n{:1$}", "", indent * 2)
+            } else {
+                Ok(())
+            }
+        };
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) if n.is_finite() => write!(f, "{n}"),
+            // JSON has no infinities nor NaN
+            Json::Number(n) => write_string(f, &n.to_string()),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) if items.is_empty() => write!(f, "[]"),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, indent + 1)?;
+                    item.write(f, indent + 1)?;
+                }
+                newline(f, indent)?;
+                write!(f, "]")
+            },
+            Json::Object(fields) if fields.is_empty() => write!(f, "{{}}"),
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, indent + 1)?;
+                    write_string(f, key)?;
+                    write!(f, "{}", if pretty { ": " } else { ":" })?;
+                    value.write(f, indent + 1)?;
+                }
+                newline(f, indent)?;
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.peek().ok_or("Unexpected end of JSON")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        for expected in word.chars() {
+            if self.next()? != expected {
+                return Err(format!("Expected '{word}'"));
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek().ok_or("Unexpected end of JSON")? {
+            'n' => self.expect("null").map(|_| Json::Null),
+            't' => self.expect("true").map(|_| Json::Bool(true)),
+            'f' => self.expect("false").map(|_| Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        ']' => return Ok(Json::Array(items)),
+                        c => return Err(format!("Expected ',' or ']' but got '{c}'")),
+                    }
+                }
+            },
+            '{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        return Err("Expected a string key".to_string());
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.next()? != ':' {
+                        return Err("Expected ':' after key".to_string());
+                    }
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        '}' => return Ok(Json::Object(fields)),
+                        c => return Err(format!("Expected ',' or '}}' but got '{c}'")),
+                    }
+                }
+            },
+            c if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number.parse().map(Json::Number).map_err(|_| format!("Invalid number '{number}'"))
+            },
+            c => Err(format!("Unexpected '{c}'")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => match self.next()? {
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => {
+                        let mut code = self.hex()?;
+                        // A surrogate pair, for characters beyond the first plane
+                        if (0xD800..0xDC00).contains(&code) && self.chars.get(self.pos..self.pos + 2) == Some(&['\\', 'u']) {
+                            self.pos += 2;
+                            let low = self.hex()?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                        }
+                        s.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    },
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.next()?.to_digit(16).ok_or("Invalid \\u escape")?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+}
+
+/// The body of the next message framed by a `Content-Length` header, as
+/// LSP and DAP clients send them; `None` at the end of the input.
+pub(crate) fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Message without a Content-Length"));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Sends `message` framed like `read_message` expects it.
+pub(crate) fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+/// A token's lexeme and where it starts.
+impl From<&Token> for Json {
+    fn from(token: &Token) -> Self {
+        Json::object([
+            ("lexeme", token.lexeme().into()),
+            ("line", token.line().into()),
+            ("column", token.column().into()),
+        ])
+    }
+}
+
+impl From<&Value> for Json {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Number(n) => Json::Number(*n),
+            Value::String(s) => Json::String(s.as_str().to_string()),
+            Value::Bool(b) => Json::Bool(*b),
+            Value::Nil => Json::Null,
+            other => Json::String(other.to_string()),
+        }
+    }
+}
+
+impl From<Type> for Json {
+    fn from(ty: Type) -> Self {
+        Json::String(ty.to_string())
+    }
+}
+
+/// What `Scanner::scanned` returns, with errors as tokens of kind `ERROR`.
+pub fn tokens(scanned: &[Result<&Token, &ParseError>]) -> Json {
+    Json::Array(scanned.iter().map(|item| match item {
+        Ok(token) => Json::object([
+            ("kind", token.kind().name().into()),
+            ("lexeme", token.lexeme().into()),
+            ("literal", token.literal().into()),
+            ("line", token.line().into()),
+            ("column", token.column().into()),
+        ]),
+        Err(e) => Json::object([
+            ("kind", "ERROR".into()),
+            ("message", e.message().into()),
+            ("line", e.line().into()),
+            ("column", e.column().into()),
+        ]),
+    }).collect())
+}
+
+fn node<const N: usize>(kind: &str, fields: [(&str, Json); N]) -> Json {
+    let mut object = vec![("kind".to_string(), kind.into())];
+    object.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value)));
+    Json::Object(object)
+}
+
+fn stmts(stmts: &[Stmt]) -> Json {
+    Json::Array(stmts.iter().map(Json::from).collect())
+}
+
+/// The syntax tree of a program, one object per node with its `kind` and
+/// the tokens it holds.
+pub fn ast(program: &Program) -> Json {
+    stmts(&program.stmts)
+}
+
+impl From<&Stmt> for Json {
+    fn from(stmt: &Stmt) -> Self {
+        match stmt {
+            Stmt::Print { value, .. } => node("Print", [("expr", value.into())]),
+            Stmt::Expr(e) => node("Expr", [("expr", e.into())]),
+            Stmt::Var { name, ty, initializer, constant, .. } => node("Var", [
+                ("name", name.into()),
+                ("type", (*ty).into()),
+                ("constant", Json::Bool(*constant)),
+                ("initializer", initializer.as_ref().into()),
+            ]),
+            Stmt::If { condition, then_br, else_br } => node("If", [
+                ("condition", condition.into()),
+                ("then", then_br.as_ref().into()),
+                ("else", else_br.as_deref().into()),
+            ]),
+            Stmt::While { condition, body } => node("While", [
+                ("condition", condition.into()),
+                ("body", body.as_ref().into()),
+            ]),
+            Stmt::Block { brace, stmts: body } => node("Block", [
+                ("brace", brace.as_ref().into()),
+                ("body", stmts(body)),
+            ]),
+            Stmt::Function(decl) => decl.as_ref().into(),
+            Stmt::Return { keyword, value } => node("Return", [
+                ("keyword", keyword.into()),
+                ("value", value.as_ref().into()),
+            ]),
+            Stmt::Yield { keyword, value } => node("Yield", [
+                ("keyword", keyword.into()),
+                ("value", value.as_ref().into()),
+            ]),
+            Stmt::ForIn { name, iterable, body } => node("ForIn", [
+                ("name", name.into()),
+                ("iterable", iterable.into()),
+                ("body", body.as_ref().into()),
+            ]),
+        }
+    }
+}
+
+impl From<&FunctionDecl> for Json {
+    fn from(decl: &FunctionDecl) -> Self {
+        node("Function", [
+            ("name", (&decl.name).into()),
+            ("params", Json::Array(decl.params.iter().map(Json::from).collect())),
+            ("returnType", decl.return_ty.into()),
+            ("generator", Json::Bool(decl.generator)),
+            ("body", stmts(&decl.body)),
+        ])
+    }
+}
+
+impl From<&Param> for Json {
+    fn from(param: &Param) -> Self {
+        node("Param", [
+            ("name", (&param.name).into()),
+            ("type", param.ty.into()),
+            ("default", param.default.as_ref().into()),
+            ("variadic", Json::Bool(param.variadic)),
+        ])
+    }
+}
+
+impl From<&Arg> for Json {
+    fn from(arg: &Arg) -> Self {
+        node("Arg", [
+            ("name", arg.name.as_ref().into()),
+            ("value", (&arg.value).into()),
+        ])
+    }
+}
+
+impl From<&Expr> for Json {
+    fn from(expr: &Expr) -> Self {
+        match expr {
+            Expr::Assign { name, value, .. } => node("Assign", [
+                ("name", name.into()),
+                ("value", value.as_ref().into()),
+            ]),
+            Expr::Binary { left, op, right } => node("Binary", [
+                ("op", op.into()),
+                ("left", left.as_ref().into()),
+                ("right", right.as_ref().into()),
+            ]),
+            Expr::Logical { left, op, right } => node("Logical", [
+                ("op", op.into()),
+                ("left", left.as_ref().into()),
+                ("right", right.as_ref().into()),
+            ]),
+            Expr::Call { callee, paren, args } => node("Call", [
+                ("callee", callee.as_ref().into()),
+                ("paren", paren.into()),
+                ("args", Json::Array(args.iter().map(Json::from).collect())),
+            ]),
+            Expr::Index { object, bracket, index } => node("Index", [
+                ("object", object.as_ref().into()),
+                ("bracket", bracket.into()),
+                ("index", index.as_ref().into()),
+            ]),
+            Expr::Grouping(e) => node("Grouping", [("expr", e.as_ref().into())]),
+            Expr::Literal(value) => node("Literal", [("value", value.into())]),
+            Expr::Variable { name, .. } => node("Variable", [("name", name.into())]),
+            Expr::Unary { op, right } => node("Unary", [
+                ("op", op.into()),
+                ("right", right.as_ref().into()),
+            ]),
+        }
+    }
+}
@@ -2,7 +2,40 @@ pub mod scanner;
 mod error;
 mod expr;
 mod value;
+mod string;
 mod token;
+mod symbol;
+mod types;
+mod environment;
+mod function;
+mod generator;
+mod gc;
+pub mod budget;
+pub mod interrupt;
+mod chunk;
+mod compiler;
 pub mod parser;
 pub mod interpreter;
+pub mod debugger;
+pub mod profiler;
+pub mod coverage;
+pub mod vm;
+pub mod checker;
+pub mod resolver;
+pub mod optimizer;
+pub mod json;
+pub mod formatter;
+pub mod linter;
+pub mod lsp;
+pub mod dap;
+mod lux;
+mod convert;
 
+pub use function::DEFAULT_MAX_CALL_DEPTH;
+pub use error::compiletime::ParseError;
+pub use expr::Program;
+pub use error::runtime::{ErrorKind, EvalError};
+pub use convert::{Fallible, FromValue, HostFn, IntoValue};
+pub use lux::{Error, Lux};
+pub use string::Str;
+pub use value::Value;
@@ -0,0 +1,290 @@
+use std::fmt::{self, Display};
+
+use crate::error::compiletime::Lint;
+use crate::expr::{Expr, Program, Stmt};
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+
+/// A check of the `Linter`, which can be turned off on its own.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rule {
+    /// Local variables and functions never read.
+    UnusedVariable,
+    /// Declarations hiding one of an enclosing scope.
+    Shadowing,
+    /// Statements following a `return` in the same block.
+    UnreachableCode,
+    /// `if (a = b)` and `while (a = b)`; `if ((a = b))` is taken as meant.
+    AssignInCondition,
+    /// `x == x` and the like, always true or always false.
+    SelfComparison,
+    /// `{}`, apart from function bodies.
+    EmptyBlock,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedVariable,
+        Rule::Shadowing,
+        Rule::UnreachableCode,
+        Rule::AssignInCondition,
+        Rule::SelfComparison,
+        Rule::EmptyBlock,
+    ];
+
+    /// Name of the rule on the command line and in lints.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::Shadowing => "shadowing",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::AssignInCondition => "assign-in-condition",
+            Rule::SelfComparison => "self-comparison",
+            Rule::EmptyBlock => "empty-block",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Finds code that is valid but likely wrong. Names starting with `_` are
+/// never reported as unused, nor are globals and parameters.
+pub struct Linter {
+    enabled: Vec<Rule>,
+    // The first scope holds the globals
+    scopes: Vec<Vec<Local>>,
+    lints: Vec<Lint>,
+}
+
+struct Local {
+    name: Token,
+    used: bool,
+    function: bool,
+    // Parameters don't have to be used
+    param: bool,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linter {
+    /// A linter with every rule enabled.
+    pub fn new() -> Linter {
+        Linter {
+            enabled: Rule::ALL.to_vec(),
+            scopes: vec![Vec::new()],
+            lints: Vec::new(),
+        }
+    }
+
+    pub fn enable(&mut self, rule: Rule) {
+        if !self.enabled.contains(&rule) {
+            self.enabled.push(rule);
+        }
+    }
+
+    pub fn disable(&mut self, rule: Rule) {
+        self.enabled.retain(|enabled| *enabled != rule);
+    }
+
+    /// Returns whether the program is free of lints; see `lints` otherwise.
+    /// The lints of a previous program are dropped.
+    pub fn lint(&mut self, program: &Program) -> bool {
+        self.lints.clear();
+        self.scopes = vec![Vec::new()];
+        self.stmts(&program.stmts);
+        self.lints.sort_by_key(|lint| (lint.token().line(), lint.token().column()));
+        self.lints.is_empty()
+    }
+
+    /// What was found, in source order.
+    pub fn lints(&self) -> &[Lint] {
+        &self.lints
+    }
+
+    fn report(&mut self, rule: Rule, messg: &str, token: &Token) {
+        if self.enabled.contains(&rule) {
+            self.lints.push(Lint::new(rule, messg, token));
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.stmt(stmt);
+            // At the first dead statement, or at the `return` when that one
+            // holds no token
+            if let (Stmt::Return { keyword, .. }, Some(dead)) = (stmt, stmts.get(i + 1)) {
+                let token = dead.token().unwrap_or(keyword).clone();
+                self.report(Rule::UnreachableCode, "Code after 'return' is never run", &token);
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) | Stmt::Print { value: e, .. } => self.expr(e),
+            Stmt::Var { name, initializer, .. } => {
+                if let Some(init) = initializer {
+                    self.expr(init);
+                }
+                self.declare(name, false, false);
+            },
+            Stmt::Block { brace, stmts } => {
+                if let (Some(brace), true) = (brace, stmts.is_empty()) {
+                    self.report(Rule::EmptyBlock, "Empty block", brace);
+                }
+                self.scopes.push(Vec::new());
+                self.stmts(stmts);
+                self.end_scope();
+            },
+            Stmt::If { condition, then_br, else_br } => {
+                self.condition(condition);
+                self.stmt(then_br);
+                if let Some(else_br) = else_br {
+                    self.stmt(else_br);
+                }
+            },
+            Stmt::While { condition, body } => {
+                self.condition(condition);
+                self.stmt(body);
+            },
+            Stmt::Function(decl) => {
+                self.declare(&decl.name, true, false);
+                self.scopes.push(Vec::new());
+                for param in &decl.params {
+                    if let Some(default) = &param.default {
+                        self.expr(default);
+                    }
+                    self.declare(&param.name, false, true);
+                }
+                self.stmts(&decl.body);
+                self.end_scope();
+            },
+            Stmt::Return { value, .. } | Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            },
+            Stmt::ForIn { name, iterable, body } => {
+                self.expr(iterable);
+                self.scopes.push(Vec::new());
+                self.declare(name, false, false);
+                self.stmt(body);
+                self.end_scope();
+            },
+        }
+    }
+
+    fn condition(&mut self, condition: &Expr) {
+        if let Expr::Assign { name, .. } = condition {
+            self.report(
+                Rule::AssignInCondition,
+                "Assignment used as a condition; wrap it in parentheses if it is meant",
+                name,
+            );
+        }
+        self.expr(condition);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) => {},
+            Expr::Variable { name, .. } => self.use_var(name.symbol()),
+            Expr::Grouping(e) => self.expr(e),
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Binary { left, op, right } => {
+                let comparison = matches!(
+                    op.kind(),
+                    TokenKind::EqualEqual | TokenKind::BangEqual | TokenKind::Less
+                        | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual
+                );
+                if comparison && pure(left) && !constant(left) && left.to_string() == right.to_string() {
+                    self.report(Rule::SelfComparison, "Comparing an expression with itself", op);
+                }
+                self.expr(left);
+                self.expr(right);
+            },
+            Expr::Logical { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            },
+            // Writing a variable doesn't use it
+            Expr::Assign { value, .. } => self.expr(value),
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(&arg.value);
+                }
+            },
+            Expr::Index { object, index, .. } => {
+                self.expr(object);
+                self.expr(index);
+            },
+        }
+    }
+
+    fn declare(&mut self, name: &Token, function: bool, param: bool) {
+        let symbol = name.symbol();
+        let (current, outer) = self.scopes.split_last_mut().unwrap();
+        // Redeclaring in the same scope replaces the variable
+        if let Some(i) = current.iter().position(|local| local.name.symbol() == symbol) {
+            current.remove(i);
+        } else if let Some(hidden) = outer.iter().rev().flatten().find(|local| local.name.symbol() == symbol) {
+            let messg = format!("'{symbol}' shadows the declaration on line {}", hidden.name.line());
+            self.report(Rule::Shadowing, &messg, name);
+        }
+        let current = self.scopes.last_mut().unwrap();
+        current.push(Local { name: name.clone(), used: false, function, param });
+    }
+
+    fn use_var(&mut self, symbol: Symbol) {
+        let local = self.scopes.iter_mut().rev().flatten().find(|local| local.name.symbol() == symbol);
+        if let Some(local) = local {
+            local.used = true;
+        }
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for local in scope {
+            if local.used || local.param || local.name.lexeme().starts_with('_') {
+                continue;
+            }
+            let what = if local.function { "function" } else { "variable" };
+            self.report(Rule::UnusedVariable, &format!("Unused {what} '{}'", local.name.lexeme()), &local.name);
+        }
+    }
+}
+
+// Doesn't read any variable; comparing constants is meant to be constant
+fn constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::Grouping(e) | Expr::Unary { right: e, .. } => constant(e),
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => constant(left) && constant(right),
+        _ => false,
+    }
+}
+
+// Evaluates to the same value every time, so comparing it with itself is
+// always true or always false
+fn pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) | Expr::Variable { .. } => true,
+        Expr::Grouping(e) | Expr::Unary { right: e, .. } => pure(e),
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => pure(left) && pure(right),
+        Expr::Index { object, index, .. } => pure(object) && pure(index),
+        Expr::Assign { .. } | Expr::Call { .. } => false,
+    }
+}
@@ -0,0 +1,457 @@
+//! Language server for editors, speaking the JSON-RPC of the Language Server
+//! Protocol over any reader and writer (stdin and stdout for `rlux lsp`).
+//!
+//! Documents are synced whole on every change. Columns count characters,
+//! which are LSP's UTF-16 units for anything but astral characters.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::error::compiletime::ParseError;
+use crate::expr::{Expr, Stmt};
+use crate::function::natives;
+use crate::json::{read_message, write_message, Json};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+
+// JSON-RPC error codes
+const PARSE_ERROR: f64 = -32700.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const INVALID_PARAMS: f64 = -32602.0;
+
+// LSP `CompletionItemKind`s
+const FUNCTION_ITEM: f64 = 3.0;
+const VARIABLE_ITEM: f64 = 6.0;
+const KEYWORD_ITEM: f64 = 14.0;
+
+const KEYWORDS: [&str; 19] = [
+    "and", "class", "const", "else", "false", "for", "fun", "if", "in", "nil", "or", "print", "return", "super",
+    "this", "true", "var", "while", "yield",
+];
+
+/// Answers the requests read from `input` on `output`, until the client
+/// sends `exit` or closes `input`.
+pub fn serve<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<()> {
+    let mut server = Server { output, documents: HashMap::new() };
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                server.send(error_response(Json::Null, PARSE_ERROR, &e))?;
+                continue;
+            },
+        };
+        let method = message.get("method").and_then(Json::as_str).unwrap_or_default();
+        let params = message.get("params").unwrap_or(&Json::Null);
+        match (method, message.get("id")) {
+            ("exit", _) => break,
+            (method, Some(id)) => {
+                let response = match server.request(method, params) {
+                    Ok(result) => Json::object([("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]),
+                    Err((code, messg)) => error_response(id.clone(), code, &messg),
+                };
+                server.send(response)?;
+            },
+            (method, None) => server.notification(method, params)?,
+        }
+    }
+    Ok(())
+}
+
+fn error_response(id: Json, code: f64, messg: &str) -> Json {
+    let error = Json::object([("code", Json::Number(code)), ("message", messg.into())]);
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), ("error", error)])
+}
+
+struct Server<W> {
+    output: W,
+    documents: HashMap<String, Document>,
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        write_message(&mut self.output, &message)
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (f64, String)> {
+        match method {
+            "initialize" => Ok(Json::object([
+                ("capabilities", Json::object([
+                    // Full syncs
+                    ("textDocumentSync", Json::Number(1.0)),
+                    ("hoverProvider", Json::Bool(true)),
+                    ("definitionProvider", Json::Bool(true)),
+                    ("completionProvider", Json::object([])),
+                ])),
+                ("serverInfo", Json::object([("name", "rlux".into())])),
+            ])),
+            "shutdown" => Ok(Json::Null),
+            "textDocument/hover" | "textDocument/definition" | "textDocument/completion" => {
+                let (uri, pos) = position(params).ok_or((INVALID_PARAMS, "Expected a text document position".to_string()))?;
+                let Some(document) = self.documents.get(uri) else {
+                    return Ok(Json::Null);
+                };
+                Ok(match method {
+                    "textDocument/hover" => document.hover(pos),
+                    "textDocument/definition" => document.definition(uri, pos),
+                    _ => document.completion(pos),
+                })
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{method}'"))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let Some(uri) = params.get("textDocument").and_then(|doc| doc.get("uri")).and_then(Json::as_str) else {
+            return Ok(());
+        };
+        let text = match method {
+            "textDocument/didOpen" => params.get("textDocument").and_then(|doc| doc.get("text")),
+            "textDocument/didChange" => {
+                params.get("contentChanges").and_then(Json::as_array).and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return self.publish(uri, Vec::new());
+            },
+            _ => return Ok(()),
+        };
+        let Some(text) = text.and_then(Json::as_str) else {
+            return Ok(());
+        };
+        let document = Document::new(text);
+        let diagnostics = document.errors.iter().map(diagnostic).collect();
+        self.documents.insert(uri.to_string(), document);
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Json>) -> io::Result<()> {
+        let params = Json::object([("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))]);
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            ("params", params),
+        ]))
+    }
+}
+
+// Line and column, both from 1 like those of tokens
+type Pos = (usize, usize);
+
+// The document and position of a request, from LSP's positions counted from 0
+fn position(params: &Json) -> Option<(&str, Pos)> {
+    let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+    let pos = params.get("position")?;
+    let line = pos.get("line")?.as_f64()? as usize;
+    let character = pos.get("character")?.as_f64()? as usize;
+    Some((uri, (line.checked_add(1)?, character.checked_add(1)?)))
+}
+
+fn range(start: Pos, len: usize) -> Json {
+    let pos = |(line, column): Pos| Json::object([
+        ("line", (line.saturating_sub(1)).into()),
+        ("character", (column.saturating_sub(1)).into()),
+    ]);
+    Json::object([("start", pos(start)), ("end", pos((start.0, start.1 + len)))])
+}
+
+fn pos(token: &Token) -> Pos {
+    (token.line(), token.column())
+}
+
+// Characters the token takes in the source
+fn len(token: &Token) -> usize {
+    match token.kind() {
+        TokenKind::String => token.lexeme().chars().count() + 2,
+        _ => token.lexeme().chars().count(),
+    }
+}
+
+fn token_range(token: &Token) -> Json {
+    range(pos(token), len(token))
+}
+
+fn diagnostic(e: &ParseError) -> Json {
+    let range = match e.token() {
+        Some(token) => range(pos(token), len(token).max(1)),
+        None => range((e.line(), e.column()), 1),
+    };
+    Json::object([
+        ("range", range),
+        // Error
+        ("severity", Json::Number(1.0)),
+        ("source", "rlux".into()),
+        ("message", e.message().into()),
+    ])
+}
+
+fn contains(token: &Token, (line, column): Pos) -> bool {
+    token.line() == line && token.column() <= column && column < token.column() + len(token)
+}
+
+struct Document {
+    errors: Vec<ParseError>,
+    index: Index,
+}
+
+impl Document {
+    fn new(text: &str) -> Document {
+        let mut scanner = Scanner::new(text.to_string());
+        let tokens = scanner.tokens().to_vec();
+        let mut parser = Parser::new(tokens.clone());
+        let stmts = parser.stmts();
+        let mut errors = scanner.errors().to_vec();
+        errors.extend_from_slice(parser.errors());
+        // Only a tree that parsed is worth resolving
+        if errors.is_empty() {
+            let mut resolver = Resolver::new();
+            resolver.resolve_stmts(&stmts);
+            errors.extend_from_slice(resolver.errors());
+        }
+        Document { errors, index: Index::new(&stmts, &tokens) }
+    }
+
+    fn hover(&self, at: Pos) -> Json {
+        let (token, decl) = match self.index.at(at) {
+            Some((token, Some(decl))) => (token, decl.detail.clone()),
+            Some((token, None)) => match natives().iter().find(|native| native.name() == token.lexeme()) {
+                Some(native) => {
+                    let arity = native.arity();
+                    let s = if arity == 1 { "" } else { "s" };
+                    (token, format!("native function {}, taking {arity} argument{s}", native.name()))
+                },
+                None => return Json::Null,
+            },
+            None => return Json::Null,
+        };
+        let contents = Json::object([("kind", "markdown".into()), ("value", format!("```lux\n{decl}\n```").as_str().into())]);
+        Json::object([("contents", contents), ("range", token_range(token))])
+    }
+
+    fn definition(&self, uri: &str, at: Pos) -> Json {
+        match self.index.at(at) {
+            Some((_, Some(decl))) => Json::object([("uri", uri.into()), ("range", token_range(&decl.name))]),
+            _ => Json::Null,
+        }
+    }
+
+    // What's declared at `at`, innermost first, then natives and keywords
+    fn completion(&self, at: Pos) -> Json {
+        let mut items = Vec::new();
+        let mut seen = Vec::new();
+        let visible = self.index.decls.iter().rev().filter(|decl| pos(&decl.name) < at && at <= decl.scope_end);
+        for decl in visible {
+            let name = decl.name.lexeme();
+            if seen.contains(&name) {
+                continue;
+            }
+            seen.push(name);
+            let kind = if decl.function { FUNCTION_ITEM } else { VARIABLE_ITEM };
+            items.push(item(name, kind, &decl.detail));
+        }
+        for native in natives() {
+            if !seen.contains(&native.name()) {
+                items.push(item(native.name(), FUNCTION_ITEM, "native function"));
+            }
+        }
+        items.extend(KEYWORDS.iter().map(|keyword| item(keyword, KEYWORD_ITEM, "keyword")));
+        Json::Array(items)
+    }
+}
+
+fn item(label: &str, kind: f64, detail: &str) -> Json {
+    Json::object([("label", label.into()), ("kind", Json::Number(kind)), ("detail", detail.into())])
+}
+
+struct Decl {
+    name: Token,
+    // Shown on hover, e.g. `var x: number`
+    detail: String,
+    function: bool,
+    // Where the scope declaring it ends
+    scope_end: Pos,
+}
+
+/// Every declaration of a document, and what each variable refers to.
+struct Index {
+    decls: Vec<Decl>,
+    // Variables read or assigned, with the declaration they resolve to
+    refs: Vec<(Token, Option<usize>)>,
+}
+
+impl Index {
+    fn new(stmts: &[Stmt], tokens: &[Token]) -> Index {
+        let mut braces = HashMap::new();
+        let mut open = Vec::new();
+        for token in tokens {
+            match token.kind() {
+                TokenKind::LeftBrace => open.push(pos(token)),
+                TokenKind::RightBrace => {
+                    if let Some(start) = open.pop() {
+                        braces.insert(start, pos(token));
+                    }
+                },
+                _ => {},
+            }
+        }
+        let end = tokens.last().map_or((usize::MAX, 0), pos);
+        let mut indexer = Indexer {
+            braces,
+            tokens,
+            scopes: vec![(Vec::new(), end)],
+            index: Index { decls: Vec::new(), refs: Vec::new() },
+        };
+        indexer.stmts(stmts);
+        // Globals can be used before their declaration, e.g. in functions
+        let mut index = indexer.index;
+        for (token, decl) in index.refs.iter_mut().filter(|(_, decl)| decl.is_none()) {
+            *decl = index.decls.iter().rposition(|d| d.name.lexeme() == token.lexeme() && d.scope_end == end);
+        }
+        index
+    }
+
+    // The identifier at `at`, and the declaration it is or refers to
+    fn at(&self, at: Pos) -> Option<(&Token, Option<&Decl>)> {
+        if let Some(decl) = self.decls.iter().find(|decl| contains(&decl.name, at)) {
+            return Some((&decl.name, Some(decl)));
+        }
+        self.refs.iter().find(|(token, _)| contains(token, at)).map(|(token, decl)| (token, decl.map(|i| &self.decls[i])))
+    }
+}
+
+struct Indexer<'a> {
+    // Where the `}` matching each `{` is
+    braces: HashMap<Pos, Pos>,
+    tokens: &'a [Token],
+    // Declarations of each scope, and where it ends
+    scopes: Vec<(Vec<usize>, Pos)>,
+    index: Index,
+}
+
+impl Indexer<'_> {
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) | Stmt::Print { value: e, .. } => self.expr(e),
+            Stmt::Var { name, ty, initializer, constant, .. } => {
+                if let Some(init) = initializer {
+                    self.expr(init);
+                }
+                let mut detail = format!("{} {}", if *constant { "const" } else { "var" }, name.lexeme());
+                if let Some(ty) = ty {
+                    detail += &format!(": {ty}");
+                }
+                self.declare(name, detail, false);
+            },
+            Stmt::Block { brace, stmts } => {
+                let end = self.block_end(brace.as_ref());
+                self.scopes.push((Vec::new(), end));
+                self.stmts(stmts);
+                self.scopes.pop();
+            },
+            Stmt::If { condition, then_br, else_br } => {
+                self.expr(condition);
+                self.stmt(then_br);
+                if let Some(else_br) = else_br {
+                    self.stmt(else_br);
+                }
+            },
+            Stmt::While { condition, body } => {
+                self.expr(condition);
+                self.stmt(body);
+            },
+            Stmt::Function(decl) => {
+                let params: Vec<_> = decl.params.iter().map(|param| param.to_string()).collect();
+                let mut detail = format!("fun {}({})", decl.name.lexeme(), params.join(", "));
+                if let Some(ty) = decl.return_ty {
+                    detail += &format!(": {ty}");
+                }
+                self.declare(&decl.name, detail, true);
+                // Parameters can't hold braces, so the first one after the
+                // name opens the body
+                let body = self.tokens.iter()
+                    .find(|token| *token.kind() == TokenKind::LeftBrace && pos(token) > pos(&decl.name));
+                let end = self.block_end(body);
+                self.scopes.push((Vec::new(), end));
+                for param in &decl.params {
+                    if let Some(default) = &param.default {
+                        self.expr(default);
+                    }
+                    self.declare(&param.name, format!("(parameter) {param}"), false);
+                }
+                self.stmts(&decl.body);
+                self.scopes.pop();
+            },
+            Stmt::Return { value, .. } | Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            },
+            Stmt::ForIn { name, iterable, body } => {
+                self.expr(iterable);
+                let end = match body.as_ref() {
+                    Stmt::Block { brace, .. } => self.block_end(brace.as_ref()),
+                    _ => self.scopes.last().unwrap().1,
+                };
+                self.scopes.push((Vec::new(), end));
+                self.declare(name, format!("var {}", name.lexeme()), false);
+                self.stmt(body);
+                self.scopes.pop();
+            },
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) => {},
+            Expr::Variable { name, .. } => self.refer(name),
+            Expr::Assign { name, value, .. } => {
+                self.expr(value);
+                self.refer(name);
+            },
+            Expr::Grouping(e) | Expr::Unary { right: e, .. } => self.expr(e),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            },
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(&arg.value);
+                }
+            },
+            Expr::Index { object, index, .. } => {
+                self.expr(object);
+                self.expr(index);
+            },
+        }
+    }
+
+    // Blocks the parser made up end with the enclosing scope
+    fn block_end(&self, brace: Option<&Token>) -> Pos {
+        brace.and_then(|brace| self.braces.get(&pos(brace)).copied()).unwrap_or(self.scopes.last().unwrap().1)
+    }
+
+    fn declare(&mut self, name: &Token, detail: String, function: bool) {
+        let scope_end = self.scopes.last().unwrap().1;
+        self.index.decls.push(Decl { name: name.clone(), detail, function, scope_end });
+        let id = self.index.decls.len() - 1;
+        self.scopes.last_mut().unwrap().0.push(id);
+    }
+
+    fn refer(&mut self, name: &Token) {
+        let symbol: Symbol = name.symbol();
+        let decl = self.scopes.iter().rev()
+            .find_map(|(ids, _)| ids.iter().rev().find(|&&id| self.index.decls[id].name.symbol() == symbol).copied());
+        self.index.refs.push((name.clone(), decl));
+    }
+}
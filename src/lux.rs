@@ -0,0 +1,195 @@
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::rc::Rc;
+use std::fs;
+use std::io::{self, Write};
+
+use crate::budget::Limits;
+use crate::convert::{HostFn, IntoValue};
+use crate::error::compiletime::ParseError;
+use crate::error::runtime::EvalError;
+use crate::function::NativeFunction;
+use crate::interpreter::Interpreter;
+use crate::interrupt::Interrupt;
+use crate::optimizer::optimize;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::value::Value;
+
+/// Why `Lux` couldn't run a program.
+#[derive(Debug)]
+pub enum Error {
+    /// The source doesn't parse or resolve; nothing of it was run.
+    Syntax(Vec<ParseError>),
+    /// The program raised an error, ran out of its limits or was interrupted.
+    Runtime(EvalError),
+    Io(io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{e}")?;
+                }
+                Ok(())
+            },
+            Error::Runtime(e) => write!(f, "{e}"),
+            Error::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<EvalError> for Error {
+    fn from(e: EvalError) -> Self {
+        Error::Runtime(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Lux engine for Rust applications.
+///
+/// Programs run on the tree-walking interpreter, and share their globals
+/// with every later `eval` and with the host:
+///
+/// ```
+/// use rlux::{Lux, Value};
+///
+/// let mut lux = Lux::new();
+/// lux.register("double", 1, |args| match &args[0] {
+///     Value::Number(n) => Ok(Value::Number(n * 2.0)),
+///     _ => Err("Expected a number".to_string()),
+/// }).unwrap();
+/// lux.set_global("x", Value::Number(20.0)).unwrap();
+/// let value = lux.eval("var y = double(x) + 2; y;").unwrap();
+/// assert_eq!(value, Value::Number(42.0));
+/// assert_eq!(lux.get_global("y"), Some(Value::Number(42.0)));
+/// ```
+pub struct Lux {
+    interp: Interpreter,
+}
+
+impl Default for Lux {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lux {
+    pub fn new() -> Lux {
+        Lux { interp: Interpreter::new() }
+    }
+
+    /// Runs `source` until its first error. Evaluates to the value of its
+    /// last statement when it is an expression statement (`1 + 2;`), to nil
+    /// otherwise.
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let mut scanner = Scanner::new(source.to_string());
+        let mut parser = Parser::new(scanner.tokens().to_vec());
+        if !scanner.errors().is_empty() {
+            return Err(Error::Syntax(scanner.errors().to_vec()));
+        }
+        let program = parser.program();
+        if !parser.errors().is_empty() {
+            return Err(Error::Syntax(parser.errors().to_vec()));
+        }
+        let mut resolver = Resolver::new();
+        if !resolver.resolve(&program) {
+            return Err(Error::Syntax(resolver.errors().to_vec()));
+        }
+        Ok(self.interp.eval(optimize(program))?)
+    }
+
+    /// Like `eval`, with the contents of a file.
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Value, Error> {
+        let source = fs::read_to_string(path)?;
+        self.eval(&source)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interp.global(name)
+    }
+
+    /// Calls a function got from Lux, e.g. with `get_global`, until its first
+    /// error.
+    ///
+    /// ```
+    /// use rlux::{Lux, Value};
+    ///
+    /// let mut lux = Lux::new();
+    /// lux.eval("fun add(a, b = 1) { return a + b; }").unwrap();
+    /// let add = lux.get_global("add").unwrap();
+    /// assert_eq!(lux.call(&add, &[Value::Number(2.0)]).unwrap(), Value::Number(3.0));
+    /// ```
+    pub fn call(&mut self, function: &Value, args: &[Value]) -> Result<Value, Error> {
+        Ok(self.interp.call_value(function.clone(), args.to_vec())?)
+    }
+
+    /// Defines or redefines a global variable; fails for constants.
+    pub fn set_global<T: IntoValue>(&mut self, name: &str, value: T) -> Result<(), Error> {
+        Ok(self.interp.define_global(name, value.into_value())?)
+    }
+
+    /// Makes `fun` callable from Lux as the global `name`, taking `arity`
+    /// arguments. The message of an `Err` becomes a runtime error at the call.
+    pub fn register<F>(&mut self, name: &str, arity: usize, fun: F) -> Result<(), Error>
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + 'static,
+    {
+        let native = NativeFunction::new(name, arity, move |_, args| Ok(fun(args)?));
+        self.set_global(name, Value::Native(Rc::new(native)))
+    }
+
+    /// Like `register`, for a Rust function or closure taking and returning
+    /// types that convert to and from `Value`:
+    ///
+    /// ```
+    /// use rlux::{Lux, Value};
+    ///
+    /// let mut lux = Lux::new();
+    /// lux.register_fn("longer", |s: String, n: f64| s.chars().count() as f64 > n).unwrap();
+    /// assert_eq!(lux.eval("longer(\"abc\", 2);").unwrap(), Value::Bool(true));
+    /// assert_eq!(
+    ///     lux.eval("longer(\"abc\", \"2\");").unwrap_err().to_string(),
+    ///     "[line 1] at ')': Argument 2: expected number but got string",
+    /// );
+    /// ```
+    pub fn register_fn<Args, Ret, F>(&mut self, name: &str, fun: F) -> Result<(), Error>
+    where
+        F: HostFn<Args, Ret> + 'static,
+    {
+        self.register(name, fun.arity(), move |args| fun.call(args))
+    }
+
+    /// As [`Interpreter::set_limits`](crate::interpreter::Interpreter::set_limits), for `eval`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.interp.set_limits(limits);
+    }
+
+    /// As [`Interpreter::interrupt_handle`](crate::interpreter::Interpreter::interrupt_handle), for `eval`.
+    pub fn interrupt_handle(&self) -> Interrupt {
+        self.interp.interrupt_handle()
+    }
+
+    /// As [`Interpreter::set_max_call_depth`](crate::interpreter::Interpreter::set_max_call_depth).
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.interp.set_max_call_depth(depth);
+    }
+
+    /// As [`Interpreter::set_output`](crate::interpreter::Interpreter::set_output), unboxed.
+    pub fn set_output<W: Write + 'static>(&mut self, out: W) {
+        self.interp.set_output(Box::new(out));
+    }
+}
@@ -1,40 +1,315 @@
 use std::io::{Write, Read};
 use std::{env, io};
-use std::fs::File;
+use std::fs::{self, File};
 use std::process::ExitCode;
 use std::path::Path;
-fn main() -> Result<(), ExitCode> {
-    let args:Vec<String> = env::args().collect();
-    match args.len() {
-        2 => { run_file(Path::new(&args[1])).expect("Couldn't run file"); },
-        1 => { run_prompt().expect("Couldn't run prompt command"); },
+use std::fmt::Display;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use rlux::budget::Limits;
+use rlux::interrupt::{self, Interrupt};
+use rlux::{ErrorKind, EvalError, Program};
+
+// Native stack reserved for each nested Lux call, enough for the tree-walking
+// interpreter in debug builds, on top of a fixed amount for everything else
+const STACK_PER_CALL: usize = 64 * 1024;
+const BASE_STACK: usize = 8 * 1024 * 1024;
+// Default of `--max-call-depth`, deeper than the library's since the
+// interpreter thread gets a stack sized for it
+const MAX_CALL_DEPTH: usize = 1000;
+
+fn main() -> ExitCode {
+    let mut options = Options {
+        backend: Backend::Tree,
+        optimize: true,
+        max_call_depth: MAX_CALL_DEPTH,
+        limits: Limits::default(),
+        dump_ast: None,
+        tokens: None,
+        profile: None,
+        coverage: None,
+    };
+    let mut args = Vec::new();
+    for arg in env::args() {
+        match arg.as_str() {
+            "-O0" => options.optimize = false,
+            "-O1" => options.optimize = true,
+            "--dump-ast" | "--dump-ast=sexpr" => options.dump_ast = Some(Format::Sexpr),
+            "--dump-ast=json" => options.dump_ast = Some(Format::Json),
+            "--tokens" | "--tokens=text" => options.tokens = Some(Format::Text),
+            "--tokens=json" => options.tokens = Some(Format::Json),
+            "--profile" | "--profile=table" => options.profile = Some(Format::Text),
+            "--profile=folded" => options.profile = Some(Format::Folded),
+            flag if flag.starts_with("--profile=") => {
+                eprintln!("Unknown format '{}', expected 'table' or 'folded'", &flag["--profile=".len()..]);
+                return ExitCode::from(64);
+            },
+            flag if flag.starts_with("--coverage=") => {
+                options.coverage = Some(flag["--coverage=".len()..].to_string());
+                // Covers the program as written, not as folded
+                options.optimize = false;
+            },
+            flag if flag.starts_with("--tokens=") => {
+                eprintln!("Unknown format '{}', expected 'text' or 'json'", &flag["--tokens=".len()..]);
+                return ExitCode::from(64);
+            },
+            flag if flag.starts_with("--dump-ast=") => {
+                eprintln!("Unknown format '{}', expected 'sexpr' or 'json'", &flag["--dump-ast=".len()..]);
+                return ExitCode::from(64);
+            },
+            flag if flag.starts_with("--backend=") => {
+                options.backend = match &flag["--backend=".len()..] {
+                    "tree" => Backend::Tree,
+                    "vm" => Backend::Vm,
+                    other => {
+                        eprintln!("Unknown backend '{other}', expected 'tree' or 'vm'");
+                        return ExitCode::from(64);
+                    }
+                };
+            },
+            flag if flag.starts_with("--max-call-depth=") => match flag_value(flag) {
+                Some(depth) => options.max_call_depth = depth,
+                None => return ExitCode::from(64),
+            },
+            flag if flag.starts_with("--max-steps=") => match flag_value(flag) {
+                Some(steps) => options.limits.max_steps = Some(steps),
+                None => return ExitCode::from(64),
+            },
+            flag if flag.starts_with("--timeout=") => match flag_value(flag) {
+                Some(ms) => options.limits.timeout = Some(Duration::from_millis(ms)),
+                None => return ExitCode::from(64),
+            },
+            flag if flag.starts_with("--max-heap=") => match flag_value(flag) {
+                Some(bytes) => options.limits.max_heap_bytes = Some(bytes),
+                None => return ExitCode::from(64),
+            },
+            _ => args.push(arg),
+        }
+    }
+    if options.profile.is_some() && matches!(options.backend, Backend::Vm) {
+        eprintln!("--profile needs the tree-walking backend");
+        return ExitCode::from(64);
+    }
+    if options.coverage.is_some() && matches!(options.backend, Backend::Vm) {
+        eprintln!("--coverage needs the tree-walking backend");
+        return ExitCode::from(64);
+    }
+    // Deep recursion in the tree-walking interpreter needs more than the main thread's stack
+    let max_call_depth = options.max_call_depth;
+    let stack_size = BASE_STACK.saturating_add(max_call_depth.saturating_mul(STACK_PER_CALL));
+    let thread = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || run_args(args, &options));
+    match thread {
+        // The panic was already reported
+        Ok(thread) => thread.join().unwrap_or(ExitCode::from(70)),
+        Err(_) => {
+            eprintln!("Couldn't reserve a stack for a call depth of {max_call_depth}");
+            ExitCode::from(64)
+        },
+    }
+}
+
+// The number after the `=` of a flag
+fn flag_value<T: FromStr>(flag: &str) -> Option<T> {
+    let (name, value) = flag.split_once('=').unwrap();
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        eprintln!("Invalid value '{value}' for {name}");
+    }
+    parsed
+}
+
+fn run_args(args: Vec<String>, options: &Options) -> ExitCode {
+    match args.get(1).map(String::as_str) {
+        Some("fmt") => return fmt(&args[2..]),
+        Some("lint") => return lint(&args[2..]),
+        Some("debug") => return debug(&args[2..], options),
+        Some("lsp") => return serve(lsp::serve(io::stdin().lock(), io::stdout())),
+        Some("dap") => return serve(dap::serve(io::stdin().lock(), io::stdout())),
+        _ => {},
+    }
+    let result = match args.len() {
+        2 if options.tokens.is_some() => {
+            let content = read_file(Path::new(&args[1])).expect("Couldn't read file");
+            return dump_tokens(&content, options.tokens.unwrap());
+        },
+        2 if options.dump_ast.is_some() => {
+            let content = read_file(Path::new(&args[1])).expect("Couldn't read file");
+            return dump_ast(&content, options.dump_ast.unwrap());
+        },
+        3 if args[1] == "check" => {
+            let content = read_file(Path::new(&args[2])).expect("Couldn't read file");
+            if !check(&content) {
+                return ExitCode::from(65);
+            }
+            let mut engine = Engine::new(options);
+            let result = run(content, &mut engine, options);
+            engine.finish(options, &args[2]);
+            result
+        },
+        2 => run_file(&args[1], options).expect("Couldn't run file"),
+        1 => { run_prompt(options).expect("Couldn't run prompt command"); Ok(true) },
         _ => {
-            eprintln!("Usage: rlox [script]");
-            return Err(ExitCode::from(64));
+            eprintln!("Usage: rlux [--backend=tree|vm] [-O0|-O1] [--tokens[=text|json]] [--dump-ast[=sexpr|json]] [--profile[=table|folded]] [--coverage=FILE] [--max-call-depth=N] [--max-steps=N] [--timeout=MS] [--max-heap=BYTES] [check] [script]");
+            eprintln!("       rlux fmt [--check] <script>...");
+            eprintln!("       rlux lint [--enable=RULE,...] [--disable=RULE,...] <script>...");
+            eprintln!("       rlux debug <script>");
+            eprintln!("       rlux lsp");
+            eprintln!("       rlux dap");
+            return ExitCode::from(64);
         }
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(65),
+        // Stopped by Ctrl-C or one of its limits
+        Err(e) => {
+            eprintln!("{e}");
+            match e.kind() {
+                ErrorKind::Interrupted => ExitCode::from(130),
+                _ => ExitCode::from(70),
+            }
+        },
     }
-    Ok(())
 }
 
-fn run_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
-    println!("Running file");
+#[derive(Clone, Copy)]
+enum Backend {
+    Tree,
+    Vm,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Text,
+    Sexpr,
+    Json,
+    // Call stacks for flamegraph tools
+    Folded,
+}
+
+#[derive(Clone)]
+struct Options {
+    backend: Backend,
+    // `-O1` (the default) folds constants before running, `-O0` does not
+    optimize: bool,
+    max_call_depth: usize,
+    limits: Limits,
+    // Print the parsed program instead of running it
+    dump_ast: Option<Format>,
+    // Print the tokens of the program instead of running it
+    tokens: Option<Format>,
+    // Time the program, then print a table or folded stacks to stderr
+    profile: Option<Format>,
+    // Write lcov coverage of the program to this file
+    coverage: Option<String>,
+}
+
+/// Keeps the globals of either backend alive between REPL lines.
+enum Engine {
+    Tree(Interpreter),
+    Vm(Vm),
+}
+
+impl Engine {
+    // Ctrl-C interrupts the engine from now on
+    fn new(options: &Options) -> Engine {
+        let engine = match options.backend {
+            Backend::Tree => {
+                let mut interp = Interpreter::new();
+                interp.set_max_call_depth(options.max_call_depth);
+                interp.set_limits(options.limits);
+                if options.profile.is_some() {
+                    interp.set_profiler(Profiler::new());
+                }
+                if options.coverage.is_some() {
+                    interp.set_coverage(Coverage::new());
+                }
+                Engine::Tree(interp)
+            },
+            Backend::Vm => {
+                let mut vm = Vm::new();
+                vm.set_max_call_depth(options.max_call_depth);
+                vm.set_limits(options.limits);
+                Engine::Vm(vm)
+            },
+        };
+        interrupt::install_handler(engine.interrupt_handle());
+        engine
+    }
+
+    fn interrupt_handle(&self) -> Interrupt {
+        match self {
+            Engine::Tree(interp) => interp.interrupt_handle(),
+            Engine::Vm(vm) => vm.interrupt_handle(),
+        }
+    }
+
+    fn interpret(&mut self, program: Program) -> Result<(), EvalError> {
+        match self {
+            Engine::Tree(interp) => interp.interpret(program),
+            Engine::Vm(vm) => vm.interpret(program),
+        }
+    }
+
+    // Reports what was recorded while the program at `path` ran
+    fn finish(&self, options: &Options, path: &str) {
+        let Engine::Tree(interp) = self else {
+            return;
+        };
+        if let Some(format) = options.profile {
+            let profiler = interp.profiler().unwrap();
+            match format {
+                Format::Folded => eprint!("{}", profiler.folded()),
+                _ => eprint!("{}", profiler.table()),
+            }
+        }
+        if let Some(out) = &options.coverage {
+            let source = fs::canonicalize(path).map_or(path.to_string(), |path| path.display().to_string());
+            if let Err(e) = fs::write(out, interp.coverage().unwrap().lcov(&source)) {
+                eprintln!("{out}: {e}");
+            }
+        }
+    }
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
     let mut file = File::open(path)?;
     let mut content = String::new();
     file.read_to_string(&mut content)?;
-    run(content);
-    Ok(())
+    Ok(content)
 }
 
-fn run_prompt() -> io::Result<()> {
+fn run_file(path: &str, options: &Options) -> io::Result<Result<bool, EvalError>> {
+    println!("Running file");
+    let content = read_file(path)?;
+    let mut engine = Engine::new(options);
+    let result = run(content, &mut engine, options);
+    engine.finish(options, path);
+    Ok(result)
+}
+
+fn run_prompt(options: &Options) -> io::Result<()> {
     println!("Running prompt");
     let stdin = io::stdin();
+    let mut engine = Engine::new(options);
+    let interrupt = engine.interrupt_handle();
     loop {
         print!("> ");
         io::stdout().flush().expect("Couldnt flush stdout");
         let mut line = String::new();
         match stdin.read_line(&mut line) {
             Ok(0) => break,
-            Ok(_) => run(line),
+            Ok(_) => {
+                // Ctrl-C pressed at the prompt
+                interrupt.clear();
+                if let Err(e) = run(line, &mut engine, options) {
+                    eprintln!("{e}");
+                }
+            },
             Err(e) => return Err(e),
         }
         
@@ -46,12 +321,276 @@ fn run_prompt() -> io::Result<()> {
 use rlux::scanner::Scanner;
 use rlux::parser::Parser;
 use rlux::interpreter::Interpreter;
+use rlux::vm::Vm;
+use rlux::checker::Checker;
+use rlux::resolver::Resolver;
+use rlux::optimizer::optimize;
+use rlux::json;
+use rlux::formatter;
+use rlux::linter::{Linter, Rule};
+use rlux::lsp;
+use rlux::dap;
+use rlux::debugger::Console;
+use rlux::profiler::Profiler;
+use rlux::coverage::Coverage;
 
-fn run(source: String) {
+// Whether the program was valid and ran to the end; fails if it was stopped
+fn run(source: String, engine: &mut Engine, options: &Options) -> Result<bool, EvalError> {
     let mut scanner = Scanner::new(source);
     let mut parser = Parser::new(scanner.tokens().to_vec());
-    let mut tree = parser.stmts();
-    // println!("{tree}");
-    let mut interp = Interpreter::new();
-    interp.interpret(tree);
+    report(scanner.errors());
+    let mut tree = parser.program();
+    report(parser.errors());
+    let mut resolver = Resolver::new();
+    if !resolver.resolve(&tree) {
+        report(resolver.errors());
+        return Ok(false);
+    }
+    if options.optimize {
+        tree = optimize(tree);
+    }
+    engine.interpret(tree)?;
+    Ok(true)
+}
+
+fn check(source: &str) -> bool {
+    let mut scanner = Scanner::new(source.to_string());
+    let mut parser = Parser::new(scanner.tokens().to_vec());
+    report(scanner.errors());
+    let tree = parser.program();
+    report(parser.errors());
+    if !scanner.errors().is_empty() || !parser.errors().is_empty() {
+        return false;
+    }
+    let mut resolver = Resolver::new();
+    if !resolver.resolve(&tree) {
+        report(resolver.errors());
+        return false;
+    }
+    let mut checker = Checker::new();
+    let typed = checker.check(&tree);
+    report(checker.errors());
+    typed
+}
+
+// Rewrites the files in place, or with `--check` lists those that aren't
+// formatted and fails
+fn fmt(args: &[String]) -> ExitCode {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<_> = args.iter().filter(|arg| *arg != "--check").collect();
+    if paths.is_empty() {
+        eprintln!("Usage: rlux fmt [--check] <script>...");
+        return ExitCode::from(64);
+    }
+    let (mut unformatted, mut failed) = (false, false);
+    for path in paths {
+        let source = match read_file(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                failed = true;
+                continue;
+            },
+        };
+        match formatter::format(&source) {
+            Ok(formatted) if formatted == source => {},
+            Ok(_) if check => {
+                println!("{path} is not formatted");
+                unformatted = true;
+            },
+            Ok(formatted) => {
+                if let Err(e) = fs::write(path, formatted) {
+                    eprintln!("{path}: {e}");
+                    failed = true;
+                }
+            },
+            Err(errors) => {
+                report(&errors);
+                failed = true;
+            },
+        }
+    }
+    if failed {
+        ExitCode::from(65)
+    } else if unformatted {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// Prints the lints of every file, and fails if there are any. Rules are
+// turned on and off in the order of the flags, `all` standing for each of them
+fn lint(args: &[String]) -> ExitCode {
+    let mut paths = Vec::new();
+    let mut linter = Linter::new();
+    for arg in args {
+        let (enable, names) = match arg.split_once('=') {
+            Some(("--enable", names)) => (true, names),
+            Some(("--disable", names)) => (false, names),
+            _ => {
+                paths.push(arg);
+                continue;
+            },
+        };
+        for name in names.split(',') {
+            let rules = match Rule::from_name(name) {
+                Some(rule) => vec![rule],
+                None if name == "all" => Rule::ALL.to_vec(),
+                None => {
+                    let names: Vec<_> = Rule::ALL.iter().map(|rule| rule.name()).collect();
+                    eprintln!("Unknown rule '{name}', expected one of {}", names.join(", "));
+                    return ExitCode::from(64);
+                },
+            };
+            for rule in rules {
+                if enable { linter.enable(rule) } else { linter.disable(rule) }
+            }
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("Usage: rlux lint [--enable=RULE,...] [--disable=RULE,...] <script>...");
+        return ExitCode::from(64);
+    }
+    let (mut linted, mut failed) = (false, false);
+    for path in paths {
+        let Ok(source) = read_file(path).inspect_err(|e| eprintln!("{path}: {e}")) else {
+            failed = true;
+            continue;
+        };
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.tokens().to_vec());
+        let tree = parser.program();
+        if !scanner.errors().is_empty() || !parser.errors().is_empty() {
+            report(scanner.errors());
+            report(parser.errors());
+            failed = true;
+            continue;
+        }
+        if !linter.lint(&tree) {
+            for lint in linter.lints() {
+                println!("{path}: {lint}");
+            }
+            linted = true;
+        }
+    }
+    if failed {
+        ExitCode::from(65)
+    } else if linted {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// Exit code of a language server or debug adapter once its client is gone
+fn serve(result: io::Result<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+// Runs the program under a debugger reading commands from the terminal,
+// which stops before its first line
+fn debug(args: &[String], options: &Options) -> ExitCode {
+    let [path] = args else {
+        eprintln!("Usage: rlux debug <script>");
+        return ExitCode::from(64);
+    };
+    let Ok(source) = read_file(path).inspect_err(|e| eprintln!("{path}: {e}")) else {
+        return ExitCode::from(66);
+    };
+    let mut scanner = Scanner::new(source.clone());
+    let mut parser = Parser::new(scanner.tokens().to_vec());
+    let tree = parser.program();
+    let mut resolver = Resolver::new();
+    if !scanner.errors().is_empty() || !parser.errors().is_empty() || !resolver.resolve(&tree) {
+        report(scanner.errors());
+        report(parser.errors());
+        report(resolver.errors());
+        return ExitCode::from(65);
+    }
+    let Engine::Tree(mut interp) = Engine::new(&Options { backend: Backend::Tree, ..options.clone() }) else {
+        unreachable!()
+    };
+    interp.set_debugger(Box::new(Console::new(&source, io::stdin().lock(), io::stdout())));
+    match interp.interpret(tree) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            match e.kind() {
+                ErrorKind::Interrupted => ExitCode::from(130),
+                _ => ExitCode::from(70),
+            }
+        },
+    }
+}
+
+// Lists scanning errors among the tokens, where they were found
+fn dump_tokens(source: &str, format: Format) -> ExitCode {
+    let scanner = Scanner::new(source.to_string());
+    let scanned = scanner.scanned();
+    let written = write_stdout(|out| match format {
+        Format::Json => writeln!(out, "{:#}", json::tokens(&scanned)),
+        _ => scanned.iter().try_for_each(|item| match item {
+            Ok(token) => {
+                let at = format!("{}:{}", token.line(), token.column());
+                let line = format!("{at:<8}{:<24}{}", token.to_string(), token.lexeme());
+                writeln!(out, "{}", line.trim_end())
+            },
+            Err(e) => writeln!(out, "{:<8}error: {}", format!("{}:{}", e.line(), e.column()), e.message()),
+        }),
+    });
+    if let Err(code) = written {
+        return code;
+    }
+    if scanner.errors().is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(65)
+    }
+}
+
+// Prints the tree even when the parser had to skip statements
+fn dump_ast(source: &str, format: Format) -> ExitCode {
+    let mut scanner = Scanner::new(source.to_string());
+    let mut parser = Parser::new(scanner.tokens().to_vec());
+    let tree = parser.program();
+    let written = write_stdout(|out| match format {
+        Format::Json => writeln!(out, "{:#}", json::ast(&tree)),
+        _ => write!(out, "{tree}"),
+    });
+    if let Err(code) = written {
+        return code;
+    }
+    report(scanner.errors());
+    report(parser.errors());
+    if scanner.errors().is_empty() && parser.errors().is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(65)
+    }
+}
+
+// Writes to standard output, where a closed pipe (`| head`) ends the output
+// normally; other errors are reported and fail with the code to exit with
+fn write_stdout(write: impl FnOnce(&mut io::StdoutLock) -> io::Result<()>) -> Result<(), ExitCode> {
+    let mut out = io::stdout().lock();
+    match write(&mut out).and_then(|()| out.flush()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("{e}");
+            Err(ExitCode::from(74))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn report<E: Display>(errors: &[E]) {
+    for e in errors {
+        eprintln!("{e}");
+    }
 }
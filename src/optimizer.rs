@@ -0,0 +1,162 @@
+use std::rc::Rc;
+
+use crate::expr::{Arg, Expr, FunctionDecl, Param, Program, Stmt};
+use crate::token::TokenKind;
+use crate::value::Value;
+
+/// Folds constant expressions and drops branches that can never run.
+///
+/// Operators are folded with the same `Value` methods the backends use, and
+/// only when they succeed: an expression that fails (e.g. `"a" - 1`) is left
+/// in place so it still raises its error at runtime. Runs after the resolver,
+/// so errors in dead code are still reported.
+pub fn optimize(program: Program) -> Program {
+    Program { stmts: fold_stmts(program.stmts), resolved: program.resolved }
+}
+
+fn fold_stmts(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts.into_iter().filter_map(fold_stmt).collect()
+}
+
+// `None` when the statement can be dropped
+fn fold_stmt(stmt: Stmt) -> Option<Stmt> {
+    let stmt = match stmt {
+        Stmt::Print { keyword, value } => Stmt::Print { keyword, value: fold_expr(value) },
+        Stmt::Expr(e) => Stmt::Expr(fold_expr(e)),
+        Stmt::Var { name, ty, initializer, constant, slot } => {
+            Stmt::Var { name, ty, initializer: initializer.map(fold_expr), constant, slot }
+        },
+        Stmt::Block { brace, stmts } => Stmt::Block { brace, stmts: fold_stmts(stmts) },
+        Stmt::If { condition, then_br, else_br } => {
+            let condition = fold_expr(condition);
+            if let Expr::Literal(value) = &condition {
+                return match value.bool() {
+                    true => fold_stmt(*then_br),
+                    false => else_br.and_then(|else_br| fold_stmt(*else_br)),
+                };
+            }
+            Stmt::If {
+                condition,
+                then_br: Box::new(branch(*then_br)),
+                else_br: else_br.and_then(|else_br| fold_stmt(*else_br)).map(Box::new),
+            }
+        },
+        Stmt::While { condition, body } => {
+            let condition = fold_expr(condition);
+            if let Expr::Literal(value) = &condition {
+                if !value.bool() {
+                    return None;
+                }
+            }
+            Stmt::While { condition, body: Box::new(branch(*body)) }
+        },
+        Stmt::Function(decl) => Stmt::Function(fold_function(decl)),
+        Stmt::Return { keyword, value } => Stmt::Return { keyword, value: value.map(fold_expr) },
+        Stmt::Yield { keyword, value } => Stmt::Yield { keyword, value: value.map(fold_expr) },
+        Stmt::ForIn { name, iterable, body } => {
+            Stmt::ForIn { name, iterable: fold_expr(iterable), body: Box::new(branch(*body)) }
+        },
+    };
+    Some(stmt)
+}
+
+// A statement that must stay, even if empty
+fn branch(body: Stmt) -> Stmt {
+    fold_stmt(body).unwrap_or(Stmt::Block { brace: None, stmts: Vec::new() })
+}
+
+fn fold_function(decl: Rc<FunctionDecl>) -> Rc<FunctionDecl> {
+    // Only the parser holds declarations at this point
+    let Ok(decl) = Rc::try_unwrap(decl) else { unreachable!("function declaration already shared") };
+    let params = decl.params.into_iter().map(|param| Param {
+        default: param.default.map(fold_expr),
+        ..param
+    }).collect();
+    Rc::new(FunctionDecl {
+        params,
+        body: fold_stmts(decl.body),
+        ..decl
+    })
+}
+
+fn fold_expr(e: Expr) -> Expr {
+    match e {
+        Expr::Grouping(inner) => match fold_expr(*inner) {
+            Expr::Literal(value) => Expr::Literal(value),
+            inner => Expr::Grouping(Box::new(inner)),
+        },
+        Expr::Unary { op, right } => {
+            let right = fold_expr(*right);
+            if let Expr::Literal(value) = &right {
+                let folded = match op.kind() {
+                    TokenKind::Bang => Some(value.inv()),
+                    TokenKind::Minus => {
+                        let mut value = value.clone();
+                        value.try_neg().ok().map(|_| value)
+                    },
+                    _ => None,
+                };
+                if let Some(value) = folded {
+                    return Expr::Literal(value);
+                }
+            }
+            Expr::Unary { op, right: Box::new(right) }
+        },
+        Expr::Binary { left, op, right } => {
+            let left = fold_expr(*left);
+            let right = fold_expr(*right);
+            if let (Expr::Literal(a), Expr::Literal(b)) = (&left, &right) {
+                if let Some(value) = fold_binary(op.kind(), a, b) {
+                    return Expr::Literal(value);
+                }
+            }
+            Expr::Binary { left: Box::new(left), op, right: Box::new(right) }
+        },
+        Expr::Logical { left, op, right } => {
+            let left = fold_expr(*left);
+            let right = fold_expr(*right);
+            if let Expr::Literal(value) = &left {
+                // Both operators yield one of their operands
+                let short_circuits = match op.kind() {
+                    TokenKind::And => !value.bool(),
+                    _ => value.bool(),
+                };
+                return if short_circuits { left } else { right };
+            }
+            Expr::Logical { left: Box::new(left), op, right: Box::new(right) }
+        },
+        Expr::Assign { name, value, slot } => Expr::Assign { name, value: Box::new(fold_expr(*value)), slot },
+        Expr::Call { callee, paren, args } => Expr::Call {
+            callee: Box::new(fold_expr(*callee)),
+            paren,
+            args: args.into_iter().map(|arg| Arg { name: arg.name, value: fold_expr(arg.value) }).collect(),
+        },
+        Expr::Index { object, bracket, index } => Expr::Index {
+            object: Box::new(fold_expr(*object)),
+            bracket,
+            index: Box::new(fold_expr(*index)),
+        },
+        e @ (Expr::Literal(_) | Expr::Variable { .. }) => e,
+    }
+}
+
+// `None` when the operator fails at runtime and must be left there
+fn fold_binary(op: &TokenKind, a: &Value, b: &Value) -> Option<Value> {
+    let arithmetic = |f: fn(&mut Value, &Value) -> Result<(), &'static str>| {
+        let mut a = a.clone();
+        f(&mut a, b).ok().map(|_| a)
+    };
+    match op {
+        TokenKind::Plus => arithmetic(Value::try_sum),
+        TokenKind::Minus => arithmetic(Value::try_sub),
+        TokenKind::Star => arithmetic(Value::try_mult),
+        TokenKind::Slash => arithmetic(Value::try_div),
+        TokenKind::Greater => a.try_gt(b).ok(),
+        TokenKind::GreaterEqual => a.try_gte(b).ok(),
+        TokenKind::Less => a.try_lt(b).ok(),
+        TokenKind::LessEqual => a.try_lte(b).ok(),
+        TokenKind::EqualEqual => Some(a.clone().equals(b)),
+        TokenKind::BangEqual => Some(a.clone().neq(b)),
+        _ => None,
+    }
+}
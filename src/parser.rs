@@ -1,11 +1,18 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
 use crate::error::compiletime::ParseError;
-use crate::expr::{Expr, Stmt};
+use crate::expr::{Arg, Expr, FunctionDecl, Param, Program, Stmt};
 use crate::token::{Token, TokenKind};
+use crate::types::Type;
 use crate::value::Value;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current_idx: usize,
+    // One entry per function being parsed, set once a `yield` is found in it
+    yields: Vec<bool>,
+    errors: Vec<ParseError>,
 }
 
 impl Parser {
@@ -13,6 +20,8 @@ impl Parser {
         Parser {
             tokens,
             current_idx: 0,
+            yields: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
@@ -27,7 +36,12 @@ impl Parser {
     //     }
     // }
 
-    pub fn stmts(&mut self) -> Vec<Stmt> {
+    /// Parses every statement it can, skipping past the errors found; see `errors`.
+    pub fn program(&mut self) -> Program {
+        Program { stmts: self.stmts(), resolved: Cell::new(false) }
+    }
+
+    pub(crate) fn stmts(&mut self) -> Vec<Stmt> {
         let mut stmts = Vec::new();
         while let Some(tk) = self.peek() {
             if tk.kind() == &TokenKind::Eof {
@@ -39,19 +53,32 @@ impl Parser {
                     stmts.push(stmt);
                 },
                 Err(e) => {
-                    eprintln!("{e}")
+                    self.errors.push(e);
+                    self.sync();
                 }
             }
         }
         stmts
     }
 
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
     fn declaration(&mut self) -> Result<Stmt, ParseError>{
         let curr_tk = self.peek().expect("couldnt get token");
         match curr_tk.kind() {
             TokenKind::Var => {
                 let _ = self.next();
-                self.var_decl()
+                self.var_decl(false)
+            },
+            TokenKind::Const => {
+                let _ = self.next();
+                self.var_decl(true)
+            },
+            TokenKind::Fun => {
+                let _ = self.next();
+                self.function()
             },
             _ => { 
                 self.stmt()
@@ -59,17 +86,25 @@ impl Parser {
         } 
     }
 
-    //var name (= value);
-    fn var_decl(&mut self) -> Result<Stmt, ParseError> {
+    //var name (: type) (= value);
+    //const name (: type) = value;
+    fn var_decl(&mut self, constant: bool) -> Result<Stmt, ParseError> {
         
-        let tk = self.peek().expect("couldnt get token");
+        let tk = self.peek().expect("couldnt get token").clone();
         let name = match tk.kind() {
-            TokenKind::Identifier(name) => {
-                let name = name.to_string();
+            TokenKind::Identifier(_) => {
+                let _ = self.next();
+                tk
+            },
+            _ => return Err(ParseError::new("expected a variable name", &tk)),
+        };
+
+        let ty = match self.peek().expect("couldnt get token").kind() {
+            TokenKind::Colon => {
                 let _ = self.next();
-                name
+                Some(self.type_annot()?)
             },
-            _ => return Err(ParseError::new("expected a variable name", tk)),
+            _ => None
         };
 
         let initializer = match self.peek().expect("couldnt get token").kind() {
@@ -82,16 +117,117 @@ impl Parser {
         };
 
         let tk = self.peek().expect("couldnt get token");
+        if constant && initializer.is_none() {
+            return Err(ParseError::new("Expected an initializer for constant", tk));
+        }
         if let TokenKind::Semicolon = tk.kind() {
             let _ = self.next();
             Ok(
                 Stmt::Var { 
                 name,
+                ty,
                 initializer,
+                constant,
+                slot: Cell::default(),
             })
         }
         else {
-            return Err(ParseError::new("Expected ';'", tk));
+            Err(ParseError::new("Expected ';'", tk))
+        }
+    }
+
+    //fun name(param, name = default, ...rest) { body }
+    fn function(&mut self) -> Result<Stmt, ParseError> {
+        let tk = self.peek().expect("couldnt get token").clone();
+        let name = match tk.kind() {
+            TokenKind::Identifier(_) => {
+                let _ = self.next();
+                tk
+            },
+            _ => return Err(ParseError::new("Expected a function name", &tk)),
+        };
+        self.consume(TokenKind::LeftParen, "Expected '(' after function name")?;
+        let mut params = Vec::new();
+        if !self.match_token(&[TokenKind::RightParent]) {
+            loop {
+                let param = self.param(&params)?;
+                params.push(param);
+                if !self.match_token(&[TokenKind::Comma]) {
+                    break;
+                }
+                let _ = self.next();
+            }
+        }
+        self.consume(TokenKind::RightParent, "Expected ')' after parameters")?;
+        let return_ty = match self.peek().expect("couldnt get token").kind() {
+            TokenKind::Colon => {
+                let _ = self.next();
+                Some(self.type_annot()?)
+            },
+            _ => None
+        };
+        self.consume(TokenKind::LeftBrace, "Expected '{' before function body")?;
+        self.yields.push(false);
+        let body = self.block();
+        let generator = self.yields.pop().unwrap();
+        let body = body?;
+        Ok(Stmt::Function(Rc::new(FunctionDecl { name, params, return_ty, body, generator, slot: Cell::default() })))
+    }
+
+    //(...)name (: type) (= default)
+    fn param(&mut self, previous: &[Param]) -> Result<Param, ParseError> {
+        let variadic = self.match_token(&[TokenKind::Ellipsis]);
+        if variadic {
+            let _ = self.next();
+        }
+        let tk = self.peek().expect("couldnt get token").clone();
+        let name = match tk.kind() {
+            TokenKind::Identifier(_) => {
+                let _ = self.next();
+                tk
+            },
+            _ => return Err(ParseError::new("Expected a parameter name", &tk)),
+        };
+        if previous.iter().any(|p| p.variadic) {
+            return Err(ParseError::new("Variadic parameter must be the last one", &name));
+        }
+        if previous.iter().any(|p| p.name.lexeme() == name.lexeme()) {
+            return Err(ParseError::new("Duplicate parameter name", &name));
+        }
+
+        let ty = match self.peek().expect("couldnt get token").kind() {
+            TokenKind::Colon => {
+                let _ = self.next();
+                Some(self.type_annot()?)
+            },
+            _ => None
+        };
+
+        let default = match self.peek().expect("couldnt get token").kind() {
+            TokenKind::Equal => {
+                let equals = self.next().unwrap().clone();
+                if variadic {
+                    return Err(ParseError::new("Variadic parameter cannot have a default value", &equals));
+                }
+                Some(*self.expr()?)
+            },
+            _ => None
+        };
+        if default.is_none() && !variadic && previous.iter().any(|p| p.default.is_some()) {
+            return Err(ParseError::new("Parameter without a default value follows one with a default", &name));
+        }
+        Ok(Param { name, ty, default, variadic })
+    }
+
+    //: number | string | bool | nil | any
+    fn type_annot(&mut self) -> Result<Type, ParseError> {
+        let tk = self.peek().expect("couldnt get token");
+        match Type::from_token(tk) {
+            Some(ty) => {
+                let _ = self.next();
+                Ok(ty)
+            },
+            None => Err(ParseError::new("Expected a type name", tk)),
         }
     }
 
@@ -99,12 +235,12 @@ impl Parser {
         let curr_tk = self.peek().expect("couldnt get token");
         match curr_tk.kind() {
             TokenKind::Print => {
-                let _ = self.next();
-                self.print_stmt()
+                let keyword = self.next().unwrap().clone();
+                self.print_stmt(keyword)
             },
             TokenKind::LeftBrace => {
-                let _ = self.next();
-                Ok(Stmt::Block(self.block()?))
+                let brace = self.next().cloned();
+                Ok(Stmt::Block { brace, stmts: self.block()? })
             },
             TokenKind::If => {
                 let _ = self.next();
@@ -117,7 +253,15 @@ impl Parser {
             TokenKind::For => {
                 let _ = self.next();
                 self.for_stmt()
-            }
+            },
+            TokenKind::Return => {
+                let keyword = self.next().unwrap().clone();
+                self.return_stmt(keyword)
+            },
+            TokenKind::Yield => {
+                let keyword = self.next().unwrap().clone();
+                self.yield_stmt(keyword)
+            },
             _ => { 
                 self.expr_stmt()
             },
@@ -126,13 +270,17 @@ impl Parser {
 
     fn for_stmt(&mut self) -> Result<Stmt, ParseError> {
         self.consume(TokenKind::LeftParen, "Expected '(' after 'for'.")?;
+        if matches!(self.peek().unwrap().kind(), TokenKind::Identifier(_))
+            && matches!(self.peek_next().map(|tk| tk.kind()), Some(TokenKind::In)) {
+            return self.for_in_stmt();
+        }
         let initializer = match self.peek().unwrap().kind() {
             TokenKind::Semicolon => {
                 None
             },
             TokenKind::Var => {
                 let _ = self.next();
-                Some(self.var_decl()?)
+                Some(self.var_decl(false)?)
             },
             _ => {
                 Some(self.expr_stmt()?)
@@ -156,15 +304,49 @@ impl Parser {
         self.consume(TokenKind::RightParent, "Expecting ')' after for clauses")?;
         let mut body =self.stmt()?;
         if let Some(increment) = increment {
-            body = Stmt::Block( vec![body, Stmt::Expr(*increment)]);
+            body = Stmt::Block { brace: None, stmts: vec![body, Stmt::Expr(*increment)] };
         }
         body = Stmt::While { condition, body:Box::new(body)};
         if let Some(initializer) = initializer {
-            body = Stmt::Block(vec![initializer,body]);
+            body = Stmt::Block { brace: None, stmts: vec![initializer, body] };
         }
         Ok(body)
     }
 
+    fn return_stmt(&mut self, keyword: Token) -> Result<Stmt, ParseError> {
+        let value = if self.match_token(&[TokenKind::Semicolon]) {
+            None
+        } else {
+            Some(*self.expr()?)
+        };
+        self.consume(TokenKind::Semicolon, "Expected ';' after return value")?;
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn yield_stmt(&mut self, keyword: Token) -> Result<Stmt, ParseError> {
+        match self.yields.last_mut() {
+            Some(yields) => *yields = true,
+            None => return Err(ParseError::new("Cannot yield outside a function", &keyword)),
+        }
+        let value = if self.match_token(&[TokenKind::Semicolon]) {
+            None
+        } else {
+            Some(*self.expr()?)
+        };
+        self.consume(TokenKind::Semicolon, "Expected ';' after yield value")?;
+        Ok(Stmt::Yield { keyword, value })
+    }
+
+    //for (name in iterable) body
+    fn for_in_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.next().unwrap().clone();
+        self.consume(TokenKind::In, "Expected 'in' after loop variable")?;
+        let iterable = *self.expr()?;
+        self.consume(TokenKind::RightParent, "Expecting ')' after for clauses")?;
+        let body = self.stmt()?;
+        Ok(Stmt::ForIn { name, iterable, body: Box::new(body) })
+    }
+
     fn while_stmt(&mut self) -> Result<Stmt, ParseError> {
         self.consume(TokenKind::LeftParen, "Expecting '(' after 'while'")?;
         let cond = self.expr()?;
@@ -227,25 +409,25 @@ impl Parser {
             if let TokenKind::Semicolon = tk.kind() {
                 let _ = self.next();
                 // println!("Expr: {expr}");
-                return Ok(Stmt::Expr(*expr));
+                Ok(Stmt::Expr(*expr))
             } else {
-                return Err(ParseError::new("Expected ';'", tk));
+                Err(ParseError::new("Expected ';'", tk))
             }
         } else {
             panic!("No EOF was found")
         }
     }
 
-    fn print_stmt(&mut self) -> Result<Stmt, ParseError> {
+    fn print_stmt(&mut self, keyword: Token) -> Result<Stmt, ParseError> {
         let expr = self.expr()?;
         
         if let Some(tk) = self.peek() {
             if let TokenKind::Semicolon = tk.kind() {
                 let _ = self.next();
                 // println!("Print: {expr}");
-                return Ok(Stmt::Print(*expr));
+                Ok(Stmt::Print { keyword, value: *expr })
             } else {
-                return Err(ParseError::new("Expected ';'", tk));
+                Err(ParseError::new("Expected ';'", tk))
             }
         } else {
             panic!("No EOF was found")
@@ -253,16 +435,16 @@ impl Parser {
     }
 
     fn expr(&mut self) -> Result<Box<Expr>, ParseError> {
-        Ok(self.assignment()?)
+        self.assignment()
     }
 
     fn assignment(&mut self) -> Result<Box<Expr>, ParseError> {
         let expr = self.or()?;
         if self.match_token(&[TokenKind::Equal]) {
             let equals = self.next().expect("Couldnt get token");
-            if let Expr::Variable(name) = *expr {
+            if let Expr::Variable { name, .. } = *expr {
                 let value = self.assignment()?;
-                return Ok(Box::new(Expr::Assign { name, value }))
+                return Ok(Box::new(Expr::Assign { name, value, slot: Cell::default() }))
             } else {
                 return Err(ParseError::new("Invalid assignment target", equals))
             }
@@ -341,7 +523,58 @@ impl Parser {
             return Ok(Box::new(Expr::Unary { op, right }))
         }
 
-        Ok(self.primary()?)
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Box<Expr>, ParseError> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.match_token(&[TokenKind::LeftParen]) {
+                let _ = self.next();
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(&[TokenKind::LeftBracket]) {
+                let bracket = self.next().unwrap().clone();
+                let index = self.expr()?;
+                self.consume(TokenKind::RightBracket, "Expected ']' after index")?;
+                expr = Box::new(Expr::Index { object: expr, bracket, index });
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    //callee(positional, ..., name: value, ...)
+    fn finish_call(&mut self, callee: Box<Expr>) -> Result<Box<Expr>, ParseError> {
+        let mut args: Vec<Arg> = Vec::new();
+        if !self.match_token(&[TokenKind::RightParent]) {
+            loop {
+                let named = matches!(self.peek().unwrap().kind(), TokenKind::Identifier(_))
+                    && matches!(self.peek_next().map(|tk| tk.kind()), Some(TokenKind::Colon));
+                let name = if named {
+                    let name = self.next().unwrap().clone();
+                    let _ = self.next();
+                    if args.iter().any(|a| a.name.as_ref().is_some_and(|n| n.lexeme() == name.lexeme())) {
+                        return Err(ParseError::new("Argument given more than once", &name));
+                    }
+                    Some(name)
+                } else {
+                    if args.iter().any(|a| a.name.is_some()) {
+                        return Err(ParseError::new("Positional argument follows a named argument", self.peek().unwrap()));
+                    }
+                    None
+                };
+                let value = *self.expr()?;
+                args.push(Arg { name, value });
+                if !self.match_token(&[TokenKind::Comma]) {
+                    break;
+                }
+                let _ = self.next();
+            }
+        }
+        let paren = self.peek().unwrap().clone();
+        self.consume(TokenKind::RightParent, "Expected ')' after arguments")?;
+        Ok(Box::new(Expr::Call { callee, paren, args }))
     }
 
     fn primary(&mut self) -> Result<Box<Expr>, ParseError> {
@@ -353,14 +586,14 @@ impl Parser {
             },
             TokenKind::Identifier(_) => {
                 let _ = self.next();
-                Ok(Box::new(Expr::Variable(tk)))
+                Ok(Box::new(Expr::Variable { name: tk, slot: Cell::default() }))
             }
             TokenKind::LeftParen => { 
                 let _ = self.next();
                 let expr = self.expr()?;
                 if let Some(tk) = self.peek() {
                     if tk.kind() != &TokenKind::RightParent {
-                        return Err(ParseError::new("Expected a ')' token", &tk))
+                        return Err(ParseError::new("Expected a ')' token", tk))
                     }
                     let _ = self.next();
                 } else {
@@ -369,7 +602,7 @@ impl Parser {
                 
                 Ok(Box::new(Expr::Grouping(expr)))
             },
-            _ => panic!("Wrong primary expression"),
+            _ => Err(ParseError::new("Expected expression", &tk)),
         }
         
     }
@@ -378,6 +611,10 @@ impl Parser {
         self.tokens.get(self.current_idx)
     }
 
+    fn peek_next(&self) -> Option<&Token>{
+        self.tokens.get(self.current_idx + 1)
+    }
+
     fn next(&mut self) -> Option<&Token>{
         let el = self.tokens.get(self.current_idx);
         if el.is_some() {
@@ -406,8 +643,8 @@ impl Parser {
                     let _ = self.next();
                     break;
                 },
-                TokenKind::Class | TokenKind::Fun | TokenKind::Var | TokenKind::For 
-                | TokenKind::If | TokenKind::While | TokenKind::Print | TokenKind::Return => { 
+                TokenKind::Class | TokenKind::Fun | TokenKind::Var | TokenKind::Const | TokenKind::For 
+                | TokenKind::If | TokenKind::While | TokenKind::Print | TokenKind::Return | TokenKind::Yield => { 
                     break;
                 },
                 _ => {
@@ -0,0 +1,170 @@
+//! Execution profiler of the tree-walking interpreter. Rather than sampling,
+//! it times every statement and call while the program runs, charging each
+//! its total time (with what it ran) and its self time (without).
+//!
+//! Recursive calls count their total time once, for the outermost call. A
+//! call in tail position replaces its caller, on the profile as on the stack.
+//! Statements nested on the line of their parent, like `if (x) print x;`,
+//! count as one run of that line. Generator bodies are timed as a whole, but
+//! their `if`, `while` and `for` lines aren't timed on their own.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::symbol::Symbol;
+
+/// What the program spent in a function, or at the top level (`<script>`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionStats {
+    pub name: String,
+    /// Where it is declared; `None` for natives and the top level.
+    pub line: Option<usize>,
+    pub calls: u64,
+    pub total: Duration,
+    pub self_time: Duration,
+}
+
+/// What the program spent on one line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineStats {
+    pub line: usize,
+    pub count: u64,
+    pub total: Duration,
+    pub self_time: Duration,
+}
+
+struct Frame<K> {
+    key: K,
+    start: Instant,
+    // Total time of the frames run from this one
+    children: Duration,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    functions: Vec<FunctionStats>,
+    function_ids: HashMap<(Symbol, Option<usize>), usize>,
+    lines: HashMap<usize, LineStats>,
+    calls: Vec<Frame<usize>>,
+    // Lines, with the calls in progress when they started
+    running: Vec<Frame<(usize, usize)>>,
+    // Self time of each call stack, by the names of its functions
+    stacks: HashMap<String, Duration>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub(crate) fn enter_call(&mut self, name: Symbol, line: Option<usize>) {
+        let next = self.functions.len();
+        let id = *self.function_ids.entry((name, line)).or_insert(next);
+        if id == next {
+            let stats = FunctionStats { name: name.to_string(), line, calls: 0, total: Duration::ZERO, self_time: Duration::ZERO };
+            self.functions.push(stats);
+        }
+        self.functions[id].calls += 1;
+        self.calls.push(Frame { key: id, start: Instant::now(), children: Duration::ZERO });
+    }
+
+    pub(crate) fn exit_call(&mut self) {
+        let Some((id, total, self_time)) = pop(&mut self.calls) else {
+            return;
+        };
+        let mut stack: Vec<_> = self.calls.iter().map(|frame| self.functions[frame.key].name.as_str()).collect();
+        stack.push(&self.functions[id].name);
+        *self.stacks.entry(stack.join(";")).or_default() += self_time;
+
+        let recursive = self.calls.iter().any(|frame| frame.key == id);
+        let stats = &mut self.functions[id];
+        stats.self_time += self_time;
+        if !recursive {
+            stats.total += total;
+        }
+    }
+
+    /// Starts timing a statement on `line`, unless it is nested in one of the
+    /// same line and call. Returns whether it did, and `exit_line` is due.
+    pub(crate) fn enter_line(&mut self, line: usize) -> bool {
+        let key = (line, self.calls.len());
+        if self.running.last().is_some_and(|frame| frame.key == key) {
+            return false;
+        }
+        let stats = self.lines.entry(line).or_insert(LineStats {
+            line,
+            count: 0,
+            total: Duration::ZERO,
+            self_time: Duration::ZERO,
+        });
+        stats.count += 1;
+        self.running.push(Frame { key, start: Instant::now(), children: Duration::ZERO });
+        true
+    }
+
+    pub(crate) fn exit_line(&mut self) {
+        let Some(((line, _), total, self_time)) = pop(&mut self.running) else {
+            return;
+        };
+        let recursive = self.running.iter().any(|frame| frame.key.0 == line);
+        let stats = self.lines.get_mut(&line).unwrap();
+        stats.self_time += self_time;
+        if !recursive {
+            stats.total += total;
+        }
+    }
+
+    /// Functions called, most self time first.
+    pub fn functions(&self) -> Vec<FunctionStats> {
+        let mut functions = self.functions.clone();
+        functions.sort_by(|a, b| b.self_time.cmp(&a.self_time).then(a.name.cmp(&b.name)));
+        functions
+    }
+
+    /// Lines run, most self time first.
+    pub fn lines(&self) -> Vec<LineStats> {
+        let mut lines: Vec<_> = self.lines.values().cloned().collect();
+        lines.sort_by(|a, b| b.self_time.cmp(&a.self_time).then(a.line.cmp(&b.line)));
+        lines
+    }
+
+    /// Both tables, in milliseconds.
+    pub fn table(&self) -> String {
+        let mut table = String::new();
+        let _ = writeln!(table, "{:<24}{:>6}{:>10}{:>14}{:>14}", "function", "line", "calls", "total ms", "self ms");
+        for f in self.functions() {
+            let line = f.line.map(|line| line.to_string()).unwrap_or_default();
+            let _ = writeln!(table, "{:<24}{line:>6}{:>10}{:>14.3}{:>14.3}", f.name, f.calls, ms(f.total), ms(f.self_time));
+        }
+        let _ = writeln!(table);
+        let _ = writeln!(table, "{:<6}{:>10}{:>14}{:>14}", "line", "count", "total ms", "self ms");
+        for l in self.lines() {
+            let _ = writeln!(table, "{:<6}{:>10}{:>14.3}{:>14.3}", l.line, l.count, ms(l.total), ms(l.self_time));
+        }
+        table
+    }
+
+    /// One line per call stack, its functions separated by `;` and followed
+    /// by its self time in microseconds, as flamegraph tools read them.
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+        stacks.iter().map(|(stack, time)| format!("{stack} {}\n", time.as_micros())).collect()
+    }
+}
+
+// Ends the innermost frame, charging its time to its parent. Returns its
+// key, total and self time
+fn pop<K>(stack: &mut Vec<Frame<K>>) -> Option<(K, Duration, Duration)> {
+    let frame = stack.pop()?;
+    let total = frame.start.elapsed();
+    if let Some(parent) = stack.last_mut() {
+        parent.children += total;
+    }
+    Some((frame.key, total, total.saturating_sub(frame.children)))
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
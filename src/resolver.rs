@@ -0,0 +1,188 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use crate::error::compiletime::ParseError;
+use crate::expr::{Expr, Program, Slot, Stmt};
+use crate::symbol::Symbol;
+use crate::token::Token;
+
+/// Static pass run between parsing and interpretation. Resolves every
+/// local variable to its slot, and rejects writes to `const` bindings
+/// whenever the target can be resolved lexically; the rest (e.g. globals
+/// declared on a previous prompt line) is left to the interpreter.
+pub struct Resolver {
+    // One scope per environment the interpreter creates; the first one
+    // holds the globals
+    scopes: Vec<HashMap<Symbol, Binding>>,
+    errors: Vec<ParseError>,
+    function_depth: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Binding {
+    index: usize,
+    constant: bool,
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver {
+            scopes: vec![HashMap::new()],
+            errors: Vec::new(),
+            function_depth: 0,
+        }
+    }
+
+    /// Returns whether the program resolved cleanly; see `errors` otherwise.
+    pub fn resolve(&mut self, program: &Program) -> bool {
+        let resolved = self.resolve_stmts(&program.stmts);
+        program.resolved.set(resolved);
+        resolved
+    }
+
+    pub(crate) fn resolve_stmts(&mut self, stmts: &[Stmt]) -> bool {
+        for stmt in stmts {
+            self.resolve_stmt(stmt);
+        }
+        self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Resolves what follows inside a scope already holding `names` in the
+    /// given slots, like one of a program paused in a debugger.
+    pub fn enter(&mut self, names: &[(usize, Symbol)]) {
+        let bindings = names.iter().map(|&(index, name)| (name, Binding { index, constant: false }));
+        self.scopes.push(bindings.collect());
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) | Stmt::Print { value: e, .. } => self.resolve_expr(e),
+            Stmt::Var { name, initializer, constant, slot, .. } => {
+                if let Some(init) = initializer {
+                    self.resolve_expr(init);
+                }
+                self.declare(name, *constant, slot);
+            },
+            Stmt::Block { stmts, .. } => {
+                self.scopes.push(HashMap::new());
+                for stmt in stmts {
+                    self.resolve_stmt(stmt);
+                }
+                self.scopes.pop();
+            },
+            Stmt::If { condition, then_br, else_br } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_br);
+                if let Some(else_br) = else_br {
+                    self.resolve_stmt(else_br);
+                }
+            },
+            Stmt::While { condition, body } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+            },
+            Stmt::Function(decl) => {
+                // Declared first so the body can call itself
+                self.declare(&decl.name, false, &decl.slot);
+                self.function_depth += 1;
+                self.scopes.push(HashMap::new());
+                for param in &decl.params {
+                    if let Some(default) = &param.default {
+                        self.resolve_expr(default);
+                    }
+                    self.declare(&param.name, false, &Cell::default());
+                }
+                for stmt in &decl.body {
+                    self.resolve_stmt(stmt);
+                }
+                self.scopes.pop();
+                self.function_depth -= 1;
+            },
+            Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            },
+            Stmt::ForIn { name, iterable, body } => {
+                self.resolve_expr(iterable);
+                self.scopes.push(HashMap::new());
+                self.declare(name, false, &Cell::default());
+                self.resolve_stmt(body);
+                self.scopes.pop();
+            },
+            Stmt::Return { keyword, value } => {
+                if self.function_depth == 0 {
+                    self.errors.push(ParseError::new("Cannot return from top-level code", keyword));
+                }
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            },
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) => {},
+            Expr::Variable { name, slot } => slot.set(self.lookup(name).0),
+            Expr::Grouping(e) => self.resolve_expr(e),
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            },
+            Expr::Assign { name, value, slot } => {
+                self.resolve_expr(value);
+                let (resolved, constant) = self.lookup(name);
+                if constant {
+                    self.errors.push(ParseError::new("Cannot assign to a constant", name));
+                }
+                slot.set(resolved);
+            },
+            Expr::Call { callee, args, .. } => {
+                self.resolve_expr(callee);
+                for arg in args {
+                    self.resolve_expr(&arg.value);
+                }
+            },
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            },
+        }
+    }
+
+    // Redeclaring a variable in the same scope reuses its slot
+    fn declare(&mut self, name: &Token, constant: bool, slot: &Cell<Slot>) {
+        let global = self.scopes.len() == 1;
+        let scope = self.scopes.last_mut().unwrap();
+        let index = scope.get(&name.symbol()).map_or(scope.len(), |binding| binding.index);
+        scope.insert(name.symbol(), Binding { index, constant });
+        if !global {
+            slot.set(Slot::Local { depth: 0, index });
+        }
+    }
+
+    // Where `name` lives, and whether it is a constant
+    fn lookup(&self, name: &Token) -> (Slot, bool) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(binding) = scope.get(&name.symbol()) {
+                if depth == self.scopes.len() - 1 {
+                    return (Slot::Global, binding.constant);
+                }
+                return (Slot::Local { depth, index: binding.index }, binding.constant);
+            }
+        }
+        (Slot::Global, false)
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,10 +1,12 @@
-use crate::{token::{Token, TokenKind}, value::Value};
+use crate::{error::compiletime::ParseError, string::Str, token::{Comment, Token, TokenKind}, value::Value};
 use std::str::FromStr;
 
 pub struct Scanner {
     source: StringIter,
     tokens: Vec<Token>,
-    had_error: bool,
+    errors: Vec<ParseError>,
+    // Comments waiting for the next token
+    comments: Vec<Comment>,
 }
 
 struct StringIter {
@@ -12,6 +14,8 @@ struct StringIter {
     start: usize,
     current: usize,
     line: usize,
+    // Where the current line starts
+    line_start: usize,
 }
 
 impl StringIter {
@@ -20,7 +24,8 @@ impl StringIter {
             string, 
             start: 0, 
             current: 0,
-            line: 1 
+            line: 1,
+            line_start: 0,
         }
     }
 
@@ -48,6 +53,12 @@ impl StringIter {
 
     pub fn count_line(&mut self) {
         self.line += 1;
+        self.line_start = self.current;
+    }
+
+    /// Column of the start of the current lexeme.
+    pub fn column(&self) -> usize {
+        self.string[self.line_start..self.start].chars().count() + 1
     }
 
     pub fn slice(&self) -> Option<&str> {
@@ -60,7 +71,8 @@ impl Scanner{
         let mut scanner = Scanner {
             source: StringIter::new(source),
             tokens: Vec::new(),
-            had_error: false,
+            errors: Vec::new(),
+            comments: Vec::new(),
         };
         Self::populate_tokens(&mut scanner);
         scanner
@@ -72,13 +84,34 @@ impl Scanner{
             self.source.update_start();
             self.scan_token();
         }
-        self.tokens.push(self.produce_token(TokenKind::Eof));
+        self.source.update_start();
+        let eof = self.produce_token(TokenKind::Eof).with_comments(std::mem::take(&mut self.comments));
+        self.tokens.push(eof);
     }
 
     pub fn tokens(&mut self) -> &[Token] {
         self.tokens.as_slice()
     }
 
+    /// Characters that don't start any token, and unterminated strings.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Tokens and errors in the order they appear in the source.
+    pub fn scanned(&self) -> Vec<Result<&Token, &ParseError>> {
+        let mut scanned = Vec::with_capacity(self.tokens.len() + self.errors.len());
+        let mut errors = self.errors.iter().peekable();
+        for token in &self.tokens {
+            while let Some(e) = errors.next_if(|e| (e.line(), e.column()) < (token.line(), token.column())) {
+                scanned.push(Err(e));
+            }
+            scanned.push(Ok(token));
+        }
+        scanned.extend(errors.map(Err));
+        scanned
+    }
+
     fn scan_token(&mut self) {
         if let Some(ch) = self.source.next() {
             let token = match ch {
@@ -87,7 +120,19 @@ impl Scanner{
                 '{' => Some(self.produce_token(TokenKind::LeftBrace)),
                 '}' => Some(self.produce_token(TokenKind::RightBrace)),
                 ',' => Some(self.produce_token(TokenKind::Comma)),
-                '.' => Some(self.produce_token(TokenKind::Dot)),
+                ':' => Some(self.produce_token(TokenKind::Colon)),
+                '[' => Some(self.produce_token(TokenKind::LeftBracket)),
+                ']' => Some(self.produce_token(TokenKind::RightBracket)),
+                '.' => {
+                    match (self.source.peek(), self.source.peek_next()) {
+                        (Some('.'), Some('.')) => {
+                            let _ = self.source.next();
+                            let _ = self.source.next();
+                            Some(self.produce_token(TokenKind::Ellipsis))
+                        },
+                        _ => Some(self.produce_token(TokenKind::Dot)),
+                    }
+                },
                 '-' => Some(self.produce_token(TokenKind::Minus)),
                 '+' => Some(self.produce_token(TokenKind::Plus)),
                 ';' => Some(self.produce_token(TokenKind::Semicolon)),
@@ -131,11 +176,12 @@ impl Scanner{
                 '/' => {
                     match self.source.peek() {
                         Some('/') => {
-                            while let Some(ch) = self.source.next() {
-                                if ch == '\n' {
-                                    break
-                                }
+                            // Leave the newline to be counted
+                            while self.source.peek().is_some_and(|ch| ch != '\n') {
+                                let _ = self.source.next();
                             }
+                            let text = self.source.slice().expect("Couldn't get comment").trim_end().to_string();
+                            self.comments.push(Comment { text, line: self.source.line });
                             None
                         },
                         _ => Some(self.produce_token(TokenKind::Slash))
@@ -164,7 +210,7 @@ impl Scanner{
             };
 
             if let Some(token) = token {
-                self.tokens.push(token);
+                self.tokens.push(token.with_comments(std::mem::take(&mut self.comments)));
             }
         }
 
@@ -174,10 +220,11 @@ impl Scanner{
         let literal = match tkind {
             TokenKind::True => Some(Value::Bool(true)),
             TokenKind::False => Some(Value::Bool(false)),
+            TokenKind::Nil => Some(Value::Nil),
             _ => None,
         };
         let lexeme = self.source.slice().expect("Couldn't get lexeme").to_string();
-        Token::new(tkind, literal, lexeme, self.source.line)
+        Token::new(tkind, literal, lexeme, self.source.line, self.source.column())
     }
 
     fn scan_string(&mut self) -> Option<Token> {
@@ -185,15 +232,15 @@ impl Scanner{
         loop {
             match self.source.next() {
                 Some('\n') => {
-                    self.source.count_line();
                     self.error("Expected a terminating \"");
+                    self.source.count_line();
                     return None
                 },
                 Some('"') => {
                     let slice = self.source.slice().expect("Slice returned isn't valid");
                     let slice = &slice[1..slice.len() - 1];
-                    let literal = Some(Value::String(slice.to_string()));
-                    let token = Token::new(TokenKind::String, literal, slice.to_string(), self.source.line);
+                    let literal = Some(Value::String(Str::from(slice)));
+                    let token = Token::new(TokenKind::String, literal, slice.to_string(), self.source.line, self.source.column());
                     return Some(token)
                 }
                 Some(_) => continue,
@@ -228,13 +275,13 @@ impl Scanner{
         }
         let slice = self.source.slice().expect("Got invalid slice at scanning number");
         let num = f64::from_str(slice).expect("Couldnt parse invalid number");
-        let token = Token::new(TokenKind::Number, Some(Value::Number(num)),slice.to_string(),self.source.line);
+        let token = Token::new(TokenKind::Number, Some(Value::Number(num)),slice.to_string(),self.source.line,self.source.column());
         Some(token)
     }
 
     fn scan_ident(&mut self) -> Option<Token> {
         while let Some(c) = self.source.peek() {
-            if c.is_alphanumeric() {
+            if c.is_alphanumeric() || c == '_' {
                 let _ = self.source.next();
             } else {
                 break ;
@@ -246,8 +293,7 @@ impl Scanner{
     }
 
     fn error(&mut self, messg: &str) {
-        self.had_error = true;
-        eprintln!("Error: {}",messg);
+        self.errors.push(ParseError::at(messg, self.source.line, self.source.column()));
     }
 }
 
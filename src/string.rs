@@ -0,0 +1,74 @@
+use std::cell::{Ref, RefCell};
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+/// Immutable string value, cheap to clone.
+///
+/// A `Str` is a prefix of a shared, append-only buffer. Concatenating onto
+/// the value that ends at the buffer's end appends in place, so building a
+/// string with repeated `s = s + piece` is amortized linear: values created
+/// earlier keep seeing their own, shorter prefix. Any other concatenation
+/// copies into a new buffer.
+#[derive(Clone)]
+pub struct Str {
+    buf: Rc<RefCell<String>>,
+    len: usize,
+}
+
+impl Str {
+    pub fn as_str(&self) -> Ref<'_, str> {
+        Ref::map(self.buf.borrow(), |buf| &buf[..self.len])
+    }
+
+    pub fn concat(&self, other: &Str) -> Str {
+        if self.appends_in_place(other) {
+            self.buf.borrow_mut().push_str(&other.as_str());
+            return Str { buf: self.buf.clone(), len: self.len + other.len };
+        }
+        let mut buf = String::with_capacity(self.len + other.len);
+        buf.push_str(&self.as_str());
+        buf.push_str(&other.as_str());
+        Str::from(buf)
+    }
+
+    /// Bytes `concat` allocates for the result.
+    pub fn concat_size(&self, other: &Str) -> usize {
+        match self.appends_in_place(other) {
+            true => other.len,
+            false => self.len + other.len,
+        }
+    }
+
+    fn appends_in_place(&self, other: &Str) -> bool {
+        let at_end = self.len == self.buf.borrow().len();
+        // `s + s` cannot append to the buffer it reads from
+        at_end && !Rc::ptr_eq(&self.buf, &other.buf)
+    }
+}
+
+impl From<String> for Str {
+    fn from(s: String) -> Self {
+        Str { len: s.len(), buf: Rc::new(RefCell::new(s)) }
+    }
+}
+
+impl From<&str> for Str {
+    fn from(s: &str) -> Self {
+        Str::from(s.to_string())
+    }
+}
+
+impl PartialEq for Str {
+    fn eq(&self, other: &Self) -> bool {
+        if Rc::ptr_eq(&self.buf, &other.buf) {
+            return self.len == other.len;
+        }
+        *self.as_str() == *other.as_str()
+    }
+}
+
+impl Display for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &*self.as_str())
+    }
+}
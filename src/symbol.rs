@@ -0,0 +1,56 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+/// An identifier interned by the scanner. Equal names get equal symbols, so
+/// comparing them and indexing tables with them needs no hashing.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Symbol(u32);
+
+// Owns the names, which are shared by both tables and freed with the
+// thread
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&symbol) = interner.symbols.get(name) {
+                return symbol;
+            }
+            let name: Rc<str> = Rc::from(name);
+            let symbol = Symbol(interner.names.len() as u32);
+            interner.names.push(name.clone());
+            interner.symbols.insert(name, symbol);
+            symbol
+        })
+    }
+
+    /// Position in the symbol table, used to index dense tables such as the globals.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub(crate) fn from_index(index: usize) -> Symbol {
+        Symbol(index as u32)
+    }
+
+    pub fn as_str(self) -> Rc<str> {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize].clone())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt::{self,Display};
 
+use crate::symbol::Symbol;
 use crate::value::Value;
 
 #[derive(Clone)]
@@ -8,18 +9,41 @@ pub struct Token {
     pub lexeme: String,
     literal: Option<Value>,
     line: usize,
+    // Counted in characters from 1; 0 for tokens made up by the compiler
+    column: usize,
+    // Comments between the previous token and this one
+    comments: Vec<Comment>,
+}
+
+/// A `//` comment, kept as trivia of the token following it.
+#[derive(Clone)]
+pub struct Comment {
+    // With its leading `//`
+    pub text: String,
+    pub line: usize,
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, literal:Option<Value>, lexeme: String, line: usize) -> Token {
+    pub fn new(kind: TokenKind, literal:Option<Value>, lexeme: String, line: usize, column: usize) -> Token {
         Token {
             kind,
             lexeme,
             literal,
             line,
+            column,
+            comments: Vec::new(),
         }
     }
 
+    pub fn with_comments(mut self, comments: Vec<Comment>) -> Token {
+        self.comments = comments;
+        self
+    }
+
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
     pub fn kind(&self) -> &TokenKind {
         &self.kind
     }
@@ -32,9 +56,21 @@ impl Token {
         self.line
     }
 
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
     pub fn lexeme(&self) -> &str {
         self.lexeme.as_ref()
     }
+
+    /// Interned name of an identifier token.
+    pub fn symbol(&self) -> Symbol {
+        match self.kind {
+            TokenKind::Identifier(symbol) => symbol,
+            _ => unreachable!("'{}' is not an identifier", self.lexeme),
+        }
+    }
 }
 
 impl Display for Token {
@@ -56,8 +92,12 @@ pub enum TokenKind {
     RightParent,
     LeftBrace,
     RightBrace, 
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
+    Ellipsis,
     Minus,
     Plus,
     Semicolon,
@@ -73,17 +113,19 @@ pub enum TokenKind {
     Less,
     LessEqual,
     //Literals
-    Identifier(String),
+    Identifier(Symbol),
     String,
     Number,
     //Keywords
     And,
     Class,
+    Const,
     Else,
     False,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -93,65 +135,83 @@ pub enum TokenKind {
     True,
     Var,
     While,
+    Yield,
     Eof,
 }
 
 impl Display for TokenKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::LeftParen => write!(f,"LEFT_PAREN"),
-            Self::RightParent => write!(f,"RIGHT_PAREN"),
-            Self::LeftBrace => write!(f,"LEFT_BRACE"),
-            Self::RightBrace => write!(f,"RIGHT_BRACE"), 
-            Self::Comma => write!(f,"COMMA"),
-            Self::Dot => write!(f,"DOT"),
-            Self::Minus => write!(f,"MINUS"),
-            Self::Plus => write!(f,"PLUS"),
-            Self::Semicolon => write!(f,"SEMICOLON"),
-            Self::Slash => write!(f,"SLASH"),
-            Self::Star => write!(f,"STAR"),
-            Self::Bang => write!(f,"BANG"),
-            Self::BangEqual => write!(f,"BANG_EQUAL"),
-            Self::Equal => write!(f,"EQUAL"),
-            Self::EqualEqual => write!(f,"EQUAL_EQUAL"),
-            Self::Greater => write!(f,"GREATER"),
-            Self::GreaterEqual => write!(f,"GREATER_EQUAL"),
-            Self::Less => write!(f,"LESS"),
-            Self::LessEqual => write!(f,"LESS_EQUAL"),
             Self::Identifier(s) => write!(f,"IDENTIFIER ({})", s),
-            Self::String => write!(f,"STRING"),
-            Self::Number => write!(f,"NUMBER"),
-            Self::And => write!(f,"AND"),
-            Self::Class => write!(f,"CLASS"),
-            Self::Else => write!(f,"ELSE"),
-            Self::False => write!(f,"FALSE"),
-            Self::Fun => write!(f,"FUN"),
-            Self::For => write!(f,"FOR"),
-            Self::If => write!(f,"IF"),
-            Self::Nil => write!(f,"NIL"),
-            Self::Or => write!(f,"OR"),
-            Self::Print => write!(f,"PRINT"),
-            Self::Return => write!(f,"RETURN"),
-            Self::Super => write!(f,"SUPER"),
-            Self::This => write!(f,"THIS"),
-            Self::True => write!(f,"TRUE"),
-            Self::Var => write!(f,"VAR"),
-            Self::While => write!(f,"WHILE"),
-            Self::Eof => write!(f,"EOF"),
+            kind => write!(f, "{}", kind.name()),
         }
     }
 }
 
 impl TokenKind {
+    /// Name of the kind alone, without the identifier's name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::LeftParen => "LEFT_PAREN",
+            Self::RightParent => "RIGHT_PAREN",
+            Self::LeftBrace => "LEFT_BRACE",
+            Self::RightBrace => "RIGHT_BRACE",
+            Self::LeftBracket => "LEFT_BRACKET",
+            Self::RightBracket => "RIGHT_BRACKET",
+            Self::Comma => "COMMA",
+            Self::Colon => "COLON",
+            Self::Dot => "DOT",
+            Self::Ellipsis => "ELLIPSIS",
+            Self::Minus => "MINUS",
+            Self::Plus => "PLUS",
+            Self::Semicolon => "SEMICOLON",
+            Self::Slash => "SLASH",
+            Self::Star => "STAR",
+            Self::Bang => "BANG",
+            Self::BangEqual => "BANG_EQUAL",
+            Self::Equal => "EQUAL",
+            Self::EqualEqual => "EQUAL_EQUAL",
+            Self::Greater => "GREATER",
+            Self::GreaterEqual => "GREATER_EQUAL",
+            Self::Less => "LESS",
+            Self::LessEqual => "LESS_EQUAL",
+            Self::Identifier(_) => "IDENTIFIER",
+            Self::String => "STRING",
+            Self::Number => "NUMBER",
+            Self::And => "AND",
+            Self::Class => "CLASS",
+            Self::Const => "CONST",
+            Self::Else => "ELSE",
+            Self::False => "FALSE",
+            Self::Fun => "FUN",
+            Self::For => "FOR",
+            Self::If => "IF",
+            Self::In => "IN",
+            Self::Nil => "NIL",
+            Self::Or => "OR",
+            Self::Print => "PRINT",
+            Self::Return => "RETURN",
+            Self::Super => "SUPER",
+            Self::This => "THIS",
+            Self::True => "TRUE",
+            Self::Var => "VAR",
+            Self::While => "WHILE",
+            Self::Yield => "YIELD",
+            Self::Eof => "EOF",
+        }
+    }
+
     pub fn from_ident(ident: &str) -> TokenKind {
         match ident {
             "and" => Self::And,
             "class" => Self::Class,
+            "const" => Self::Const,
             "else" => Self::Else,
             "false" => Self::False,
             "for" => Self::For,
             "fun" => Self::Fun,
             "if" => Self::If,
+            "in" => Self::In,
             "nil" => Self::Nil,
             "or" => Self::Or,
             "print" => Self::Print,
@@ -161,7 +221,8 @@ impl TokenKind {
             "true" => Self::True,
             "var" => Self::Var,
             "while" => Self::While,
-            ident => Self::Identifier(ident.to_string()),
+            "yield" => Self::Yield,
+            ident => Self::Identifier(Symbol::intern(ident)),
         }
     }
 
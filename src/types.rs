@@ -0,0 +1,69 @@
+use std::fmt::{self, Display};
+
+use crate::token::{Token, TokenKind};
+use crate::value::Value;
+
+/// Static type of an optional annotation (`var x: number = 1;`).
+/// `Any` opts out of checking, and is what unannotated bindings get.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Type {
+    Number,
+    String,
+    Bool,
+    Nil,
+    Function,
+    List,
+    Generator,
+    Any,
+}
+
+impl Type {
+    pub fn from_token(tk: &Token) -> Option<Type> {
+        match tk.kind() {
+            TokenKind::Nil => Some(Type::Nil),
+            TokenKind::Fun => Some(Type::Function),
+            TokenKind::Identifier(name) => match &*name.as_str() {
+                "number" => Some(Type::Number),
+                "string" => Some(Type::String),
+                "bool" => Some(Type::Bool),
+                "list" => Some(Type::List),
+                "generator" => Some(Type::Generator),
+                "any" => Some(Type::Any),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn of(value: &Value) -> Type {
+        match value {
+            Value::Number(_) => Type::Number,
+            Value::String(_) => Type::String,
+            Value::Bool(_) => Type::Bool,
+            Value::Nil => Type::Nil,
+            Value::Function(_) | Value::Native(_) | Value::Closure(_) => Type::Function,
+            Value::List(_) => Type::List,
+            Value::Generator(_) => Type::Generator,
+        }
+    }
+
+    /// Whether a value of type `other` can be stored where `self` is expected.
+    pub fn accepts(&self, other: Type) -> bool {
+        *self == Type::Any || other == Type::Any || *self == other
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number => write!(f, "number"),
+            Self::String => write!(f, "string"),
+            Self::Bool => write!(f, "bool"),
+            Self::Nil => write!(f, "nil"),
+            Self::Function => write!(f, "fun"),
+            Self::List => write!(f, "list"),
+            Self::Generator => write!(f, "generator"),
+            Self::Any => write!(f, "any"),
+        }
+    }
+}
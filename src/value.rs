@@ -1,11 +1,23 @@
-use std::fmt::Display;
+use std::cell::RefCell;
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
+
+use crate::function::{Function, NativeFunction};
+use crate::generator::Generator;
+use crate::string::Str;
+use crate::vm::Closure;
 
 #[derive(PartialEq, Clone)]
 pub enum Value {
     Number (f64),
-    String (String),
+    String (Str),
     Bool (bool),
     Nil,
+    Function (Rc<Function>),
+    Native (Rc<NativeFunction>),
+    Closure (Rc<Closure>),
+    List (Rc<RefCell<Vec<Value>>>),
+    Generator (Rc<RefCell<Generator>>),
 }
 
 impl Display for Value {
@@ -14,13 +26,38 @@ impl Display for Value {
             Self::Number(n) => {write!(f,"{}",n)},
             Self::String(s) => {write!(f, "\"{}\"",s)},
             Self::Bool(b) => {write!(f, "{}", b)},
-            Self::Nil => {write!(f, "nil")}
+            Self::Nil => {write!(f, "nil")},
+            Self::Function(fun) => {write!(f, "{}", fun)},
+            Self::Native(fun) => {write!(f, "{}", fun)},
+            Self::Closure(fun) => {write!(f, "{}", fun)},
+            Self::Generator(gen) => {write!(f, "{}", gen.borrow())},
+            Self::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
         }
     }
 }
 
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+// Errors of the operators, which the checker reports in the same words
+pub(crate) const NUMBER_OPERAND: &str = "Operand must be a number";
+pub(crate) const NUMBER_OPERANDS: &str = "Operands must be numbers";
+pub(crate) const SUM_OPERANDS: &str = "Operands must be both numbers or strings";
+
 impl Value {
-    fn bool(&self) -> bool {
+    pub fn bool(&self) -> bool {
         match self {
             Self::Bool(b) => *b,
             Self::Nil => false,
@@ -38,7 +75,7 @@ impl Value {
             *n = -*n;
             Ok(())
         } else {
-            Err("Operand must be a number")
+            Err(NUMBER_OPERAND)
         }
     }
 
@@ -49,7 +86,7 @@ impl Value {
                 Ok(())
             },
             _ => {
-                Err("operands must be numbers")
+                Err(NUMBER_OPERANDS)
             }
         }
     }
@@ -61,7 +98,7 @@ impl Value {
                 Ok(())
             },
             _ => {
-                Err("operands must be numbers")
+                Err(NUMBER_OPERANDS)
             }
         }
     }
@@ -73,7 +110,7 @@ impl Value {
                 Ok(())
             },
             _ => {
-                Err("operands must be numbers")
+                Err(NUMBER_OPERANDS)
             }
         }
     }
@@ -84,14 +121,21 @@ impl Value {
                 Ok(())
             },
             (Value::String(a), Value::String(b)) => {
-                a.push_str(b);
+                *a = a.concat(b);
                 Ok(())
             },
             _ => {
-                Err("operands must be both numbers or strings")
+                Err(SUM_OPERANDS)
             }
         }
     }
+    /// Bytes `try_sum` allocates.
+    pub fn sum_size(&self, operand: &Value) -> usize {
+        match (self, operand) {
+            (Value::String(a), Value::String(b)) => a.concat_size(b),
+            _ => 0,
+        }
+    }
     pub fn try_lt(&self, operand: &Value) -> Result<Value, &'static str> {
         match (self, operand) {
             (Value::Number(a), Value::Number(b)) => {
@@ -99,7 +143,7 @@ impl Value {
                 Ok(Value::Bool(b))
             },
             _ => {
-                Err("operands must be numbers")
+                Err(NUMBER_OPERANDS)
             }
         }
     }
@@ -110,7 +154,7 @@ impl Value {
                 Ok(Value::Bool(b))
             },
             _ => {
-                Err("operands must be numbers")
+                Err(NUMBER_OPERANDS)
             }
         }
     }
@@ -121,7 +165,7 @@ impl Value {
                 Ok(Value::Bool(b))
             },
             _ => {
-                Err("operands must be numbers")
+                Err(NUMBER_OPERANDS)
             }
         }
     }
@@ -132,7 +176,7 @@ impl Value {
                 Ok(Value::Bool(b))
             },
             _ => {
-                Err("operands must be numbers")
+                Err(NUMBER_OPERANDS)
             }
         }
     }
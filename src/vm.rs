@@ -0,0 +1,645 @@
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::budget::{Budget, Limits};
+use crate::chunk::{Chunk, Op, Proto};
+use crate::compiler::{Compiler, NO_NAMES};
+use crate::environment::Globals;
+use crate::error::runtime::EvalError;
+use crate::expr::Program;
+use crate::function::{arity_messg, natives, NativeError, Runtime, DEFAULT_MAX_CALL_DEPTH};
+use crate::gc::{self, Heap, ObjectId, Trace};
+use crate::interrupt::Interrupt;
+use crate::generator::{Generator, GeneratorBody, GeneratorState};
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::value::Value;
+
+/// A compiled function together with the variables it captured.
+pub struct Closure {
+    pub(crate) proto: Rc<Proto>,
+    upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.proto.token.lexeme())
+    }
+}
+
+/// A captured variable: still in its stack slot while the declaring
+/// function runs, moved into the upvalue once that slot goes away.
+enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Stack index of the first parameter
+    base: usize,
+    // Parameters with a default that the caller did not pass
+    missing: Vec<bool>,
+}
+
+/// A generator frame taken off the VM while it is not running. Upvalues
+/// pointing into its stack are closed on suspension and reopened, at the
+/// new position, on resumption.
+pub struct Suspended {
+    frame: CallFrame,
+    stack: Vec<Value>,
+    upvalues: Vec<(usize, Rc<RefCell<Upvalue>>)>,
+}
+
+impl Suspended {
+    pub fn trace(&self, visit: &mut dyn FnMut(ObjectId)) {
+        visit(gc::id(&self.frame.closure));
+        for value in &self.stack {
+            gc::trace_value(value, visit);
+        }
+        for (_, upvalue) in &self.upvalues {
+            visit(gc::id(upvalue));
+        }
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, visit: &mut dyn FnMut(ObjectId)) -> bool {
+        for upvalue in &self.upvalues {
+            visit(gc::id(upvalue));
+        }
+        true
+    }
+
+    // Immutable: freed once the upvalues it captured are cleared
+    fn clear(&self) {}
+}
+
+impl Trace for RefCell<Upvalue> {
+    fn trace(&self, visit: &mut dyn FnMut(ObjectId)) -> bool {
+        let Ok(upvalue) = self.try_borrow() else { return false };
+        if let Upvalue::Closed(value) = &*upvalue {
+            gc::trace_value(value, visit);
+        }
+        true
+    }
+
+    fn clear(&self) {
+        if let Upvalue::Closed(value) = &mut *self.borrow_mut() {
+            *value = Value::Nil;
+        }
+    }
+}
+
+enum Stop {
+    Return,
+    Yield(Value, Suspended),
+}
+
+/// Stack-based virtual machine running the bytecode produced by `Compiler`.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: Globals,
+    // Upvalues still pointing into the stack
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    heap: Heap,
+    max_depth: usize,
+    budget: Budget,
+    out: Box<dyn Write>,
+    diagnostics: Box<dyn Write>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        let mut globals = Globals::default();
+        for native in natives() {
+            let name = Symbol::intern(native.name());
+            globals.define(name, Value::Native(Rc::new(native)), false);
+        }
+        Vm {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            open_upvalues: Vec::new(),
+            heap: Heap::new(),
+            max_depth: DEFAULT_MAX_CALL_DEPTH,
+            budget: Budget::default(),
+            out: Box::new(io::stdout()),
+            diagnostics: Box::new(io::stderr()),
+        }
+    }
+
+    /// As [`Interpreter::set_max_call_depth`](crate::interpreter::Interpreter::set_max_call_depth).
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// As [`Interpreter::set_limits`](crate::interpreter::Interpreter::set_limits).
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget.set_limits(limits);
+    }
+
+    /// As [`Interpreter::interrupt_handle`](crate::interpreter::Interpreter::interrupt_handle).
+    pub fn interrupt_handle(&self) -> Interrupt {
+        self.budget.interrupt().clone()
+    }
+
+    /// As [`Interpreter::set_output`](crate::interpreter::Interpreter::set_output).
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        self.out = out;
+    }
+
+    /// As [`Interpreter::set_diagnostics`](crate::interpreter::Interpreter::set_diagnostics).
+    pub fn set_diagnostics(&mut self, diagnostics: Box<dyn Write>) {
+        self.diagnostics = diagnostics;
+    }
+
+    /// As [`Interpreter::interpret`](crate::interpreter::Interpreter::interpret).
+    pub fn interpret(&mut self, program: Program) -> Result<(), EvalError> {
+        let script = match Compiler::new().compile(&program.stmts) {
+            Ok(script) => script,
+            Err(errors) => {
+                for e in errors {
+                    let _ = writeln!(self.diagnostics, "{e}");
+                }
+                return Ok(());
+            },
+        };
+        self.budget.start(self.heap.bytes());
+        let closure = self.heap.alloc(Closure { proto: script, upvalues: Vec::new() });
+        self.frames.push(CallFrame { closure: closure.clone(), ip: 0, base: 0, missing: Vec::new() });
+        loop {
+            match self.run(0) {
+                Ok(_) => break,
+                Err(e) => {
+                    let ip = self.frames.first().map_or(0, |f| f.ip);
+                    self.close_upvalues(0);
+                    self.stack.clear();
+                    self.frames.clear();
+                    if let Some(e) = self.budget.stopped() {
+                        return Err(e);
+                    }
+                    let _ = writeln!(self.diagnostics, "{e}");
+                    // Like the tree-walking interpreter, carry on with the
+                    // next top-level statement
+                    let starts = &closure.proto.chunk.stmt_starts;
+                    let Some(&next) = starts.iter().find(|&&start| start >= ip) else {
+                        break;
+                    };
+                    self.frames.push(CallFrame { closure: closure.clone(), ip: next, base: 0, missing: Vec::new() });
+                },
+            }
+        }        Ok(())
+    }
+
+    // Runs until the frame at index `depth` returns or yields
+    fn run(&mut self, depth: usize) -> Result<Stop, EvalError> {
+        loop {
+            let (op, base) = {
+                let frame = self.frames.last_mut().unwrap();
+                let op = frame.closure.proto.chunk.code[frame.ip];
+                frame.ip += 1;
+                (op, frame.base)
+            };
+            self.budget.step(self.heap.bytes())?;
+            match op {
+                Op::Constant(i) => {
+                    let value = self.chunk().constants[i as usize].clone();
+                    self.stack.push(value);
+                },
+                Op::Nil => self.stack.push(Value::Nil),
+                Op::True => self.stack.push(Value::Bool(true)),
+                Op::False => self.stack.push(Value::Bool(false)),
+                Op::Pop => {
+                    self.stack.pop();
+                },
+                Op::GetLocal(slot) => {
+                    let value = self.stack[base + slot as usize].clone();
+                    self.stack.push(value);
+                },
+                Op::SetLocal(slot) => {
+                    let value = self.peek().clone();
+                    self.stack[base + slot as usize] = value;
+                },
+                Op::GetGlobal(name) => {
+                    let value = match self.globals.get(name) {
+                        Ok(value) => value.clone(),
+                        Err(messg) => return Err(self.error(messg)),
+                    };
+                    self.stack.push(value);
+                },
+                Op::SetGlobal(name) => {
+                    let value = self.peek().clone();
+                    if let Err(messg) = self.globals.assign(name, value) {
+                        return Err(self.error(messg));
+                    }
+                },
+                Op::DefineGlobal { name, constant } => {
+                    let value = self.stack.pop().unwrap();
+                    self.globals.define(name, value, constant);
+                },
+                Op::GetUpvalue(i) => {
+                    let upvalue = self.frames.last().unwrap().closure.upvalues[i as usize].clone();
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                },
+                Op::SetUpvalue(i) => {
+                    let value = self.peek().clone();
+                    let upvalue = self.frames.last().unwrap().closure.upvalues[i as usize].clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                },
+                Op::Equal => {
+                    let right = self.stack.pop().unwrap();
+                    let mut left = self.stack.pop().unwrap();
+                    self.stack.push(left.equals(&right));
+                },
+                Op::NotEqual => {
+                    let right = self.stack.pop().unwrap();
+                    let mut left = self.stack.pop().unwrap();
+                    self.stack.push(left.neq(&right));
+                },
+                Op::Greater => self.compare(Value::try_gt)?,
+                Op::GreaterEqual => self.compare(Value::try_gte)?,
+                Op::Less => self.compare(Value::try_lt)?,
+                Op::LessEqual => self.compare(Value::try_lte)?,
+                Op::Add => {
+                    let size = self.stack[self.stack.len() - 2].sum_size(self.peek());
+                    self.heap.account(size);
+                    self.arithmetic(Value::try_sum)?;
+                },
+                Op::Subtract => self.arithmetic(Value::try_sub)?,
+                Op::Multiply => self.arithmetic(Value::try_mult)?,
+                Op::Divide => self.arithmetic(Value::try_div)?,
+                Op::Not => {
+                    let value = self.stack.pop().unwrap();
+                    self.stack.push(value.inv());
+                },
+                Op::Negate => {
+                    let mut value = self.stack.pop().unwrap();
+                    if let Err(messg) = value.try_neg() {
+                        return Err(self.error(messg));
+                    }
+                    self.stack.push(value);
+                },
+                Op::Print => {
+                    let value = self.stack.pop().unwrap();
+                    let _ = writeln!(self.out, "{value}");
+                },
+                Op::Jump(to) | Op::Loop(to) => self.frames.last_mut().unwrap().ip = to as usize,
+                Op::JumpIfFalse(to) => {
+                    if !self.peek().bool() {
+                        self.frames.last_mut().unwrap().ip = to as usize;
+                    }
+                },
+                Op::Call { argc, names } => self.call(argc as usize, names, false)?,
+                Op::TailCall { argc, names } => self.call(argc as usize, names, true)?,
+                Op::Index => {
+                    let index = self.stack.pop().unwrap();
+                    let object = self.stack.pop().unwrap();
+                    let item = match (object, index) {
+                        (Value::List(items), Value::Number(n)) => {
+                            let items = items.borrow();
+                            if n.fract() != 0.0 || n < 0.0 || n as usize >= items.len() {
+                                return Err(self.error("List index out of range"));
+                            }
+                            items[n as usize].clone()
+                        },
+                        (Value::List(_), _) => return Err(self.error("List index must be a number")),
+                        _ => return Err(self.error("Only lists can be indexed")),
+                    };
+                    self.stack.push(item);
+                },
+                Op::Closure(i) => {
+                    let proto = self.chunk().protos[i as usize].clone();
+                    let enclosing = self.frames.last().unwrap().closure.clone();
+                    let upvalues = proto.upvalues.iter().map(|desc| {
+                        if desc.is_local {
+                            self.capture_upvalue(base + desc.index as usize)
+                        } else {
+                            enclosing.upvalues[desc.index as usize].clone()
+                        }
+                    }).collect();
+                    let closure = self.heap.alloc(Closure { proto, upvalues });
+                    self.stack.push(Value::Closure(closure));
+                },
+                Op::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                },
+                Op::Return => {
+                    let result = self.stack.pop().unwrap();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == depth {
+                        return Ok(Stop::Return);
+                    }
+                    self.stack.push(result);
+                },
+                Op::DefaultArg { param, skip } => {
+                    let frame = self.frames.last_mut().unwrap();
+                    if !frame.missing[param as usize] {
+                        frame.ip = skip as usize;
+                    }
+                },
+                Op::GeneratorStart => {
+                    let token = self.frames.last().unwrap().closure.proto.token.clone();
+                    let suspended = self.suspend();
+                    let gen = Generator { token, state: GeneratorState::Suspended(GeneratorBody::Vm(suspended)) };
+                    let gen = self.heap.alloc(RefCell::new(gen));
+                    self.stack.push(Value::Generator(gen));
+                },
+                Op::Yield => {
+                    let value = self.stack.pop().unwrap();
+                    return Ok(Stop::Yield(value, self.suspend()));
+                },
+                Op::IterInit => {
+                    if !matches!(self.peek(), Value::List(_) | Value::Generator(_)) {
+                        return Err(self.error("Can only iterate over lists and generators"));
+                    }
+                },
+                Op::ForNext { slot, exit } => {
+                    let slot = base + slot as usize;
+                    let item = match self.stack[slot].clone() {
+                        Value::List(items) => {
+                            let Value::Number(next) = self.stack[slot + 1] else { unreachable!() };
+                            self.stack[slot + 1] = Value::Number(next + 1.0);
+                            items.borrow().get(next as usize).cloned()
+                        },
+                        Value::Generator(gen) => self.resume_generator(&gen)?,
+                        _ => unreachable!(),
+                    };
+                    match item {
+                        Some(item) => self.stack.push(item),
+                        None => self.frames.last_mut().unwrap().ip = exit as usize,
+                    }
+                },
+            }
+        }
+    }
+
+    // A tail call replaces the calling frame when the callee is a closure
+    fn call(&mut self, argc: usize, names: u16, tail: bool) -> Result<(), EvalError> {
+        let callee_pos = self.stack.len() - argc - 1;
+        let names = match names {
+            NO_NAMES => Vec::new(),
+            i => self.chunk().arg_names[i as usize].clone(),
+        };
+        match self.stack[callee_pos].clone() {
+            Value::Closure(closure) => self.call_closure(closure, callee_pos, names, tail),
+            Value::Native(fun) => {
+                if let Some(name) = names.first() {
+                    return Err(EvalError::new("Native functions only take positional arguments", name));
+                }
+                if argc != fun.arity() {
+                    return Err(self.error(&arity_messg(fun.arity(), Some(fun.arity()), argc)));
+                }
+                let args = self.stack.split_off(callee_pos + 1);
+                self.stack.pop();
+                match fun.call(self, &args) {
+                    Ok(value) => {
+                        self.stack.push(value);
+                        Ok(())
+                    },
+                    Err(NativeError::Message(messg)) => Err(self.error(&messg)),
+                    Err(NativeError::Eval(e)) => Err(e),
+                }
+            },
+            _ => Err(self.error("Can only call functions")),
+        }
+    }
+
+    fn call_closure(&mut self, closure: Rc<Closure>, callee_pos: usize, names: Vec<Token>, tail: bool) -> Result<(), EvalError> {
+        // The script's frame is not a call
+        if !tail && self.frames.len() > self.max_depth {
+            return Err(self.error("Stack overflow"));
+        }
+        let params = &closure.proto.params;
+        let fixed = params.iter().filter(|p| !p.variadic).count();
+        let variadic = fixed < params.len();
+        let args = self.stack.split_off(callee_pos + 1);
+        self.stack.pop();
+        let got = args.len();
+        let positional = got - names.len();
+
+        let min = params.iter().filter(|p| !p.has_default && !p.variadic).count();
+        let max = if variadic { None } else { Some(params.len()) };
+        let mut slots: Vec<Option<Value>> = vec![None; fixed];
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        for (i, value) in args.by_ref().take(positional).enumerate() {
+            if i < fixed {
+                slots[i] = Some(value);
+            } else if variadic {
+                rest.push(value);
+            } else {
+                return Err(self.error(&arity_messg(min, max, got)));
+            }
+        }
+        for (name, value) in names.iter().zip(args) {
+            match params.iter().position(|p| !p.variadic && p.name == name.lexeme()) {
+                Some(i) if slots[i].is_some() => {
+                    return Err(EvalError::new("Argument given more than once", name));
+                },
+                Some(i) => slots[i] = Some(value),
+                None => return Err(EvalError::new("Unknown parameter name", name)),
+            }
+        }
+
+        let mut missing = vec![false; params.len()];
+        for (i, param) in params.iter().enumerate() {
+            if param.variadic {
+                self.heap.account(rest.len() * std::mem::size_of::<Value>());
+                let items = self.heap.alloc(RefCell::new(std::mem::take(&mut rest)));
+                self.stack.push(Value::List(items));
+                continue;
+            }
+            match slots[i].take() {
+                Some(value) => self.stack.push(value),
+                None if param.has_default => {
+                    missing[i] = true;
+                    self.stack.push(Value::Nil);
+                },
+                None => {
+                    self.stack.truncate(callee_pos);
+                    return Err(self.error(&arity_messg(min, max, got)));
+                },
+            }
+        }
+        let mut base = callee_pos;
+        if tail {
+            let caller = self.frames.pop().unwrap();
+            self.close_upvalues(caller.base);
+            self.stack.drain(caller.base..callee_pos);
+            base = caller.base;
+        }
+        self.frames.push(CallFrame { closure, ip: 0, base, missing });
+        Ok(())
+    }
+
+    fn resume_generator(&mut self, gen: &Rc<RefCell<Generator>>) -> Result<Option<Value>, EvalError> {
+        if self.frames.len() > self.max_depth {
+            return Err(EvalError::new("Stack overflow", &gen.borrow().token));
+        }
+        let suspended = {
+            let mut gen = gen.borrow_mut();
+            match std::mem::replace(&mut gen.state, GeneratorState::Running) {
+                GeneratorState::Suspended(GeneratorBody::Vm(suspended)) => suspended,
+                GeneratorState::Suspended(GeneratorBody::Tree { .. }) => unreachable!("generator created by another backend"),
+                GeneratorState::Done => {
+                    gen.state = GeneratorState::Done;
+                    return Ok(None);
+                },
+                GeneratorState::Running => {
+                    return Err(EvalError::new("Generator is already running", &gen.token));
+                },
+            }
+        };
+
+        let depth = self.frames.len();
+        let base = self.stack.len();
+        let Suspended { mut frame, stack, upvalues } = suspended;
+        self.stack.extend(stack);
+        for (offset, upvalue) in upvalues {
+            let slot = base + offset;
+            let closed = std::mem::replace(&mut *upvalue.borrow_mut(), Upvalue::Open(slot));
+            if let Upvalue::Closed(value) = closed {
+                self.stack[slot] = value;
+            }
+            self.open_upvalues.push(upvalue);
+        }
+        frame.base = base;
+        self.frames.push(frame);
+
+        let result = self.run(depth);
+        let mut gen = gen.borrow_mut();
+        match result {
+            Ok(Stop::Yield(value, suspended)) => {
+                gen.state = GeneratorState::Suspended(GeneratorBody::Vm(suspended));
+                Ok(Some(value))
+            },
+            Ok(Stop::Return) => {
+                gen.state = GeneratorState::Done;
+                Ok(None)
+            },
+            Err(e) => {
+                gen.state = GeneratorState::Done;
+                self.close_upvalues(base);
+                self.stack.truncate(base);
+                self.frames.truncate(depth);
+                Err(e)
+            },
+        }
+    }
+
+    // Takes the innermost frame off the VM, along with its stack slots
+    fn suspend(&mut self) -> Suspended {
+        let frame = self.frames.pop().unwrap();
+        let mut upvalues = Vec::new();
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match &*upvalue.borrow() {
+                Upvalue::Open(slot) if *slot >= frame.base => *slot,
+                _ => return true,
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            upvalues.push((slot - frame.base, upvalue.clone()));
+            false
+        });
+        let stack = self.stack.split_off(frame.base);
+        Suspended { frame, stack, upvalues }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self.open_upvalues.iter().find(|upvalue| {
+            matches!(&*upvalue.borrow(), Upvalue::Open(open) if *open == slot)
+        });
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
+        }
+        let upvalue = self.heap.alloc(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    // Moves every captured variable at or above `from` off the stack
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match &*upvalue.borrow() {
+                Upvalue::Open(slot) if *slot >= from => *slot,
+                _ => return true,
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            false
+        });
+    }
+
+    fn arithmetic(&mut self, op: fn(&mut Value, &Value) -> Result<(), &'static str>) -> Result<(), EvalError> {
+        let right = self.stack.pop().unwrap();
+        let left = self.stack.last_mut().unwrap();
+        if let Err(messg) = op(left, &right) {
+            return Err(self.error(messg));
+        }
+        Ok(())
+    }
+
+    fn compare(&mut self, op: fn(&Value, &Value) -> Result<Value, &'static str>) -> Result<(), EvalError> {
+        let right = self.stack.pop().unwrap();
+        let left = self.stack.pop().unwrap();
+        match op(&left, &right) {
+            Ok(value) => {
+                self.stack.push(value);
+                Ok(())
+            },
+            Err(messg) => Err(self.error(messg)),
+        }
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().unwrap()
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self.frames.last().unwrap().closure.proto.chunk
+    }
+
+    // Error located at the instruction being executed
+    fn error(&self, messg: &str) -> EvalError {
+        let frame = self.frames.last().unwrap();
+        EvalError::new(messg, frame.closure.proto.chunk.token(frame.ip - 1))
+    }
+}
+
+impl Runtime for Vm {
+    fn resume(&mut self, gen: &Rc<RefCell<Generator>>) -> Result<Option<Value>, EvalError> {
+        self.resume_generator(gen)
+    }
+
+    fn collect(&mut self) -> usize {
+        self.heap.collect()
+    }
+}
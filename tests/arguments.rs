@@ -0,0 +1,87 @@
+//! Binds default, named and variadic arguments, and reports calls that don't
+//! match the parameters, on both backends.
+
+mod common;
+
+use common::run;
+
+#[test]
+fn binds_default_arguments() {
+    let source = "fun show(...values) { print values; }
+fun f(a, b = a * 2, c = b + 1) { show(a, b, c); }
+f(1);
+f(1, 5);
+f(1, 5, 0);
+var calls = 0;
+fun count() { calls = calls + 1; return calls; }
+fun g(n = count()) { return n; }
+show(g(), g(), g(10), calls);
+";
+    assert_eq!(run(source), ("[1, 2, 3]\n[1, 5, 6]\n[1, 5, 0]\n[1, 2, 10, 2]\n".to_string(), String::new()));
+}
+
+#[test]
+fn binds_named_arguments() {
+    let source = "fun show(...values) { print values; }
+fun f(a, b = 10, c = 20) { show(a, b, c); }
+f(c: 1, a: 2);
+f(1, c: 3);
+f(b: 0, a: 1, c: 2);
+";
+    assert_eq!(run(source), ("[2, 10, 1]\n[1, 10, 3]\n[1, 0, 2]\n".to_string(), String::new()));
+}
+
+#[test]
+fn collects_variadic_arguments() {
+    let source = "fun show(...values) { print values; }
+fun f(a, ...rest) { show(a, rest, len(rest)); }
+f(1);
+f(1, 2, 3);
+fun g(a = 0, ...rest) { show(a, rest); }
+g();
+g(a: 5);
+";
+    assert_eq!(run(source), ("[1, [], 0]\n[1, [2, 3], 2]\n[0, []]\n[5, []]\n".to_string(), String::new()));
+}
+
+#[test]
+fn reports_wrong_numbers_of_arguments() {
+    let source = "fun exact(a, b) {}
+fun ranged(a, b = 1) {}
+fun variadic(a, ...rest) {}
+exact(1);
+exact(1, 2, 3);
+ranged();
+ranged(1, 2, 3);
+variadic();
+ranged(b: 2);
+";
+    let (out, errors) = run(source);
+    assert_eq!(out, "");
+    assert_eq!(errors, "[line 4] at ')': Expected 2 arguments but got 1
+[line 5] at ')': Expected 2 arguments but got 3
+[line 6] at ')': Expected 1 to 2 arguments but got 0
+[line 7] at ')': Expected 1 to 2 arguments but got 3
+[line 8] at ')': Expected at least 1 arguments but got 0
+[line 9] at ')': Expected 1 to 2 arguments but got 1
+");
+}
+
+#[test]
+fn reports_unknown_and_repeated_names() {
+    let source = "fun f(a, b = 1, ...rest) {}
+f(c: 1);
+f(rest: 1);
+f(1, a: 2);
+";
+    let (out, errors) = run(source);
+    assert_eq!(out, "");
+    assert_eq!(errors, "[line 2] at 'c': Unknown parameter name
+[line 3] at 'rest': Unknown parameter name
+[line 4] at 'a': Argument given more than once
+");
+    let (_, errors) = run("fun f(a) {}\nf(a: 1, a: 2);\n");
+    assert_eq!(errors, "[line 2] at 'a': Argument given more than once\n");
+    let (_, errors) = run("fun f(a, b) {}\nf(a: 1, 2);\n");
+    assert_eq!(errors, "[line 2] at '2': Positional argument follows a named argument\n");
+}
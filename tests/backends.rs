@@ -0,0 +1,53 @@
+//! Runs every program in `tests/programs` with both backends, with and
+//! without optimisations, and checks that they all print what the `.out`
+//! file next to the program holds, and report the errors of its `.err`
+//! file, if any.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn run(program: &Path, backend: &str, level: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rlux"))
+        .arg(format!("--backend={backend}"))
+        .arg(level)
+        .arg(program)
+        .output()
+        .expect("Couldn't run rlux")
+}
+
+#[test]
+fn backends_agree() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut programs: Vec<_> = fs::read_dir(dir)
+        .expect("Couldn't read tests/programs")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lux"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty());
+
+    for program in programs {
+        let tree = run(&program, "tree", "-O0");
+        let name = program.display();
+        assert!(tree.status.success(), "{name} failed with the tree backend");
+        let expected = fs::read_to_string(program.with_extension("out")).expect("Couldn't read the expected output");
+        assert_eq!(String::from_utf8_lossy(&tree.stdout), format!("Running file\n{expected}"), "{name}: unexpected output");
+        let expected = fs::read_to_string(program.with_extension("err")).unwrap_or_default();
+        assert_eq!(String::from_utf8_lossy(&tree.stderr), expected, "{name}: unexpected errors");
+        for (backend, level) in [("vm", "-O0"), ("tree", "-O1"), ("vm", "-O1")] {
+            let other = run(&program, backend, level);
+            assert_eq!(
+                String::from_utf8_lossy(&tree.stdout),
+                String::from_utf8_lossy(&other.stdout),
+                "{name}: output differs with {backend} {level}",
+            );
+            assert_eq!(
+                String::from_utf8_lossy(&tree.stderr),
+                String::from_utf8_lossy(&other.stderr),
+                "{name}: errors differ with {backend} {level}",
+            );
+            assert_eq!(tree.status.code(), other.status.code(), "{name}: exit code differs with {backend} {level}");
+        }
+    }
+}
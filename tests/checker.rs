@@ -0,0 +1,87 @@
+//! Type-checks an annotated program, then breaks each rule of the checker.
+
+mod common;
+
+use std::process::Command;
+
+use rlux::checker::Checker;
+
+use common::parse;
+
+fn errors(source: &str) -> Vec<String> {
+    let mut checker = Checker::new();
+    let typed = checker.check(&parse(source));
+    let errors: Vec<_> = checker.errors().iter().map(|e| e.to_string()).collect();
+    assert_eq!(typed, errors.is_empty());
+    errors
+}
+
+#[test]
+fn passes_annotated_programs() {
+    let source = "var n: number = 1;
+var s: string = \"a\" + \"b\";
+var anything = nil;
+anything = 2;
+fun repeat(text: string, times: number = 2): string {
+  var out: string = \"\";
+  for (var i: number = 0; i < times; i = i + 1) out = out + text;
+  return out;
+}
+fun sum(...nums): number {
+  var total: number = 0;
+  for (n in nums) total = total + n;
+  return total;
+}
+var r: string = repeat(times: n, text: s);
+var ok: bool = -n < sum(1, 2) and !false;
+print r + anything;
+";
+    assert_eq!(errors(source), Vec::<String>::new());
+}
+
+#[test]
+fn reports_mismatched_declarations() {
+    assert_eq!(errors("var n: number = \"1\";"), [
+        "[line 1] at 'n': Cannot initialize variable of type number with a value of type string",
+    ]);
+    assert_eq!(errors("var n: number = 1;\nn = true;"), [
+        "[line 2] at 'n': Cannot assign a value of type bool to variable of type number",
+    ]);
+    assert_eq!(errors("fun f(a: string = 1) {}"), [
+        "[line 1] at 'a': Default value of type number does not match parameter of type string",
+    ]);
+    assert_eq!(errors("fun f(): number {\n  return \"a\";\n}"), [
+        "[line 2] at 'return': Cannot return a value of type string from a function returning number",
+    ]);
+}
+
+#[test]
+fn reports_operands_in_the_interpreters_words() {
+    assert_eq!(errors("print -\"a\";"), ["[line 1] at '-': Operand must be a number"]);
+    assert_eq!(errors("print \"a\" * 2;"), ["[line 1] at '*': Operands must be numbers"]);
+    assert_eq!(errors("print true < 2;"), ["[line 1] at '<': Operands must be numbers"]);
+    assert_eq!(errors("print 1 + \"a\";"), ["[line 1] at '+': Operands must be both numbers or strings"]);
+}
+
+#[test]
+fn reports_calls_indexes_and_loops() {
+    assert_eq!(errors("var n: number = 1;\nn();"), ["[line 2] at ')': Can only call functions"]);
+    assert_eq!(errors("fun f(a: number) {}\nf(\"a\");\nf(a: nil);"), [
+        "[line 2] at ')': Argument of type string does not match parameter 'a' of type number",
+        "[line 3] at 'a': Argument of type nil does not match parameter 'a' of type number",
+    ]);
+    assert_eq!(errors("var s: string = \"ab\";\nprint s[0];"), ["[line 2] at '[': Only lists can be indexed"]);
+    assert_eq!(errors("fun f(l: list) {\n  return l[\"a\"];\n}"), ["[line 2] at '[': List index must be a number"]);
+    assert_eq!(errors("for (x in 3) print x;"), ["[line 1] at 'x': Can only iterate over lists and generators"]);
+}
+
+#[test]
+fn check_exits_with_65_on_syntax_errors() {
+    let path = std::env::temp_dir().join(format!("rlux-check-{}.lux", std::process::id()));
+    std::fs::write(&path, "print 1 +;\nprint \"ran\";\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rlux")).arg("check").arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "[line 1] at ';': Expected expression\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+}
@@ -0,0 +1,80 @@
+//! Helpers shared by the integration tests, each of which uses some of them.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rlux::Program;
+use rlux::parser::Parser;
+use rlux::resolver::Resolver;
+use rlux::scanner::Scanner;
+
+// Tests run in parallel, each with its own file
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes `source` to a file of its own in the temporary directory.
+pub fn temp_file(source: &str) -> PathBuf {
+    let file = FILES.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("rlux-{}-{file}.lux", std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+/// Runs `source` with `rlux` on `backend`, passing `flags` before the file.
+pub fn rlux(source: &str, backend: &str, flags: &[&str]) -> Output {
+    let path = temp_file(source);
+    let output = Command::new(env!("CARGO_BIN_EXE_rlux"))
+        .arg(format!("--backend={backend}"))
+        .args(flags)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    output
+}
+
+/// What `source` prints and reports, which must be the same on both backends.
+pub fn run(source: &str) -> (String, String) {
+    let outputs: Vec<_> = ["tree", "vm"].iter().map(|backend| {
+        let output = rlux(source, backend, &[]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stdout = stdout.strip_prefix("Running file\n").unwrap().to_string();
+        (stdout, String::from_utf8_lossy(&output.stderr).into_owned())
+    }).collect();
+    assert_eq!(outputs[0], outputs[1], "the backends disagree on {source:?}");
+    outputs[0].clone()
+}
+
+/// Parses and resolves `source`, which must have no errors.
+pub fn parse(source: &str) -> Program {
+    let mut scanner = Scanner::new(source.to_string());
+    let mut parser = Parser::new(scanner.tokens().to_vec());
+    let program = parser.program();
+    assert!(scanner.errors().is_empty() && parser.errors().is_empty());
+    assert!(Resolver::new().resolve(&program));
+    program
+}
+
+/// A buffer that stays readable after being handed out.
+#[derive(Clone, Default)]
+pub struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! Rejects assignments to constants, before running when the resolver can
+//! see them and at runtime otherwise.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use rlux::parser::Parser;
+use rlux::resolver::Resolver;
+use rlux::scanner::Scanner;
+use rlux::Lux;
+
+fn resolve(source: &str) -> Vec<String> {
+    let mut scanner = Scanner::new(source.to_string());
+    let mut parser = Parser::new(scanner.tokens().to_vec());
+    let program = parser.program();
+    assert!(parser.errors().is_empty());
+    let mut resolver = Resolver::new();
+    let resolved = resolver.resolve(&program);
+    let errors: Vec<_> = resolver.errors().iter().map(|e| e.to_string()).collect();
+    assert_eq!(resolved, errors.is_empty());
+    errors
+}
+
+#[test]
+fn rejects_assignments_while_resolving() {
+    assert_eq!(resolve("const x = 1;\nx = 2;"), ["[line 2] at 'x': Cannot assign to a constant"]);
+    assert_eq!(resolve("fun f() {\n  const x = 1;\n  fun g() { x = 2; }\n}"), [
+        "[line 3] at 'x': Cannot assign to a constant",
+    ]);
+    // Shadowing and redeclaring are allowed
+    assert_eq!(resolve("const x = 1;\n{ var x = 2; x = 3; }\nvar x = 4;\nx = 5;"), Vec::<String>::new());
+    assert_eq!(resolve("const x = 1;\nconst x = 2;"), Vec::<String>::new());
+}
+
+#[test]
+fn rejects_assignments_at_runtime() {
+    let mut lux = Lux::new();
+    lux.eval("const x = 1;").unwrap();
+    // The resolver of the second program doesn't know `x` is a constant
+    let e = lux.eval("x = 2;").unwrap_err();
+    assert_eq!(e.to_string(), "[line 1] at 'x': Cannot assign to a constant");
+    assert_eq!(lux.eval("x;").unwrap().to_string(), "1");
+
+    for backend in ["tree", "vm"] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rlux"))
+            .arg(format!("--backend={backend}"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"const x = 1;\nx = 2;\nprint x;\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "[line 1] at 'x': Cannot assign to a constant\n");
+        assert!(String::from_utf8_lossy(&output.stdout).ends_with("> 1\n> "), "{backend}");
+    }
+}
+
+#[test]
+fn exits_with_65_when_a_constant_is_assigned() {
+    let path = std::env::temp_dir().join(format!("rlux-constants-{}.lux", std::process::id()));
+    std::fs::write(&path, "const x = 1;\nprint x;\nx = 2;\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rlux")).arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "[line 3] at 'x': Cannot assign to a constant\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Running file\n");
+}
@@ -0,0 +1,111 @@
+//! Converts between Rust types and Lux values, and calls typed host functions.
+
+use std::collections::HashMap;
+
+use rlux::{FromValue, IntoValue, Lux, Str, Value};
+
+fn longer(s: String, n: f64) -> bool {
+    s.chars().count() as f64 > n
+}
+
+fn eval_err(lux: &mut Lux, source: &str) -> String {
+    lux.eval(source).unwrap_err().to_string()
+}
+
+#[test]
+fn registers_typed_functions() {
+    let mut lux = Lux::new();
+    lux.register_fn("longer", longer).unwrap();
+    lux.register_fn("answer", || 42).unwrap();
+    lux.register_fn("sum", |items: Vec<f64>| items.iter().sum::<f64>()).unwrap();
+    lux.register_fn("greet", |name: Option<String>| format!("hi {}", name.as_deref().unwrap_or("there"))).unwrap();
+
+    assert_eq!(lux.eval("longer(\"abcd\", 3);").unwrap(), Value::Bool(true));
+    assert_eq!(lux.eval("answer();").unwrap(), Value::Number(42.0));
+    assert_eq!(lux.eval("fun list(...items) { return items; } sum(list(1, 2, 3));").unwrap(), Value::Number(6.0));
+    assert_eq!(lux.eval("greet(nil);").unwrap().to_string(), "\"hi there\"");
+    assert_eq!(lux.eval("greet(\"lux\");").unwrap().to_string(), "\"hi lux\"");
+}
+
+#[test]
+fn reports_mismatched_arguments() {
+    let mut lux = Lux::new();
+    lux.register_fn("longer", longer).unwrap();
+    lux.register_fn("byte", |b: u8| b).unwrap();
+    lux.register_fn("sum", |items: Vec<f64>| items.iter().sum::<f64>()).unwrap();
+    lux.eval("fun list(...items) { return items; }").unwrap();
+
+    assert_eq!(eval_err(&mut lux, "longer(1, 2);"), "[line 1] at ')': Argument 1: expected string but got number");
+    assert_eq!(eval_err(&mut lux, "longer(\"a\");"), "[line 1] at ')': Expected 2 arguments but got 1");
+    assert_eq!(eval_err(&mut lux, "byte(1.5);"), "[line 1] at ')': Argument 1: expected integer from 0 to 255 but got 1.5");
+    assert_eq!(eval_err(&mut lux, "byte(256);"), "[line 1] at ')': Argument 1: expected integer from 0 to 255 but got 256");
+    assert_eq!(eval_err(&mut lux, "byte(nil);"), "[line 1] at ')': Argument 1: expected integer but got nil");
+    assert_eq!(eval_err(&mut lux, "sum(list(1, \"2\"));"), "[line 1] at ')': Argument 1: item 1: expected number but got string");
+}
+
+#[test]
+fn raises_errors_of_fallible_functions() {
+    let mut lux = Lux::new();
+    lux.register_fn("root", |n: f64| {
+        if n < 0.0 {
+            return Err(format!("Cannot take the root of {n}"));
+        }
+        Ok(n.sqrt())
+    }).unwrap();
+    assert_eq!(lux.eval("root(9);").unwrap(), Value::Number(3.0));
+    assert_eq!(eval_err(&mut lux, "root(-1);"), "[line 1] at ')': Cannot take the root of -1");
+}
+
+#[test]
+fn converts_collections() {
+    let mut lux = Lux::new();
+    lux.set_global("names", vec!["a", "b"]).unwrap();
+    lux.set_global("maybe", None::<f64>).unwrap();
+    let ages = HashMap::from([("bob".to_string(), 40), ("al".to_string(), 30)]);
+    lux.set_global("ages", ages.clone()).unwrap();
+
+    assert_eq!(lux.eval("names[1] + \"!\";").unwrap().to_string(), "\"b!\"");
+    assert_eq!(lux.eval("maybe;").unwrap(), Value::Nil);
+    assert_eq!(lux.eval("ages;").unwrap().to_string(), "[[\"al\", 30], [\"bob\", 40]]");
+
+    let names = lux.get_global("names").unwrap();
+    assert_eq!(Vec::<String>::from_value(&names).unwrap(), ["a", "b"]);
+    let back = HashMap::<String, u32>::from_value(&lux.get_global("ages").unwrap()).unwrap();
+    assert_eq!(back, ages);
+    assert_eq!(Option::<f64>::from_value(&Value::Nil).unwrap(), None);
+    assert_eq!(i32::from_value(&(-7i64).into_value()).unwrap(), -7);
+    assert_eq!(
+        HashMap::<String, f64>::from_value(&names).unwrap_err(),
+        "item 0: expected [key, value] pair but got string",
+    );
+}
+
+#[test]
+fn converts_integers_up_to_their_bounds() {
+    let two = |bits: i32| Value::Number(2f64.powi(bits));
+    // The largest numbers below 2^64 and 2^63 that an f64 holds
+    assert_eq!(u64::from_value(&Value::Number(2f64.powi(64) - 2048.0)).unwrap(), u64::MAX - 2047);
+    assert_eq!(i64::from_value(&Value::Number(2f64.powi(63) - 1024.0)).unwrap(), i64::MAX - 1023);
+    assert_eq!(u64::from_value(&two(64)).unwrap_err(), "expected integer from 0 to 18446744073709551615 but got 18446744073709552000");
+    assert!(i64::from_value(&two(63)).is_err());
+    assert!(usize::from_value(&two(usize::BITS as i32)).is_err());
+    assert_eq!(i64::from_value(&Value::Number(-(2f64.powi(63)))).unwrap(), i64::MIN);
+    assert!(i64::from_value(&Value::Number(-(2f64.powi(63)) - 2048.0)).is_err());
+
+    assert_eq!(i32::from_value(&Value::Number(2147483647.0)).unwrap(), i32::MAX);
+    assert_eq!(i32::from_value(&Value::Number(-2147483648.0)).unwrap(), i32::MIN);
+    assert!(i32::from_value(&two(31)).is_err());
+    assert!(i32::from_value(&Value::Number(-2147483649.0)).is_err());
+    assert_eq!(u8::from_value(&Value::Number(255.0)).unwrap(), 255);
+    assert!(u8::from_value(&Value::Number(-1.0)).is_err());
+}
+
+#[test]
+fn shares_string_arguments() {
+    let mut lux = Lux::new();
+    lux.register_fn("count", |s: Str| s.as_str().chars().count()).unwrap();
+    lux.register_fn("same", |s: Str| s).unwrap();
+    assert_eq!(lux.eval("count(\"héllo\");").unwrap(), Value::Number(5.0));
+    assert_eq!(lux.eval("same(\"a\" + \"b\");").unwrap().to_string(), "\"ab\"");
+    assert_eq!(eval_err(&mut lux, "count(1);"), "[line 1] at ')': Argument 1: expected string but got number");
+}
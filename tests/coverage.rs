@@ -0,0 +1,94 @@
+//! Records lines and branches of programs run with coverage, and writes them
+//! as lcov.
+
+mod common;
+
+use std::io;
+
+use rlux::coverage::{Branch, Coverage};
+use rlux::interpreter::Interpreter;
+
+use common::parse;
+
+const PROGRAM: &str = "\
+fun sign(n) {
+  if (n < 0) return -1;
+  if (n == 0 and false) return 0;
+  return 1;
+}
+var i = 0;
+while (i < 3) {
+  print sign(i) or nil;
+  i = i + 1;
+}
+if (false) {
+  print \"never\";
+}
+";
+
+fn cover(source: &str) -> Interpreter {
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(io::sink()));
+    interp.set_coverage(Coverage::new());
+    interp.interpret(parse(source)).unwrap();
+    interp
+}
+
+#[test]
+fn counts_lines_and_branches() {
+    let interp = cover(PROGRAM);
+    let coverage = interp.coverage().unwrap();
+    // Line 12 is never reached; line 5 and the braces hold no statement
+    assert_eq!(coverage.lines(), [(1, 1), (2, 3), (3, 3), (4, 3), (6, 1), (7, 1), (8, 3), (9, 3), (11, 1), (12, 0)]);
+    let branch = |line, block, taken| Branch { line, block, taken };
+    assert_eq!(coverage.branches(), [
+        branch(2, 0, [0, 3]),
+        // The `if`, then its `and`
+        branch(3, 0, [0, 3]),
+        branch(3, 1, [1, 2]),
+        // Three times round the loop, then out
+        branch(7, 0, [3, 1]),
+        branch(8, 0, [0, 3]),
+        branch(11, 0, [0, 1]),
+    ]);
+}
+
+#[test]
+fn writes_lcov() {
+    let interp = cover("var a = 1;\nif (a > 1 or a < 0) {\n  a = 2;\n}\nfun unused() {\n  print a;\n}\n");
+    assert_eq!(interp.coverage().unwrap().lcov("/src/a.lux"), "\
+TN:
+SF:/src/a.lux
+BRDA:2,0,0,0
+BRDA:2,0,1,1
+BRDA:2,1,0,1
+BRDA:2,1,1,0
+BRF:4
+BRH:2
+DA:1,1
+DA:2,1
+DA:3,0
+DA:5,1
+DA:6,0
+LF:5
+LH:3
+end_of_record
+");
+}
+
+#[test]
+fn branches_never_reached_are_marked() {
+    let interp = cover("fun f(x) {\n  while (x) x = false;\n}\n");
+    let lcov = interp.coverage().unwrap().lcov("f.lux");
+    assert!(lcov.contains("BRDA:2,0,0,-\nBRDA:2,0,1,-\nBRF:2\nBRH:0\n"));
+}
+
+#[test]
+fn covers_generators() {
+    let interp = cover("fun count(n) {\n  var i = 0;\n  while (i < n) {\n    if (i > 0) yield i;\n    i = i + 1;\n  }\n}\nfor (x in count(3)) print x;\n");
+    let coverage = interp.coverage().unwrap();
+    // Line 4 runs its `if` three times and its `yield` twice
+    assert_eq!(coverage.lines(), [(1, 1), (2, 1), (3, 1), (4, 5), (5, 3), (8, 3)]);
+    let taken: Vec<_> = coverage.branches().iter().map(|branch| (branch.line, branch.taken)).collect();
+    assert_eq!(taken, [(3, [3, 1]), (4, [2, 1])]);
+}
@@ -0,0 +1,171 @@
+//! Drives `rlux dap` through whole debugging sessions of framed requests.
+
+mod common;
+
+use std::io::Cursor;
+
+use rlux::dap;
+use rlux::json::Json;
+
+use common::{temp_file, Buffer};
+
+const PROGRAM: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+print x;
+print add(x, 10);
+";
+
+/// Sends `requests`, numbered from 1, about a program with `source`, and
+/// returns every message of the adapter.
+fn session(source: &str, requests: &[(&str, String)]) -> Vec<Json> {
+    let path = temp_file(source);
+    let program = Json::from(path.to_str().unwrap()).to_string();
+
+    let mut input = String::new();
+    for (seq, (command, arguments)) in requests.iter().enumerate() {
+        let arguments = arguments.replace("$PROGRAM", &program);
+        let request = format!(r#"{{"seq":{},"type":"request","command":"{command}","arguments":{arguments}}}"#, seq + 1);
+        input += &format!("Content-Length: {}\r\n\r\n{request}", request.len());
+    }
+    let output = Buffer::default();
+    dap::serve(Cursor::new(input.into_bytes()), output.clone()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let output = output.text();
+    let mut rest = output.as_str();
+    let mut messages = Vec::new();
+    while let Some((header, body)) = rest.split_once("\r\n\r\n") {
+        let len: usize = header.trim_start_matches("Content-Length: ").parse().unwrap();
+        messages.push(Json::parse(&body[..len]).unwrap());
+        rest = &body[len..];
+    }
+    messages
+}
+
+fn str<'a>(json: &'a Json, key: &str) -> &'a str {
+    json.get(key).and_then(Json::as_str).unwrap()
+}
+
+fn response<'a>(messages: &'a [Json], command: &str) -> &'a Json {
+    messages.iter().find(|m| str(m, "type") == "response" && str(m, "command") == command).unwrap()
+}
+
+fn events<'a>(messages: &'a [Json], event: &str) -> Vec<&'a Json> {
+    messages.iter().filter(|m| str(m, "type") == "event" && str(m, "event") == event).map(|m| m.get("body").unwrap()).collect()
+}
+
+fn output(messages: &[Json]) -> String {
+    events(messages, "output").iter().filter(|body| str(body, "category") == "stdout").map(|body| str(body, "output")).collect()
+}
+
+fn start(extra: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+    let mut requests = vec![
+        ("initialize", r#"{"adapterID":"rlux"}"#.to_string()),
+        ("launch", r#"{"program":$PROGRAM}"#.to_string()),
+        ("setBreakpoints", r#"{"source":{"path":$PROGRAM},"breakpoints":[{"line":3}]}"#.to_string()),
+        ("configurationDone", "{}".to_string()),
+    ];
+    requests.extend(extra.iter().map(|&(command, arguments)| (command, arguments.to_string())));
+    requests
+}
+
+#[test]
+fn runs_to_completion_without_breakpoints() {
+    let messages = session(PROGRAM, &[
+        ("initialize", "{}".to_string()),
+        ("launch", r#"{"program":$PROGRAM}"#.to_string()),
+        ("configurationDone", "{}".to_string()),
+        ("disconnect", "{}".to_string()),
+    ]);
+    let capabilities = response(&messages, "initialize").get("body").unwrap();
+    assert_eq!(capabilities.get("supportsConditionalBreakpoints"), Some(&Json::Bool(true)));
+    assert_eq!(events(&messages, "initialized").len(), 1);
+    assert!(events(&messages, "stopped").is_empty());
+    assert_eq!(output(&messages), "3\n13\n");
+    assert_eq!(events(&messages, "exited")[0].get("exitCode"), Some(&Json::Number(0.0)));
+    assert_eq!(events(&messages, "terminated").len(), 1);
+}
+
+#[test]
+fn stops_at_breakpoints_and_shows_scopes() {
+    let messages = session(PROGRAM, &start(&[
+        ("threads", "{}"),
+        ("stackTrace", r#"{"threadId":1}"#),
+        ("scopes", r#"{"frameId":0}"#),
+        ("variables", r#"{"variablesReference":1}"#),
+        ("variables", r#"{"variablesReference":2}"#),
+        ("pause", r#"{"threadId":1}"#),
+        ("continue", r#"{"threadId":1}"#),
+        ("continue", r#"{"threadId":1}"#),
+        ("disconnect", "{}"),
+    ]));
+    let stopped = events(&messages, "stopped");
+    assert_eq!(stopped.len(), 2);
+    assert_eq!(str(stopped[0], "reason"), "breakpoint");
+
+    let frame = &response(&messages, "stackTrace").get("body").unwrap().get("stackFrames").unwrap().as_array().unwrap()[0];
+    assert_eq!(frame.get("line"), Some(&Json::Number(3.0)));
+
+    let scopes = response(&messages, "scopes").get("body").unwrap().get("scopes").unwrap().as_array().unwrap();
+    let names: Vec<_> = scopes.iter().map(|scope| str(scope, "name")).collect();
+    assert_eq!(names, ["Locals", "Globals"]);
+
+    let variables: Vec<_> = messages.iter()
+        .filter(|m| str(m, "type") == "response" && str(m, "command") == "variables")
+        .map(|m| {
+            let vars = m.get("body").unwrap().get("variables").unwrap().as_array().unwrap();
+            vars.iter().map(|var| format!("{}={}", str(var, "name"), str(var, "value"))).collect::<Vec<_>>().join(" ")
+        })
+        .collect();
+    assert_eq!(variables, ["a=1 b=2 sum=3", "add=<fn add>"]);
+    let pause = response(&messages, "pause");
+    assert_eq!(pause.get("success"), Some(&Json::Bool(false)));
+    assert_eq!(str(pause, "message"), "Unsupported request 'pause'");
+    assert_eq!(output(&messages), "3\n13\n");
+}
+
+#[test]
+fn steps_and_evaluates() {
+    let messages = session(PROGRAM, &start(&[
+        ("evaluate", r#"{"expression":"sum * 2","frameId":0}"#),
+        ("evaluate", r#"{"expression":"sum = 30"}"#),
+        ("evaluate", r#"{"expression":"missing"}"#),
+        ("stepOut", r#"{"threadId":1}"#),
+        ("next", r#"{"threadId":1}"#),
+        ("terminate", "{}"),
+    ]));
+    let results: Vec<_> = messages.iter()
+        .filter(|m| str(m, "type") == "response" && str(m, "command") == "evaluate")
+        .map(|m| match m.get("success") {
+            Some(Json::Bool(true)) => str(m.get("body").unwrap(), "result").to_string(),
+            _ => str(m, "message").to_string(),
+        })
+        .collect();
+    assert_eq!(results, ["6", "30", "[line 1] at 'missing': Variable is not defined"]);
+
+    let stopped = events(&messages, "stopped");
+    let reasons: Vec<_> = stopped.iter().map(|body| str(body, "reason")).collect();
+    assert_eq!(reasons, ["breakpoint", "step", "step"]);
+    // The assignment took effect before `x` was printed, and terminating
+    // stopped the program before the second call
+    assert_eq!(output(&messages), "30\n");
+    assert_eq!(events(&messages, "exited")[0].get("exitCode"), Some(&Json::Number(130.0)));
+}
+
+#[test]
+fn launching_a_broken_program_fails() {
+    let messages = session("print (;", &[
+        ("initialize", "{}".to_string()),
+        ("launch", r#"{"program":$PROGRAM,"stopOnEntry":true}"#.to_string()),
+        ("configurationDone", "{}".to_string()),
+        ("disconnect", "{}".to_string()),
+    ]);
+    let launch = response(&messages, "launch");
+    assert_eq!(launch.get("success"), Some(&Json::Bool(false)));
+    assert!(str(launch, "message").starts_with("[line 1]"));
+    assert!(events(&messages, "exited").is_empty());
+}
@@ -0,0 +1,148 @@
+//! Steps through programs with scripted debuggers, and through the console
+//! of `rlux debug`.
+
+mod common;
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use rlux::debugger::{Breakpoint, Console, Debugger, Paused, Reason, Resume};
+use rlux::interpreter::Interpreter;
+use rlux::{ErrorKind, Value};
+
+use common::{parse, Buffer};
+
+const PROGRAM: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+for (var i = 0; i < 3; i = i + 1) {
+  x = x + i;
+}
+print x;
+";
+
+type Setup = Box<dyn FnMut(&mut Paused<'_>)>;
+
+/// Resumes with each of `script` in turn, then quits, after running the
+/// optional `setup` on the first pause.
+struct Script {
+    script: Vec<Resume>,
+    setup: Option<Setup>,
+    stops: Rc<RefCell<Vec<(usize, Reason, usize)>>>,
+}
+
+impl Debugger for Script {
+    fn paused(&mut self, paused: &mut Paused<'_>) -> Resume {
+        if let Some(mut setup) = self.setup.take() {
+            setup(paused);
+        }
+        self.stops.borrow_mut().push((paused.line(), paused.reason(), paused.depth()));
+        if self.script.is_empty() { Resume::Quit } else { self.script.remove(0) }
+    }
+}
+
+fn stops(script: Vec<Resume>, setup: Option<Setup>) -> Vec<(usize, Reason, usize)> {
+    let stops = Rc::new(RefCell::new(Vec::new()));
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(io::sink()));
+    interp.set_debugger(Box::new(Script { script, setup, stops: stops.clone() }));
+    let _ = interp.interpret(parse(PROGRAM));
+    stops.take()
+}
+
+#[test]
+fn steps_into_over_and_out() {
+    use Resume::*;
+    let into = stops(vec![StepInto, StepInto, StepInto, StepOut], None);
+    let lines: Vec<_> = into.iter().map(|&(line, _, depth)| (line, depth)).collect();
+    // Out of `add`, on to the line after its call
+    assert_eq!(lines, [(1, 0), (5, 0), (2, 1), (3, 1), (6, 0)]);
+    assert_eq!(into[0].1, Reason::Entry);
+
+    let over = stops(vec![StepOver, StepOver, StepOver, StepOver], None);
+    let lines: Vec<_> = over.iter().map(|&(line, _, _)| line).collect();
+    assert_eq!(lines, [1, 5, 6, 7, 6]);
+}
+
+#[test]
+fn stops_at_conditional_breakpoints() {
+    let setup = |paused: &mut Paused<'_>| {
+        paused.breakpoints().push(Breakpoint { line: 7, condition: Some("i == 2".to_string()) });
+        paused.breakpoints().push(Breakpoint { line: 2, condition: None });
+    };
+    let stops = stops(vec![Resume::Continue, Resume::Continue, Resume::Continue], Some(Box::new(setup)));
+    let stops: Vec<_> = stops.iter().map(|&(line, reason, _)| (line, reason)).collect();
+    assert_eq!(stops, [(1, Reason::Entry), (2, Reason::Breakpoint), (7, Reason::Breakpoint)]);
+}
+
+#[test]
+fn inspects_and_changes_variables() {
+    struct Inspect(Rc<RefCell<Vec<String>>>);
+    impl Debugger for Inspect {
+        fn paused(&mut self, paused: &mut Paused<'_>) -> Resume {
+            if paused.line() != 3 {
+                return Resume::StepInto;
+            }
+            let mut seen = self.0.borrow_mut();
+            for scope in paused.scopes() {
+                let vars: Vec<_> = scope.iter().map(|(name, value)| format!("{name}={value}")).collect();
+                seen.push(vars.join(" "));
+            }
+            seen.push(paused.eval("sum * 10").unwrap().to_string());
+            paused.eval("sum = 100").unwrap();
+            seen.push(paused.eval("nope").unwrap_err().to_string());
+            Resume::Continue
+        }
+    }
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(io::sink()));
+    interp.set_debugger(Box::new(Inspect(seen.clone())));
+    interp.interpret(parse(PROGRAM)).unwrap();
+    assert_eq!(*seen.borrow(), ["a=1 b=2 sum=3", "add=<fn add>", "30", "[line 1] at 'nope': Variable is not defined"]);
+    // The assignment took effect: 100, plus 0, 1 and 2
+    assert_eq!(interp.global("x"), Some(Value::Number(103.0)));
+}
+
+#[test]
+fn quitting_interrupts_the_program() {
+    let mut interp = Interpreter::new();
+    let out = Buffer::default();
+    interp.set_output(Box::new(out.clone()));
+    interp.set_debugger(Box::new(Console::new(PROGRAM, "next\nquit\n".as_bytes(), io::sink())));
+    let e = interp.interpret(parse(PROGRAM)).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Interrupted);
+    assert_eq!(out.text(), "");
+}
+
+#[test]
+fn console_session() {
+    let commands = "break 7 if i == 1\ncontinue\nvars\nprint x + i\ndelete 7\nbogus\ncontinue\n";
+    let output = Buffer::default();
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(output.clone()));
+    interp.set_debugger(Box::new(Console::new(PROGRAM, commands.as_bytes(), output.clone())));
+    interp.interpret(parse(PROGRAM)).unwrap();
+    assert_eq!(output.text(), "\
+Stopped at line 1
+   1 | fun add(a, b) {
+(rlux) Breakpoint at line 7 if i == 1
+(rlux) Breakpoint at line 7
+   7 |   x = x + i;
+(rlux) scope 0:
+scope 1:
+scope 2:
+  i = 1
+globals:
+  add = <fn add>
+  x = 3
+(rlux) 4
+(rlux) (rlux) Unknown command 'bogus', try 'help'
+(rlux) 6
+");
+}
@@ -0,0 +1,155 @@
+//! Checks the `--tokens` and `--dump-ast` output of the command line.
+
+use std::fs;
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rlux::json::{self, Json};
+use rlux::parser::Parser;
+use rlux::scanner::Scanner;
+
+const PROGRAM: &str = "var x: number = -1 + 2;
+fun greet(name, ...rest) { print \"hi \" + name; }
+if (x > 0 and true) greet(name: \"lux\"); else x = nil;
+";
+
+// Tests run in parallel, each with its own file
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+fn dump(source: &str, flag: &str) -> Output {
+    let file = FILES.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("rlux-dump-{}-{file}.lux", std::process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rlux")).arg(flag).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+// Runs `rlux flag` on a long program, reading only the start of its output
+fn dump_to_closed_pipe(flag: &str) -> Output {
+    let file = FILES.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("rlux-dump-{}-{file}.lux", std::process::id()));
+    fs::write(&path, PROGRAM.repeat(5000)).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlux"))
+        .arg(flag)
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut start = [0; 16];
+    child.stdout.take().unwrap().read_exact(&mut start).unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn dumps_s_expressions() {
+    let output = dump(PROGRAM, "--dump-ast");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+(var x: number (+ (- 1) 2))
+(fun greet (name ...rest) (print (+ \"hi \" name)))
+(if (and (> x 0) true) (expr (call greet name: \"lux\")) (expr (= x nil)))
+");
+}
+
+#[test]
+fn dumps_json_with_spans() {
+    let output = dump(PROGRAM, "--dump-ast=json");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("[\n  {\n    \"kind\": \"Var\",\n    \"name\": {\n      \"lexeme\": \"x\",\n      \"line\": 1,\n      \"column\": 5\n    },"));
+    assert!(stdout.contains("\"kind\": \"Call\""));
+
+    let mut scanner = Scanner::new("print -a;".to_string());
+    let tree = Parser::new(scanner.tokens().to_vec()).program();
+    let op = Json::object([("lexeme", "-".into()), ("line", 1.into()), ("column", 7.into())]);
+    assert_eq!(json::ast(&tree).to_string(), format!(
+        "[{{\"kind\":\"Print\",\"expr\":{{\"kind\":\"Unary\",\"op\":{op},\"right\":{{\"kind\":\"Variable\",\"name\":{}}}}}}}]",
+        Json::object([("lexeme", "a".into()), ("line", 1.into()), ("column", 8.into())]),
+    ));
+}
+
+#[test]
+fn reports_syntax_errors_after_the_tree() {
+    let output = dump("print 1;\nprint (;\nprint 2;\n", "--dump-ast");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "(print 1)\n(print 2)\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "[line 2] at ';': Expected expression");
+}
+
+#[test]
+fn lists_tokens_with_scan_errors() {
+    let output = dump("var s = \"hi\";\nprint s @ 1;\n", "--tokens");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+1:1     VAR                     var
+1:5     IDENTIFIER (s)          s
+1:7     EQUAL                   =
+1:9     STRING[\"hi\"]            hi
+1:13    SEMICOLON               ;
+2:1     PRINT                   print
+2:7     IDENTIFIER (s)          s
+2:9     error: Unexpected character.
+2:11    NUMBER[1]               1
+2:12    SEMICOLON               ;
+3:1     EOF
+");
+}
+
+#[test]
+fn lists_tokens_as_json() {
+    let output = dump("true\n\"open", "--tokens=json");
+    assert_eq!(output.status.code(), Some(65));
+    let token = Json::object([
+        ("kind", "TRUE".into()),
+        ("lexeme", "true".into()),
+        ("literal", Json::Bool(true)),
+        ("line", 1.into()),
+        ("column", 1.into()),
+    ]);
+    let error = Json::object([
+        ("kind", "ERROR".into()),
+        ("message", "Expected a terminating \"".into()),
+        ("line", 2.into()),
+        ("column", 1.into()),
+    ]);
+    let eof = Json::object([
+        ("kind", "EOF".into()),
+        ("lexeme", "".into()),
+        ("literal", Json::Null),
+        ("line", 2.into()),
+        ("column", 6.into()),
+    ]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{:#}\n", Json::Array(vec![token, error, eof])));
+}
+
+#[test]
+fn stops_quietly_when_the_reader_goes_away() {
+    for flag in ["--dump-ast", "--dump-ast=json", "--tokens", "--tokens=json"] {
+        let output = dump_to_closed_pipe(flag);
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "", "{flag}");
+        assert!(output.status.success(), "{flag}");
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn fails_when_the_output_cant_be_written() {
+    let path = std::env::temp_dir().join(format!("rlux-dump-{}-full.lux", std::process::id()));
+    fs::write(&path, PROGRAM).unwrap();
+    for flag in ["--dump-ast", "--tokens"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_rlux"))
+            .arg(flag)
+            .arg(&path)
+            .stdout(fs::File::create("/dev/full").unwrap())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(74), "{flag}");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "No space left on device (os error 28)\n");
+    }
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,129 @@
+//! Drives the interpreter through the `Lux` embedding API.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use rlux::budget::{Limit, Limits};
+use rlux::{Error, ErrorKind, Lux, Value};
+
+#[test]
+fn evaluates_to_the_last_expression() {
+    let mut lux = Lux::new();
+    assert_eq!(lux.eval("1 + 2;").unwrap(), Value::Number(3.0));
+    assert_eq!(lux.eval("var a = 1;").unwrap(), Value::Nil);
+    assert_eq!(lux.eval("\"a\" + \"b\";").unwrap().to_string(), "\"ab\"");
+}
+
+#[test]
+fn keeps_globals_between_evals() {
+    let mut lux = Lux::new();
+    lux.eval("var count = 1; fun bump() { count = count + 1; return count; }").unwrap();
+    assert_eq!(lux.eval("bump();").unwrap(), Value::Number(2.0));
+    assert_eq!(lux.get_global("count"), Some(Value::Number(2.0)));
+    assert_eq!(lux.get_global("missing"), None);
+
+    lux.set_global("count", Value::Number(10.0)).unwrap();
+    assert_eq!(lux.eval("bump();").unwrap(), Value::Number(11.0));
+    lux.set_global("greeting", Value::String("hi".into())).unwrap();
+    assert_eq!(lux.eval("greeting + \"!\";").unwrap().to_string(), "\"hi!\"");
+}
+
+#[test]
+fn refuses_to_redefine_constants() {
+    let mut lux = Lux::new();
+    lux.eval("const limit = 3;").unwrap();
+    assert!(matches!(lux.set_global("limit", Value::Nil), Err(Error::Runtime(_))));
+    assert_eq!(lux.get_global("limit"), Some(Value::Number(3.0)));
+}
+
+#[test]
+fn calls_registered_closures() {
+    let mut lux = Lux::new();
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    lux.register("add", 2, move |args| {
+        counter.set(counter.get() + 1);
+        match (&args[0], &args[1]) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+            _ => Err("Expected two numbers".to_string()),
+        }
+    }).unwrap();
+    assert_eq!(lux.eval("add(add(1, 2), 3);").unwrap(), Value::Number(6.0));
+    assert_eq!(calls.get(), 2);
+
+    let e = lux.eval("add(1, nil);").unwrap_err();
+    assert_eq!(e.to_string(), "[line 1] at ')': Expected two numbers");
+    let e = lux.eval("add(1);").unwrap_err();
+    assert_eq!(e.to_string(), "[line 1] at ')': Expected 2 arguments but got 1");
+}
+
+#[test]
+fn calls_functions_from_the_host() {
+    let mut lux = Lux::new();
+    lux.eval("var calls = 0; fun add(a, b = 1) { calls = calls + 1; return a + b; }").unwrap();
+    let add = lux.get_global("add").unwrap();
+    match &add {
+        Value::Function(fun) => assert_eq!(fun.name(), "add"),
+        other => panic!("expected a function, got {other:?}"),
+    }
+    assert_eq!(lux.call(&add, &[Value::Number(2.0)]).unwrap(), Value::Number(3.0));
+    assert_eq!(lux.call(&add, &[Value::Number(2.0), Value::Number(5.0)]).unwrap(), Value::Number(7.0));
+    assert_eq!(lux.get_global("calls"), Some(Value::Number(2.0)));
+
+    let e = lux.call(&add, &[]).unwrap_err();
+    assert_eq!(e.to_string(), "[line 1] at 'add': Expected 1 to 2 arguments but got 0");
+    let e = lux.call(&add, &[Value::Nil]).unwrap_err();
+    assert_eq!(e.to_string(), "[line 1] at '+': Operands must be both numbers or strings");
+
+    let len = lux.get_global("len").unwrap();
+    assert_eq!(lux.call(&len, &[Value::String("abc".into())]).unwrap(), Value::Number(3.0));
+    assert_eq!(lux.call(&len, &[Value::Nil]).unwrap_err().to_string(), "Argument must be a list or a string");
+    assert_eq!(lux.call(&Value::Nil, &[]).unwrap_err().to_string(), "Can only call functions");
+}
+
+#[test]
+fn reports_errors_instead_of_printing_them() {
+    let mut lux = Lux::new();
+    match lux.eval("var = 1;") {
+        Err(Error::Syntax(errors)) => assert_eq!(errors.len(), 1),
+        other => panic!("expected a syntax error, got {other:?}"),
+    }
+    // Stops at the first runtime error
+    match lux.eval("var before = 1; nil + 1; var after = 1;") {
+        Err(Error::Runtime(e)) => assert_eq!(e.kind(), ErrorKind::Runtime),
+        other => panic!("expected a runtime error, got {other:?}"),
+    }
+    assert!(lux.get_global("before").is_some());
+    assert!(lux.get_global("after").is_none());
+
+    lux.set_limits(Limits { max_steps: Some(1000), ..Limits::default() });
+    match lux.eval("while (true) {}") {
+        Err(Error::Runtime(e)) => assert_eq!(e.kind(), ErrorKind::Limit(Limit::Steps)),
+        other => panic!("expected a limit, got {other:?}"),
+    }
+}
+
+#[test]
+fn stops_deep_recursion_within_a_normal_stack() {
+    // As big as the stack of a main thread
+    let thread = std::thread::Builder::new().stack_size(8 * 1024 * 1024).spawn(|| {
+        let mut lux = Lux::new();
+        lux.eval("fun deep(n) { if (n == 0) return 0; return 1 + deep(n - 1); }").unwrap();
+        let shallow = lux.eval("deep(150);").map(|value| value.to_string()).map_err(|e| e.to_string());
+        let overflow = lux.eval("deep(900);").map(|value| value.to_string()).map_err(|e| e.to_string());
+        (shallow, overflow)
+    });
+    let (shallow, overflow) = thread.unwrap().join().unwrap();
+    assert_eq!(shallow.unwrap(), "150");
+    assert_eq!(overflow.unwrap_err(), "[line 1] at ')': Stack overflow");
+}
+
+#[test]
+fn runs_files() {
+    let path = std::env::temp_dir().join(format!("rlux-embedding-{}.lux", std::process::id()));
+    std::fs::write(&path, "fun square(x) { return x * x; }\nsquare(7);\n").unwrap();
+    let mut lux = Lux::new();
+    assert_eq!(lux.run_file(&path).unwrap(), Value::Number(49.0));
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(lux.run_file(&path), Err(Error::Io(_))));
+}
@@ -0,0 +1,114 @@
+//! Checks `rlux fmt`: canonical output, comments kept, and formatting that
+//! changes nothing but whitespace.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use rlux::formatter::format;
+use rlux::parser::Parser;
+use rlux::scanner::Scanner;
+
+const MESSY: &str = "// header
+
+var   x=-1+ 2 ;   // trailing
+fun f( a,b ){ // after brace
+
+
+   // inside
+  if(a>b){return a;}else{ return  -b ;}
+  // before close
+}
+for(var i=0;i<2;i=i+1)print f (i,x)[0];
+while(!false){}
+
+
+
+print x; // end
+// eof
+";
+
+const FORMATTED: &str = "// header
+
+var x = -1 + 2; // trailing
+fun f(a, b) { // after brace
+  // inside
+  if (a > b) {
+    return a;
+  } else {
+    return -b;
+  }
+  // before close
+}
+for (var i = 0; i < 2; i = i + 1) print f(i, x)[0];
+while (!false) {}
+
+print x; // end
+// eof
+";
+
+// The program as S-expressions, which formatting mustn't change
+fn tree(source: &str) -> String {
+    let mut scanner = Scanner::new(source.to_string());
+    Parser::new(scanner.tokens().to_vec()).program().to_string()
+}
+
+#[test]
+fn formats_canonically() {
+    assert_eq!(format(MESSY).unwrap(), FORMATTED);
+    assert_eq!(format(FORMATTED).unwrap(), FORMATTED);
+    assert_eq!(format("").unwrap(), "");
+}
+
+#[test]
+fn keeps_comments_as_trivia() {
+    let mut scanner = Scanner::new("// one\n// two\nprint 1; // three\n".to_string());
+    let tokens = scanner.tokens();
+    let comments: Vec<_> = tokens[0].comments().iter().map(|c| (c.text.as_str(), c.line)).collect();
+    assert_eq!(comments, [("// one", 1), ("// two", 2)]);
+    assert_eq!(tokens[0].line(), 3);
+    let eof = tokens.last().unwrap();
+    assert_eq!(eof.comments()[0].text, "// three");
+}
+
+#[test]
+fn only_changes_whitespace() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "lux") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        let formatted = format(&source).unwrap();
+        assert_eq!(tree(&source), tree(&formatted), "{} changed", path.display());
+        assert_eq!(format(&formatted).unwrap(), formatted, "{} isn't stable", path.display());
+    }
+}
+
+#[test]
+fn refuses_programs_that_dont_parse() {
+    let errors = format("print (;").unwrap_err();
+    assert_eq!(errors[0].to_string(), "[line 1] at ';': Expected expression");
+}
+
+#[test]
+fn checks_and_rewrites_files() {
+    let path = std::env::temp_dir().join(format!("rlux-fmt-{}.lux", std::process::id()));
+    fs::write(&path, MESSY).unwrap();
+    let rlux = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rlux"));
+        command.arg("fmt");
+        command
+    };
+
+    let output = rlux().arg("--check").arg(&path).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), format!("{} is not formatted", path.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), MESSY);
+
+    assert!(rlux().arg(&path).status().unwrap().success());
+    assert_eq!(fs::read_to_string(&path).unwrap(), FORMATTED);
+    assert!(rlux().arg("--check").arg(&path).status().unwrap().success());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,77 @@
+//! Checks that the collector frees reference cycles and keeps live objects
+//! intact, on both backends.
+
+mod common;
+
+use common::run;
+
+#[test]
+fn reclaims_closure_cycles() {
+    let output = run("
+        fun cycle() {
+          fun rec() { return rec; }
+          return rec;
+        }
+        cycle();
+        print gc() > 0;
+        print gc();
+    ");
+    assert_eq!(output, ("true\n0\n".to_string(), String::new()));
+}
+
+#[test]
+fn reclaims_suspended_generators() {
+    let output = run("
+        fun gen() {
+          var me = nil;
+          fun get() { return me; }
+          me = get;
+          yield get;
+        }
+        var g = gen();
+        next(g);
+        g = nil;
+        print gc() > 0;
+        print gc();
+    ");
+    assert_eq!(output, ("true\n0\n".to_string(), String::new()));
+}
+
+#[test]
+fn keeps_reachable_objects() {
+    let output = run("
+        fun cycle() {
+          fun rec() { return rec; }
+          return rec;
+        }
+        fun list(...items) { return items; }
+        fun count() { var i = 0; while (true) { yield i; i = i + 1; } }
+        var kept = cycle();
+        var items = list(1, list(2, 3));
+        var counter = count();
+        print next(counter);
+        gc();
+        print kept()()();
+        print items;
+        print next(counter);
+    ");
+    assert_eq!(output, ("0\n<fn rec>\n[1, [2, 3]]\n1\n".to_string(), String::new()));
+}
+
+#[test]
+fn collects_while_allocating() {
+    // Without automatic collections all 5000 cycles would be left for gc()
+    let output = run("
+        fun cycle() {
+          fun rec() { return rec; }
+          return rec;
+        }
+        var i = 0;
+        while (i < 5000) {
+          cycle();
+          i = i + 1;
+        }
+        print gc() < 5000;
+    ");
+    assert_eq!(output, ("true\n".to_string(), String::new()));
+}
@@ -0,0 +1,67 @@
+//! Resumes generators with `next`, `done` and `for-in` on both backends,
+//! including errors raised inside them and exhausted generators.
+
+mod common;
+
+use common::{rlux, run};
+
+#[test]
+fn yields_values_lazily() {
+    let source = "fun count(n) {
+  var i = 0;
+  while (i < n) { print \"yield\"; yield i; i = i + 1; }
+}
+var c = count(2);
+print \"created\";
+print next(c);
+for (x in c) print x;
+";
+    assert_eq!(run(source).0, "\"created\"\n\"yield\"\n0\n\"yield\"\n1\n");
+}
+
+#[test]
+fn tells_a_yielded_nil_from_an_exhausted_generator() {
+    let source = "fun nils() { yield nil; }
+var g = nils();
+print done(g);
+print next(g);
+print done(g);
+print next(g);
+print done(g);
+print next(g);
+";
+    assert_eq!(run(source), ("false\nnil\nfalse\nnil\ntrue\nnil\n".to_string(), String::new()));
+    let (_, errors) = run("done(1);\nnext(nil);\n");
+    assert_eq!(errors, "[line 1] at ')': Argument must be a generator\n[line 2] at ')': Argument must be a generator\n");
+}
+
+#[test]
+fn reports_errors_where_they_happen() {
+    let source = "fun bad() {
+  yield 1;
+  yield \"a\" - 1;
+  yield 3;
+}
+var b = bad();
+print next(b);
+print next(b);
+print done(b);
+for (x in bad()) print x;
+";
+    assert_eq!(run(source), (
+        "1\ntrue\n1\n".to_string(),
+        "[line 3] at '-': Operands must be numbers\n[line 3] at '-': Operands must be numbers\n".to_string(),
+    ));
+    let (_, errors) = run("fun g() { yield next(s); }\nvar s = g();\nnext(s);\n");
+    assert_eq!(errors, "[line 1] at 'g': Generator is already running\n");
+}
+
+#[test]
+fn stops_at_limits_inside_generators() {
+    let source = "fun forever() { while (true) yield 1; }\nvar g = forever();\nwhile (true) next(g);\n";
+    for backend in ["tree", "vm"] {
+        let output = rlux(source, backend, &["--max-steps=1000"]);
+        assert_eq!(output.status.code(), Some(70), "{backend}");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "Step limit exceeded\n");
+    }
+}
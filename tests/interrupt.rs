@@ -0,0 +1,118 @@
+//! Checks that interrupting a running program stops it, and only it,
+//! keeping the REPL and its variables alive.
+
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use rlux::budget::{Limit, Limits};
+use rlux::interpreter::Interpreter;
+use rlux::parser::Parser;
+use rlux::resolver::Resolver;
+use rlux::scanner::Scanner;
+use rlux::{Error, ErrorKind, Lux};
+
+const SPIN: &str = "while (true) {}\n";
+
+#[cfg(unix)]
+fn spawn(args: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_rlux"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Couldn't run rlux")
+}
+
+#[cfg(unix)]
+fn ctrl_c(child: &Child) {
+    // Give the program time to start spinning
+    thread::sleep(Duration::from_millis(300));
+    let status = Command::new("kill").arg("-INT").arg(child.id().to_string()).status().unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn interrupts_from_another_thread() {
+    let mut scanner = Scanner::new(SPIN.to_string());
+    let mut parser = Parser::new(scanner.tokens().to_vec());
+    let program = parser.program();
+    assert!(Resolver::new().resolve(&program));
+
+    let mut interp = Interpreter::new();
+    let interrupt = interp.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        interrupt.interrupt();
+    });
+    let result = interp.interpret(program);
+    interrupter.join().unwrap();
+    assert_eq!(result.map_err(|e| e.kind()), Err(ErrorKind::Interrupted));
+}
+
+#[test]
+fn interrupts_only_its_own_evaluations() {
+    let (mut interrupted, mut other) = (Lux::new(), Lux::new());
+    other.set_limits(Limits { max_steps: Some(10_000), ..Limits::default() });
+    interrupted.interrupt_handle().interrupt();
+    match other.eval(SPIN) {
+        Err(Error::Runtime(e)) => assert_eq!(e.kind(), ErrorKind::Limit(Limit::Steps)),
+        result => panic!("expected a limit, got {result:?}"),
+    }
+    match interrupted.eval(SPIN) {
+        Err(Error::Runtime(e)) => assert_eq!(e.kind(), ErrorKind::Interrupted),
+        result => panic!("expected an interrupt, got {result:?}"),
+    }
+    // Noticing the interrupt clears it
+    assert_eq!(interrupted.eval("1;").unwrap().to_string(), "1");
+}
+
+#[cfg(unix)]
+#[test]
+fn exits_with_130_in_file_mode() {
+    let path = std::env::temp_dir().join(format!("rlux-interrupt-{}.lux", std::process::id()));
+    std::fs::write(&path, SPIN).expect("Couldn't write program");
+    for backend in ["tree", "vm"] {
+        let child = spawn(&[&format!("--backend={backend}"), path.to_str().unwrap()]);
+        ctrl_c(&child);
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(output.status.code(), Some(130));
+        assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "Interrupted");
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn returns_to_the_prompt() {
+    for backend in ["tree", "vm"] {
+        let mut child = spawn(&[&format!("--backend={backend}")]);
+        let mut stdin = child.stdin.take().unwrap();
+        write!(stdin, "var x = 41;\n{SPIN}").unwrap();
+        stdin.flush().unwrap();
+        ctrl_c(&child);
+        writeln!(stdin, "print x + 1;").unwrap();
+        drop(stdin);
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "Interrupted");
+        assert!(String::from_utf8_lossy(&output.stdout).contains("42"));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn leaves_ctrl_c_alone_when_not_running_programs() {
+    use std::os::unix::process::ExitStatusExt;
+
+    // The server waits for requests on its stdin
+    let child = spawn(&["lsp"]);
+    ctrl_c(&child);
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.signal(), Some(2));
+}
@@ -0,0 +1,104 @@
+//! Checks that execution limits stop runaway programs on both backends,
+//! through the library and the command line.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use rlux::budget::{Limit, Limits};
+use rlux::interpreter::Interpreter;
+use rlux::vm::Vm;
+use rlux::{ErrorKind, EvalError};
+
+use common::parse;
+
+// What each backend stopped with
+fn interpret(source: &str, limits: Limits) -> Vec<Result<(), ErrorKind>> {
+    let mut interp = Interpreter::new();
+    interp.set_limits(limits);
+    let mut vm = Vm::new();
+    vm.set_limits(limits);
+    let kind = |result: Result<(), EvalError>| result.map_err(|e| e.kind());
+    vec![kind(interp.interpret(parse(source))), kind(vm.interpret(parse(source)))]
+}
+
+const SPIN: &str = "var i = 0; while (true) { i = i + 1; }";
+
+#[test]
+fn stops_after_max_steps() {
+    let limits = Limits { max_steps: Some(10_000), ..Limits::default() };
+    for result in interpret(SPIN, limits) {
+        assert_eq!(result, Err(ErrorKind::Limit(Limit::Steps)));
+    }
+}
+
+#[test]
+fn stops_after_timeout() {
+    let limits = Limits { timeout: Some(Duration::from_millis(100)), ..Limits::default() };
+    let start = Instant::now();
+    for result in interpret(SPIN, limits) {
+        assert_eq!(result, Err(ErrorKind::Limit(Limit::Time)));
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn stops_after_max_heap_bytes() {
+    let limits = Limits { max_heap_bytes: Some(1 << 20), ..Limits::default() };
+    let source = "var s = \"x\"; while (true) { s = s + s; }";
+    for result in interpret(source, limits) {
+        assert_eq!(result, Err(ErrorKind::Limit(Limit::Heap)));
+    }
+}
+
+#[test]
+fn stops_inside_generators() {
+    // The limit is hit while `next` resumes the generator, and must not be
+    // reported like the errors the program carries on after
+    let limits = Limits { max_steps: Some(10_000), ..Limits::default() };
+    let source = "
+        fun count() { var i = 0; while (true) { yield i; i = i + 1; } }
+        var counter = count();
+        while (true) next(counter);
+    ";
+    for result in interpret(source, limits) {
+        assert_eq!(result, Err(ErrorKind::Limit(Limit::Steps)));
+    }
+}
+
+#[test]
+fn runs_programs_within_limits() {
+    let limits = Limits {
+        max_steps: Some(1_000_000),
+        timeout: Some(Duration::from_secs(10)),
+        max_heap_bytes: Some(1 << 20),
+    };
+    let source = "var i = 0; while (i < 100) { i = i + 1; } print nil + 1; print i;";
+    for result in interpret(source, limits) {
+        assert_eq!(result, Ok(()));
+    }
+}
+
+#[test]
+fn exits_when_limited_from_the_command_line() {
+    let path = std::env::temp_dir().join(format!("rlux-limits-{}.lux", std::process::id()));
+    fs::write(&path, SPIN).expect("Couldn't write program");
+    for backend in ["tree", "vm"] {
+        for (flag, messg) in [
+            ("--max-steps=1000", "Step limit exceeded"),
+            ("--timeout=50", "Time limit exceeded"),
+        ] {
+            let output = Command::new(env!("CARGO_BIN_EXE_rlux"))
+                .arg(format!("--backend={backend}"))
+                .arg(flag)
+                .arg(&path)
+                .output()
+                .expect("Couldn't run rlux");
+            assert_eq!(output.status.code(), Some(70));
+            assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), messg);
+        }
+    }
+    fs::remove_file(&path).unwrap();
+}
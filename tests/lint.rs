@@ -0,0 +1,106 @@
+//! Checks each rule of the linter, turning rules off, and `rlux lint`.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use rlux::linter::{Linter, Rule};
+
+use common::parse;
+
+fn lints(linter: &mut Linter, source: &str) -> Vec<String> {
+    linter.lint(&parse(source));
+    linter.lints().iter().map(|lint| lint.to_string()).collect()
+}
+
+#[test]
+fn finds_unused_variables() {
+    let source = "var global = 1;
+fun f(param, _skipped) {
+  var unused = 1;
+  var _ignored = 2;
+  var read = 3;
+  read = 4;
+  fun helper() {}
+  for (item in list) print read;
+}";
+    assert_eq!(lints(&mut Linter::new(), source), [
+        "[line 3] at 'unused': Unused variable 'unused' (unused-variable)",
+        "[line 7] at 'helper': Unused function 'helper' (unused-variable)",
+        "[line 8] at 'item': Unused variable 'item' (unused-variable)",
+    ]);
+}
+
+#[test]
+fn finds_shadowing_and_unreachable_code() {
+    let source = "var x = 1;
+var x = 2;
+fun f(a) {
+  var x = a;
+  { var a = x; print a; }
+  return x;
+  print x;
+}";
+    assert_eq!(lints(&mut Linter::new(), source), [
+        "[line 4] at 'x': 'x' shadows the declaration on line 2 (shadowing)",
+        "[line 5] at 'a': 'a' shadows the declaration on line 3 (shadowing)",
+        "[line 7] at 'print': Code after 'return' is never run (unreachable-code)",
+    ]);
+}
+
+#[test]
+fn finds_suspicious_conditions() {
+    let source = "var a = 1; var b = 2;
+if (a = b) print a;
+if ((a = b)) print a;
+while (a < a) print b;
+print 1 == 1;
+print a + 1 != (a + 1);
+print f() == f();
+if (b) {} else { print b; }";
+    assert_eq!(lints(&mut Linter::new(), source), [
+        "[line 2] at 'a': Assignment used as a condition; wrap it in parentheses if it is meant (assign-in-condition)",
+        "[line 4] at '<': Comparing an expression with itself (self-comparison)",
+        "[line 8] at '{': Empty block (empty-block)",
+    ]);
+}
+
+#[test]
+fn turns_rules_off() {
+    let source = "{ var x = 1; } if (x = 1) {}";
+    let mut linter = Linter::new();
+    assert_eq!(lints(&mut linter, source).len(), 3);
+    linter.disable(Rule::EmptyBlock);
+    linter.disable(Rule::UnusedVariable);
+    assert_eq!(lints(&mut linter, source), [
+        "[line 1] at 'x': Assignment used as a condition; wrap it in parentheses if it is meant (assign-in-condition)",
+    ]);
+    linter.enable(Rule::EmptyBlock);
+    assert_eq!(lints(&mut linter, source).len(), 2);
+    assert_eq!(Rule::from_name("self-comparison"), Some(Rule::SelfComparison));
+    assert!(Rule::ALL.iter().all(|rule| Rule::from_name(rule.name()) == Some(*rule)));
+}
+
+#[test]
+fn lints_files_from_the_command_line() {
+    let path = std::env::temp_dir().join(format!("rlux-lint-{}.lux", std::process::id()));
+    fs::write(&path, "{\n  var x = 1;\n}\nwhile (false) {}\n").unwrap();
+    let lint = |flags: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rlux")).arg("lint").args(flags).arg(&path).output().unwrap()
+    };
+
+    let output = lint(&[]);
+    assert_eq!(output.status.code(), Some(1));
+    let name = path.display();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!(
+        "{name}: [line 2] at 'x': Unused variable 'x' (unused-variable)\n{name}: [line 4] at '{{': Empty block (empty-block)\n",
+    ));
+
+    let output = lint(&["--disable=all", "--enable=empty-block"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 1);
+    assert!(lint(&["--disable=unused-variable,empty-block"]).status.success());
+    assert_eq!(lint(&["--disable=typo"]).status.code(), Some(64));
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,139 @@
+//! Drives `rlux lsp` through whole sessions of framed JSON-RPC messages.
+
+use rlux::json::Json;
+use rlux::lsp;
+
+const URI: &str = "file:///test.lux";
+
+fn frame(message: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{message}", message.len())
+}
+
+/// Answers `messages`, followed by `shutdown` and `exit`, and returns what
+/// the server sent back.
+fn session(messages: &[String]) -> Vec<Json> {
+    let mut input: String = messages.iter().map(|m| frame(m)).collect();
+    input += &frame(r#"{"jsonrpc":"2.0","id":99,"method":"shutdown"}"#);
+    input += &frame(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+    let mut output = Vec::new();
+    lsp::serve(input.as_bytes(), &mut output).unwrap();
+
+    let mut output = String::from_utf8(output).unwrap();
+    let mut replies = Vec::new();
+    while let Some((header, rest)) = output.split_once("\r\n\r\n") {
+        let len: usize = header.trim_start_matches("Content-Length: ").parse().unwrap();
+        replies.push(Json::parse(&rest[..len]).unwrap());
+        output = rest[len..].to_string();
+    }
+    let shutdown = replies.pop().unwrap();
+    assert_eq!(shutdown.get("result"), Some(&Json::Null));
+    replies
+}
+
+fn open(text: &str) -> String {
+    let text = Json::from(text);
+    format!(
+        r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{URI}","languageId":"lux","version":1,"text":{text}}}}}}}"#
+    )
+}
+
+fn at(id: usize, method: &str, line: usize, character: usize) -> String {
+    format!(
+        r#"{{"jsonrpc":"2.0","id":{id},"method":"textDocument/{method}","params":{{"textDocument":{{"uri":"{URI}"}},"position":{{"line":{line},"character":{character}}}}}}}"#
+    )
+}
+
+fn start(json: &Json) -> (f64, f64) {
+    let start = json.get("range").unwrap().get("start").unwrap();
+    (start.get("line").unwrap().as_f64().unwrap(), start.get("character").unwrap().as_f64().unwrap())
+}
+
+fn labels(completion: &Json) -> Vec<&str> {
+    completion.as_array().unwrap().iter().map(|item| item.get("label").unwrap().as_str().unwrap()).collect()
+}
+
+#[test]
+fn initialize_announces_capabilities() {
+    let replies = session(&[r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#.to_string()]);
+    let capabilities = replies[0].get("result").unwrap().get("capabilities").unwrap();
+    assert_eq!(capabilities.get("textDocumentSync"), Some(&Json::Number(1.0)));
+    assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+    assert_eq!(capabilities.get("definitionProvider"), Some(&Json::Bool(true)));
+    assert!(capabilities.get("completionProvider").is_some());
+}
+
+#[test]
+fn syntax_errors_are_published() {
+    let replies = session(&[open("var a = 1;\nprint a +;\nvar b = 2 @;\n")]);
+    let params = replies[0].get("params").unwrap();
+    assert_eq!(replies[0].get("method").unwrap().as_str(), Some("textDocument/publishDiagnostics"));
+    assert_eq!(params.get("uri").unwrap().as_str(), Some(URI));
+
+    let diagnostics = params.get("diagnostics").unwrap().as_array().unwrap();
+    assert_eq!(diagnostics.len(), 2);
+    // Scanner errors come first
+    assert_eq!(start(&diagnostics[0]), (2.0, 10.0));
+    assert_eq!(diagnostics[0].get("message").unwrap().as_str(), Some("Unexpected character."));
+    assert_eq!(start(&diagnostics[1]), (1.0, 9.0));
+    assert_eq!(diagnostics[1].get("severity"), Some(&Json::Number(1.0)));
+}
+
+#[test]
+fn fixing_a_document_clears_its_diagnostics() {
+    let change = format!(
+        r#"{{"jsonrpc":"2.0","method":"textDocument/didChange","params":{{"textDocument":{{"uri":"{URI}","version":2}},"contentChanges":[{{"text":"print 1;"}}]}}}}"#
+    );
+    let replies = session(&[open("print 1"), change]);
+    let count = |reply: &Json| reply.get("params").unwrap().get("diagnostics").unwrap().as_array().unwrap().len();
+    assert_eq!(count(&replies[0]), 1);
+    assert_eq!(count(&replies[1]), 0);
+}
+
+#[test]
+fn hover_and_definition_follow_scopes() {
+    let source = "var x = 1;\nfun f(x: number) {\n  return x + 1;\n}\nprint f(x);\n";
+    let replies = session(&[open(source), at(1, "hover", 2, 9), at(2, "definition", 2, 9), at(3, "definition", 4, 8), at(4, "hover", 4, 6)]);
+
+    let hover = replies[1].get("result").unwrap().get("contents").unwrap().get("value").unwrap();
+    assert_eq!(hover.as_str(), Some("```lux\n(parameter) x: number\n```"));
+    // The parameter, not the global
+    assert_eq!(start(replies[2].get("result").unwrap()), (1.0, 6.0));
+    assert_eq!(start(replies[3].get("result").unwrap()), (0.0, 4.0));
+
+    let hover = replies[4].get("result").unwrap().get("contents").unwrap().get("value").unwrap();
+    assert_eq!(hover.as_str(), Some("```lux\nfun f(x: number)\n```"));
+}
+
+#[test]
+fn completion_lists_what_is_in_scope() {
+    let source = "var global = 1;\n{\n  var inner = 2;\n  \n}\n\n";
+    let replies = session(&[open(source), at(1, "completion", 3, 2), at(2, "completion", 5, 0)]);
+
+    let inside = labels(replies[1].get("result").unwrap());
+    assert!(inside.contains(&"global") && inside.contains(&"inner"));
+    assert!(inside.contains(&"clock") && inside.contains(&"while"));
+
+    let outside = labels(replies[2].get("result").unwrap());
+    assert!(outside.contains(&"global") && !outside.contains(&"inner"));
+}
+
+#[test]
+fn unknown_requests_are_rejected() {
+    let replies = session(&[r#"{"jsonrpc":"2.0","id":7,"method":"workspace/symbol","params":{}}"#.to_string()]);
+    assert_eq!(replies[0].get("id"), Some(&Json::Number(7.0)));
+    assert_eq!(replies[0].get("error").unwrap().get("code"), Some(&Json::Number(-32601.0)));
+}
+
+#[test]
+fn out_of_range_positions_are_rejected() {
+    let hover = |position: &str| format!(
+        r#"{{"jsonrpc":"2.0","id":1,"method":"textDocument/hover","params":{{"textDocument":{{"uri":"{URI}"}},"position":{position}}}}}"#
+    );
+    let replies = session(&[
+        open("print 1;"),
+        hover(r#"{"line":1e300,"character":0}"#),
+        hover(r#"{"line":0,"character":1e300}"#),
+    ]);
+    let errors: Vec<_> = replies.iter().filter_map(|reply| reply.get("error")?.get("code")).collect();
+    assert_eq!(errors, [&Json::Number(-32602.0), &Json::Number(-32602.0)]);
+}
@@ -0,0 +1,71 @@
+//! Captures what programs print and the errors they report, on both backends
+//! and through `Lux`.
+
+mod common;
+
+use rlux::interpreter::Interpreter;
+use rlux::parser::Parser;
+use rlux::scanner::Scanner;
+use rlux::vm::Vm;
+use rlux::{Error, Lux};
+
+use common::{parse, Buffer};
+
+const PROGRAM: &str = "print 1 + 2;\nprint nil + 1;\nprint \"after\";\n";
+
+#[test]
+fn tree_writes_to_its_sinks() {
+    let (out, diagnostics) = (Buffer::default(), Buffer::default());
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(out.clone()));
+    interp.set_diagnostics(Box::new(diagnostics.clone()));
+    interp.interpret(parse(PROGRAM)).unwrap();
+    assert_eq!(out.text(), "3\n\"after\"\n");
+    assert_eq!(diagnostics.text(), "[line 2] at '+': Operands must be both numbers or strings\n");
+}
+
+#[test]
+fn vm_writes_to_its_sinks() {
+    let (out, diagnostics) = (Buffer::default(), Buffer::default());
+    let mut vm = Vm::new();
+    vm.set_output(Box::new(out.clone()));
+    vm.set_diagnostics(Box::new(diagnostics.clone()));
+    vm.interpret(parse(PROGRAM)).unwrap();
+    assert_eq!(out.text(), "3\n\"after\"\n");
+    assert_eq!(diagnostics.text(), "[line 2] at '+': Operands must be both numbers or strings\n");
+}
+
+#[test]
+fn lux_captures_print_and_scan_errors() {
+    let out = Buffer::default();
+    let mut lux = Lux::new();
+    lux.set_output(out.clone());
+    lux.eval("for (var i = 0; i < 3; i = i + 1) print i;").unwrap();
+    assert_eq!(out.text(), "0\n1\n2\n");
+
+    match lux.eval("print 1;\nprint @;") {
+        Err(Error::Syntax(errors)) => assert_eq!(errors[0].to_string(), "[line 2] Error: Unexpected character."),
+        other => panic!("expected a syntax error, got {other:?}"),
+    }
+    assert_eq!(out.text(), "0\n1\n2\n");
+}
+
+#[test]
+fn backends_resolve_programs_themselves() {
+    let unresolved = |source: &str| Parser::new(Scanner::new(source.to_string()).tokens().to_vec()).program();
+    let source = "fun f(n) { var a = n * 2; return a; } print f(3);";
+    let (out, diagnostics) = (Buffer::default(), Buffer::default());
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(out.clone()));
+    interp.set_diagnostics(Box::new(diagnostics.clone()));
+    interp.interpret(unresolved(source)).unwrap();
+    assert_eq!(interp.eval(unresolved("f(4);")).unwrap().to_string(), "8");
+    interp.interpret(unresolved("const c = 1; c = 2; print \"skipped\";")).unwrap();
+    assert_eq!(interp.eval(unresolved("return 1;")).unwrap_err().to_string(), "[line 1] at 'return': Cannot return from top-level code");
+
+    let mut vm = Vm::new();
+    vm.set_output(Box::new(out.clone()));
+    vm.interpret(unresolved(source)).unwrap();
+    assert_eq!(out.text(), "6\n6\n");
+    assert_eq!(diagnostics.text(), "[line 1] at 'c': Cannot assign to a constant\n");
+}
@@ -0,0 +1,100 @@
+//! Counts calls and lines, and folds stacks, on programs run with a profiler.
+
+mod common;
+
+use std::io;
+
+use rlux::interpreter::Interpreter;
+use rlux::profiler::Profiler;
+
+use common::parse;
+
+const PROGRAM: &str = "\
+fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 1) + fib(n - 2);
+}
+fun twice(f) {
+  var a = f(5);
+  return a + f(5);
+}
+print twice(fib);
+for (var i = 0; i < 3; i = i + 1) {
+  clock();
+}
+";
+
+fn profile(source: &str) -> Interpreter {
+    let mut interp = Interpreter::new();
+    interp.set_output(Box::new(io::sink()));
+    interp.set_profiler(Profiler::new());
+    interp.interpret(parse(source)).unwrap();
+    interp
+}
+
+#[test]
+fn counts_calls_and_lines() {
+    let interp = profile(PROGRAM);
+    let profiler = interp.profiler().unwrap();
+    let mut calls: Vec<_> = profiler.functions().iter().map(|f| (f.name.clone(), f.line, f.calls)).collect();
+    calls.sort();
+    // fib(5) makes 15 calls
+    assert_eq!(calls, [
+        ("<script>".to_string(), None, 1),
+        ("clock".to_string(), None, 3),
+        ("fib".to_string(), Some(1), 30),
+        ("twice".to_string(), Some(5), 1),
+    ]);
+
+    let mut lines: Vec<_> = profiler.lines().iter().map(|l| (l.line, l.count)).collect();
+    lines.sort();
+    // The loop's initializer and the loop itself are both statements of line 10
+    assert_eq!(lines, [(1, 1), (2, 30), (3, 14), (5, 1), (6, 1), (7, 1), (9, 1), (10, 2), (11, 3)]);
+}
+
+#[test]
+fn totals_include_what_runs_from_there() {
+    let interp = profile(PROGRAM);
+    let profiler = interp.profiler().unwrap();
+    let functions = profiler.functions();
+    let script = functions.iter().find(|f| f.name == "<script>").unwrap();
+    for f in &functions {
+        assert!(f.self_time <= f.total);
+        assert!(f.total <= script.total);
+    }
+    let self_times = functions.iter().map(|f| f.self_time).sum();
+    assert!(script.total >= self_times);
+    // Sorted by self time
+    assert!(functions.windows(2).all(|pair| pair[0].self_time >= pair[1].self_time));
+}
+
+#[test]
+fn folds_call_stacks() {
+    let interp = profile(PROGRAM);
+    let folded = interp.profiler().unwrap().folded();
+    let stacks: Vec<_> = folded.lines().map(|line| line.rsplit_once(' ').unwrap().0).collect();
+    assert_eq!(stacks, [
+        "<script>",
+        "<script>;clock",
+        "<script>;twice",
+        "<script>;twice;fib",
+        "<script>;twice;fib;fib",
+        "<script>;twice;fib;fib;fib",
+        "<script>;twice;fib;fib;fib;fib",
+        "<script>;twice;fib;fib;fib;fib;fib",
+    ]);
+    assert!(folded.lines().all(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>().is_ok()));
+}
+
+#[test]
+fn profiles_generators_and_the_table() {
+    let interp = profile("fun count(n) {\n  for (var i = 0; i < n; i = i + 1) yield i;\n}\nfor (x in count(4)) print x;\n");
+    let profiler = interp.profiler().unwrap();
+    let count = profiler.functions().into_iter().find(|f| f.name == "count").unwrap();
+    // Resumed for each item, then once more to finish
+    assert_eq!(count.calls, 5);
+    let table = profiler.table();
+    assert!(table.starts_with("function"));
+    assert!(table.contains("\nline "));
+    assert!(Interpreter::new().profiler().is_none());
+}